@@ -0,0 +1,212 @@
+use wast::parser::{Cursor, Parser, Peek, Result};
+
+use crate::{
+    state::{parse_without_state, ParseIn, State},
+    token_tree, Expr, SExpr, TokenTree,
+};
+
+pub(crate) mod kw {
+    wast::annotation!(custom);
+}
+
+/// Annotations that are always recognized, even when the input was not
+/// scanned ahead of parsing.
+const WELL_KNOWN: &[&str] = &["custom", "name", "producers"];
+
+/// Returns the names of all annotations that appear in `input`.
+pub(crate) fn discover(input: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut after_lparen = false;
+
+    for token in wast::lexer::Lexer::new(input).flatten() {
+        match token {
+            | wast::lexer::Token::LParen(_) => after_lparen = true,
+            | wast::lexer::Token::Reserved(r)
+                if after_lparen && r.len() > 1 && r.starts_with('@') =>
+            {
+                if !names.iter().any(|n: &String| n == &r[1..]) {
+                    names.push(r[1..].to_owned());
+                }
+
+                after_lparen = false;
+            },
+            | wast::lexer::Token::Whitespace(_)
+            | wast::lexer::Token::LineComment(_)
+            | wast::lexer::Token::BlockComment(_) => {},
+            | _ => after_lparen = false,
+        }
+    }
+
+    names
+}
+
+/// Registers the well-known annotations and `extra` with `parser`. The
+/// registrations last as long as the returned guards.
+pub(crate) fn register<'a>(
+    parser: Parser<'a>,
+    extra: &'a [String],
+) -> Vec<impl Drop + 'a> {
+    WELL_KNOWN
+        .iter()
+        .copied()
+        .chain(extra.iter().map(String::as_str))
+        .map(|name| parser.register_annotation(name))
+        .collect()
+}
+
+/// Escapes `bytes` as the contents of a WebAssembly string literal.
+pub(crate) fn escape(bytes: &[u8]) -> String {
+    let mut s = String::new();
+
+    match std::str::from_utf8(bytes) {
+        | Ok(utf8) => {
+            for c in utf8.chars() {
+                match c {
+                    | '"' => s.push_str("\\\""),
+                    | '\\' => s.push_str("\\\\"),
+                    | '\t' => s.push_str("\\t"),
+                    | '\n' => s.push_str("\\n"),
+                    | '\r' => s.push_str("\\r"),
+                    | c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                        s.push_str(&format!("\\{:02x}", c as u32))
+                    },
+                    | c => s.push(c),
+                }
+            }
+        },
+        | Err(_) => {
+            for b in bytes {
+                match b {
+                    | b'"' => s.push_str("\\\""),
+                    | b'\\' => s.push_str("\\\\"),
+                    | 0x20..=0x7e => s.push(*b as char),
+                    | _ => s.push_str(&format!("\\{:02x}", b)),
+                }
+            }
+        },
+    }
+
+    s
+}
+
+/// https://github.com/WebAssembly/annotations
+///
+/// An annotation such as `(@name "foo")`. The payload is kept as a token tree
+/// so that annotations this crate does not understand are printed back
+/// verbatim.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    name:  String,
+    items: Vec<AnnotationItem>,
+}
+
+impl Annotation {
+    pub fn new(name: String, items: Vec<AnnotationItem>) -> Self {
+        Self { name, items }
+    }
+
+    /// Creates a `(@name "...")` annotation.
+    pub fn name_of<S: AsRef<str>>(s: S) -> Self {
+        Self::new(
+            "name".to_owned(),
+            vec![AnnotationItem::string(s.as_ref().as_bytes())],
+        )
+    }
+
+    /// Returns the annotation name without the leading `@`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn items(&self) -> &[AnnotationItem] {
+        &self.items
+    }
+
+    /// Returns the annotation with the comments in its payload.
+    pub(crate) fn expr(&self) -> Expr<'_> {
+        let expr = Expr::SExpr(Box::new(self));
+
+        token_tree::inner_comments(&self.items, 0).wrap_owned(expr)
+    }
+}
+
+impl SExpr for Annotation {
+    fn car(&self) -> String {
+        format!("@{}", self.name)
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        self.items
+            .iter()
+            .filter(|item| !matches!(item, AnnotationItem::Comment(_)))
+            .map(AnnotationItem::expr)
+            .collect()
+    }
+}
+
+impl ParseIn for Annotation {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        parser.parens(|p| {
+            let name = p.step(|cursor| match cursor.annotation() {
+                | Some((name, rest)) => Ok((name.to_owned(), rest)),
+                | None => Err(cursor.error("expected an annotation")),
+            })?;
+            let items = TokenTree::parse_items(p, state.text())?;
+
+            Ok(Self { name, items })
+        })
+    }
+}
+
+impl Peek for Annotation {
+    fn peek(cursor: Cursor<'_>) -> bool {
+        match cursor.lparen() {
+            | Some(rest) => rest.annotation().is_some(),
+            | None => false,
+        }
+    }
+
+    fn display() -> &'static str {
+        "an annotation"
+    }
+}
+
+/// Zero or more annotations in a row, e.g. `(@name "x") (@foo)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotations(Vec<Annotation>);
+
+impl Annotations {
+    pub fn new(annotations: Vec<Annotation>) -> Self {
+        Self(annotations)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Annotation> {
+        self.0.iter()
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
+        self.0.iter().map(Annotation::expr).collect()
+    }
+}
+
+impl ParseIn for Annotations {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let mut annotations = Vec::new();
+
+        while parser.peek::<Annotation>() {
+            annotations.push(Annotation::parse_in(parser, state)?);
+        }
+
+        Ok(Self(annotations))
+    }
+}
+
+parse_without_state!(Annotation, Annotations);
+
+/// A single token, or a parenthesized group of tokens, in the payload of an
+/// [`Annotation`].
+pub type AnnotationItem = TokenTree;
@@ -0,0 +1,243 @@
+//! Formats WebAssembly text files in the canonical style of
+//! [`wat_ast::format`].
+
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use wat_ast::PrintOptions;
+
+const USAGE: &str = "\
+Usage: wat-fmt [OPTIONS] [FILE]...
+
+Formats WebAssembly text files. Reads standard input and writes standard output
+when no file is given.
+
+Options:
+      --check        List files that are not formatted instead of printing
+                     them, and exit with 1 if there are any
+  -i, --in-place     Rewrite files in place
+  -w, --width <N>    Maximum line width [default: 80]
+      --indent <N>   Columns per indentation level [default: 2]
+      --tabs         Indent with tabs
+  -h, --help         Print this message
+";
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        | Ok(args) => args,
+        | Err(msg) => {
+            eprintln!("error: {}\n\n{}", msg, USAGE);
+            process::exit(2);
+        },
+    };
+
+    if args.help {
+        print!("{}", USAGE);
+        return;
+    }
+
+    let status = if args.files.is_empty() {
+        report(None, format_stdin(&args))
+    } else {
+        args.files
+            .iter()
+            .map(|path| report(Some(path), format_file(&args, path)))
+            .max()
+            .unwrap_or(0)
+    };
+
+    process::exit(status);
+}
+
+struct Args {
+    check:    bool,
+    in_place: bool,
+    help:     bool,
+    options:  PrintOptions,
+    files:    Vec<PathBuf>,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+    let mut parsed = Args {
+        check:    false,
+        in_place: false,
+        help:     false,
+        options:  PrintOptions::canonical(),
+        files:    Vec::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        let options = parsed.options.clone();
+
+        match arg.as_str() {
+            | "--check" => parsed.check = true,
+            | "-i" | "--in-place" => parsed.in_place = true,
+            | "-w" | "--width" => {
+                parsed.options =
+                    options.with_line_width(number(&arg, &mut args)?)
+            },
+            | "--indent" => {
+                parsed.options =
+                    options.with_indent_width(number(&arg, &mut args)?)
+            },
+            | "--tabs" => parsed.options = options.with_tabs(true),
+            | "-h" | "--help" => parsed.help = true,
+            | "--" => parsed.files.extend(args.by_ref().map(PathBuf::from)),
+            | _ if arg.starts_with('-') => {
+                return Err(format!("unknown option `{}`", arg));
+            },
+            | _ => parsed.files.push(PathBuf::from(arg)),
+        }
+    }
+
+    if parsed.check && parsed.in_place {
+        return Err("`--check` cannot be used with `--in-place`".to_owned());
+    }
+
+    if parsed.in_place && parsed.files.is_empty() {
+        return Err("`--in-place` needs at least one file".to_owned());
+    }
+
+    Ok(parsed)
+}
+
+/// Parses the value of the option `name`.
+fn number<I: Iterator<Item = String>>(
+    name: &str,
+    args: &mut I,
+) -> Result<usize, String> {
+    let value = args
+        .next()
+        .ok_or_else(|| format!("`{}` needs a value", name))?;
+
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{}` for `{}`", value, name))
+}
+
+/// Whether an input was already formatted.
+enum Outcome {
+    Formatted,
+    Unformatted,
+}
+
+enum Error {
+    Io(io::Error),
+    Parse(wat_ast::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<wat_ast::Error> for Error {
+    fn from(err: wat_ast::Error) -> Self {
+        Self::Parse(err)
+    }
+}
+
+/// Reports the result of formatting `path`, returning the exit status for it.
+fn report(path: Option<&Path>, result: Result<Outcome, Error>) -> i32 {
+    match result {
+        | Ok(Outcome::Formatted) => 0,
+        | Ok(Outcome::Unformatted) => 1,
+        | Err(Error::Io(err)) => {
+            match path {
+                | Some(path) => eprintln!("error: {}: {}", path.display(), err),
+                | None => eprintln!("error: {}", err),
+            }
+
+            2
+        },
+        // Parse errors already name the file.
+        | Err(Error::Parse(err)) => {
+            eprintln!("error: {}", err);
+            2
+        },
+    }
+}
+
+fn format_stdin(args: &Args) -> Result<Outcome, Error> {
+    let mut src = String::new();
+
+    io::stdin().read_to_string(&mut src)?;
+
+    let formatted = wat_ast::format(&src, &args.options)?;
+
+    if args.check {
+        return Ok(outcome(&src, &formatted));
+    }
+
+    io::stdout().write_all(formatted.as_bytes())?;
+
+    Ok(Outcome::Formatted)
+}
+
+fn format_file(args: &Args, path: &Path) -> Result<Outcome, Error> {
+    let src = fs::read_to_string(path)?;
+    let formatted = wat_ast::format(&src, &args.options).map_err(|mut err| {
+        err.set_path(path);
+        err
+    })?;
+
+    if args.check {
+        let outcome = outcome(&src, &formatted);
+
+        if let Outcome::Unformatted = outcome {
+            println!("{}", path.display());
+        }
+
+        return Ok(outcome);
+    }
+
+    if args.in_place {
+        if formatted != src {
+            fs::write(path, formatted)?;
+        }
+    } else {
+        io::stdout().write_all(formatted.as_bytes())?;
+    }
+
+    Ok(Outcome::Formatted)
+}
+
+fn outcome(src: &str, formatted: &str) -> Outcome {
+    if src == formatted {
+        Outcome::Formatted
+    } else {
+        Outcome::Unformatted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn parse() {
+        let parsed = args(&["-i", "--width", "100", "a.wat", "--", "-b.wat"])
+            .unwrap();
+
+        assert!(parsed.in_place);
+        assert_eq!(parsed.options.line_width(), 100);
+        assert_eq!(parsed.files, vec![
+            PathBuf::from("a.wat"),
+            PathBuf::from("-b.wat"),
+        ]);
+
+        assert!(args(&["--check", "-i", "a.wat"]).is_err());
+        assert!(args(&["--in-place"]).is_err());
+        assert!(args(&["--indent"]).is_err());
+        assert!(args(&["--unknown"]).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use crate::{
+    Annotations, Document, Error, Float, Malformed, Print, PrintOptions,
+    Section, ValueType,
+};
+
+/// Formats `src` in the canonical style, see [`Document::canonical`].
+///
+/// Formatting is idempotent: formatting the output again with the same
+/// options returns it unchanged.
+pub fn format(src: &str, options: &PrintOptions) -> Result<String, Error> {
+    let doc = src.parse::<Document>()?;
+
+    Ok(doc.canonical().print_with(options))
+}
+
+/// Rewrites a node in place into its canonical spelling.
+pub(crate) trait Canonicalize {
+    fn canonicalize(&mut self);
+}
+
+impl<T: Canonicalize> Canonicalize for Option<T> {
+    fn canonicalize(&mut self) {
+        if let Some(t) = self {
+            t.canonicalize();
+        }
+    }
+}
+
+impl<T: Canonicalize> Canonicalize for Vec<T> {
+    fn canonicalize(&mut self) {
+        self.iter_mut().for_each(Canonicalize::canonicalize);
+    }
+}
+
+/// Implements [`Canonicalize`] for nodes that can only be spelled one way.
+macro_rules! already_canonical {
+    ($($node:ty),* $(,)?) => {
+        $(
+            impl Canonicalize for $node {
+                fn canonicalize(&mut self) {}
+            }
+        )*
+    };
+}
+
+already_canonical!(Annotations, Float, Malformed, ValueType);
+
+/// The keywords of the module fields, in the order of the sections they
+/// make up in the binary format.
+const FIELD_ORDER: &[&str] = &[
+    "type", "import", "func", "table", "memory", "global", "export", "start",
+    "elem", "data",
+];
+
+/// Returns the position of `section` in the canonical order of module fields.
+/// Fields of the same kind keep their relative order, so that indexes do not
+/// change. [`Unknown`](crate::Unknown) fields are ranked by their keyword.
+pub(crate) fn rank(section: &Section) -> usize {
+    let keyword = match section {
+        | Section::Type(_) => "type",
+        | Section::Import(_) => "import",
+        | Section::Function(_) => "func",
+        | Section::Memory(_) => "memory",
+        | Section::Global(_) => "global",
+        | Section::Start(_) => "start",
+        | Section::Data(_) => "data",
+        | Section::Unknown(u) => u.keyword(),
+        | Section::Custom(_) => return FIELD_ORDER.len(),
+        | Section::Annotation(_) | Section::Error(_) => {
+            return FIELD_ORDER.len() + 1;
+        },
+    };
+
+    FIELD_ORDER
+        .iter()
+        .position(|k| *k == keyword)
+        .unwrap_or(FIELD_ORDER.len() + 1)
+}
@@ -0,0 +1,493 @@
+use std::{borrow::Cow, cell::RefCell};
+
+use wast::{lexer::Token, parser::Parser, Span};
+
+use crate::Expr;
+
+/// A comment, holding its full source text including the delimiters. Built
+/// with [`Comment::line`] and [`Comment::block`], which check that the text
+/// stays a single comment.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Comment {
+    kind: CommentKind,
+    src:  String,
+}
+
+/// The kind of a [`Comment`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CommentKind {
+    /// `;; ...`
+    Line,
+
+    /// `(; ... ;)`
+    Block,
+}
+
+impl Comment {
+    /// Creates a `;; text` comment, or returns `None` if `text` has a line
+    /// break, which would end the comment early.
+    pub fn line<S: AsRef<str>>(text: S) -> Option<Self> {
+        let text = text.as_ref();
+
+        if text.contains(['\n', '\r']) {
+            return None;
+        }
+
+        Some(Self {
+            kind: CommentKind::Line,
+            src:  format!(";; {}", text),
+        })
+    }
+
+    /// Creates a `(; text ;)` comment, or returns `None` if `text` has a `(;`
+    /// or `;)`, which would nest or end the comment.
+    pub fn block<S: AsRef<str>>(text: S) -> Option<Self> {
+        let text = text.as_ref();
+
+        if text.contains("(;") || text.contains(";)") {
+            return None;
+        }
+
+        Some(Self {
+            kind: CommentKind::Block,
+            src:  format!("(; {} ;)", text),
+        })
+    }
+
+    /// Creates a comment from its source text, as lexed.
+    pub(crate) fn from_src(src: &str) -> Self {
+        if src.starts_with("(;") {
+            Self {
+                kind: CommentKind::Block,
+                src:  src.to_owned(),
+            }
+        } else {
+            Self {
+                kind: CommentKind::Line,
+                src:  src.trim_end().to_owned(),
+            }
+        }
+    }
+
+    pub fn kind(&self) -> CommentKind {
+        self.kind
+    }
+
+    /// Returns the source text of the comment, including the delimiters.
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+}
+
+/// Comments attached to a node.
+///
+/// Leading line comments are printed on their own lines above the node and
+/// leading block comments right before it. Trailing comments follow the node
+/// on the same line. Inner comments sit between the children of a list,
+/// such as after the name of a function. Line comments always end the line
+/// they are on, so they force the enclosing expression into the multi-line
+/// layout.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Comments {
+    leading:  Vec<Comment>,
+    trailing: Vec<Comment>,
+    inner:    Vec<InnerComment>,
+}
+
+impl Comments {
+    pub fn new(leading: Vec<Comment>, trailing: Vec<Comment>) -> Self {
+        Self {
+            leading,
+            trailing,
+            inner: Vec::new(),
+        }
+    }
+
+    /// Adds a comment before the node.
+    pub fn with_leading(mut self, comment: Comment) -> Self {
+        self.leading.push(comment);
+        self
+    }
+
+    /// Adds a comment after the node.
+    pub fn with_trailing(mut self, comment: Comment) -> Self {
+        self.trailing.push(comment);
+        self
+    }
+
+    /// Adds a comment between the children of the node. A node that is not
+    /// a list prints it after itself.
+    pub fn with_inner(mut self, comment: InnerComment) -> Self {
+        self.inner.push(comment);
+        self
+    }
+
+    pub fn leading(&self) -> &[Comment] {
+        &self.leading
+    }
+
+    pub fn trailing(&self) -> &[Comment] {
+        &self.trailing
+    }
+
+    pub fn inner(&self) -> &[InnerComment] {
+        &self.inner
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leading.is_empty()
+            && self.trailing.is_empty()
+            && self.inner.is_empty()
+    }
+
+    /// Moves all of `other` after the node, e.g. when the node `other` was
+    /// attached to is merged into this one.
+    pub(crate) fn append(&mut self, other: Comments) {
+        self.trailing.extend(other.leading);
+        self.trailing.extend(other.inner.into_iter().map(|c| c.comment));
+        self.trailing.extend(other.trailing);
+    }
+
+    /// Wraps `expr` so that it is printed with these comments.
+    pub(crate) fn wrap<'a>(&'a self, expr: Expr<'a>) -> Expr<'a> {
+        if self.is_empty() {
+            expr
+        } else {
+            Expr::Commented(Cow::Borrowed(self), Box::new(expr))
+        }
+    }
+
+    /// Like [`wrap`](Self::wrap), for comments that are not kept around
+    /// while printing.
+    pub(crate) fn wrap_owned(self, expr: Expr<'_>) -> Expr<'_> {
+        if self.is_empty() {
+            expr
+        } else {
+            Expr::Commented(Cow::Owned(self), Box::new(expr))
+        }
+    }
+}
+
+/// A comment between the children of a list, see [`Comments::inner`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InnerComment {
+    index:    usize,
+    comment:  Comment,
+    own_line: bool,
+}
+
+impl InnerComment {
+    /// Creates a comment printed before the child at `index` of the list, not
+    /// counting its head, or before its `)` if `index` is past the last
+    /// child. An `own_line` comment starts a new line, which breaks the list
+    /// over several lines.
+    pub fn new(index: usize, comment: Comment, own_line: bool) -> Self {
+        Self {
+            index,
+            comment,
+            own_line,
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn comment(&self) -> &Comment {
+        &self.comment
+    }
+
+    pub fn is_own_line(&self) -> bool {
+        self.own_line
+    }
+}
+
+/// A comment found while lexing, before it is claimed by a node.
+#[derive(Debug)]
+struct Pending {
+    comment: Comment,
+
+    /// The significant token before the comment.
+    prev: Option<Span>,
+
+    /// The significant token after the comment, or the end of the input.
+    next: Span,
+
+    /// Whether there is no line break between `prev` and the comment.
+    same_line: bool,
+
+    /// The `(` of the lists the comment is in, from the outermost, each with
+    /// the number of items before the comment. The first is the whole input,
+    /// which has no `(`.
+    lists: Vec<(Option<Span>, usize)>,
+
+    claimed: bool,
+}
+
+/// A list that is open while collecting comments.
+struct List {
+    open:       Option<Span>,
+    items:      usize,
+    annotation: bool,
+}
+
+/// The comments of the input being parsed. `wast` does not expose comments
+/// to the parser in a way that can be tied to nodes, so they are collected
+/// ahead of time and claimed by node spans during parsing.
+///
+/// Comments in the payload of an annotation are left out: the annotation
+/// keeps them itself, see [`TokenTree::Comment`](crate::TokenTree::Comment).
+#[derive(Debug, Default)]
+pub(crate) struct Table {
+    pending: RefCell<Vec<Pending>>,
+
+    /// The positions in `pending` of the claimed comments, in the order they
+    /// were claimed, so that claims can be undone when a parse is retried.
+    claimed: RefCell<Vec<usize>>,
+}
+
+impl Table {
+    /// Collects the comments in `input`.
+    pub(crate) fn new(input: &str) -> Self {
+        let mut pending = Vec::new();
+        let mut prev = None;
+        let mut same_line = true;
+        let mut waiting = Vec::new();
+        let mut lists = vec![List {
+            open:       None,
+            items:      0,
+            annotation: false,
+        }];
+
+        for token in wast::lexer::Lexer::new(input).flatten() {
+            let span = Span::from_offset(
+                token.src().as_ptr() as usize - input.as_ptr() as usize,
+            );
+
+            match token {
+                | Token::Whitespace(s) => same_line &= !s.contains('\n'),
+                | Token::LineComment(s) => {
+                    waiting.push((Comment::from_src(s), same_line));
+                    same_line = false;
+                },
+                | Token::BlockComment(s) => {
+                    waiting.push((Comment::from_src(s), same_line));
+                    same_line &= !s.contains('\n');
+                },
+                | _ => {
+                    resolve(&mut pending, &mut waiting, &lists, prev, span);
+
+                    let top = lists.len() - 1;
+
+                    match token {
+                        | Token::LParen(_) => {
+                            lists[top].items += 1;
+                            lists.push(List {
+                                open:       Some(span),
+                                items:      0,
+                                annotation: false,
+                            });
+                        },
+                        | Token::RParen(_) if top > 0 => {
+                            lists.pop();
+                        },
+                        | Token::Reserved(r) => {
+                            lists[top].annotation |= top > 0
+                                && lists[top].items == 0
+                                && r.len() > 1
+                                && r.starts_with('@');
+                            lists[top].items += 1;
+                        },
+                        | _ => lists[top].items += 1,
+                    }
+
+                    prev = Some(span);
+                    same_line = true;
+                },
+            }
+        }
+
+        let end = Span::from_offset(input.len());
+
+        resolve(&mut pending, &mut waiting, &lists, prev, end);
+
+        Self {
+            pending: RefCell::new(pending),
+            claimed: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Returns a point to [`rewind`](Self::rewind) to.
+    pub(crate) fn checkpoint(&self) -> usize {
+        self.claimed.borrow().len()
+    }
+
+    /// Releases the comments claimed since `checkpoint`.
+    pub(crate) fn rewind(&self, checkpoint: usize) {
+        let released = self.claimed.borrow_mut().split_off(checkpoint);
+        let mut pending = self.pending.borrow_mut();
+
+        for i in released {
+            pending[i].claimed = false;
+        }
+    }
+
+    /// Claims the unclaimed comments for which `f` holds in the run of
+    /// comments for which `within` holds, after those for which `before`
+    /// holds, and maps them with `map`. Comments are in source order, so
+    /// `before` holds for a prefix of them.
+    fn claim<T>(
+        &self,
+        before: impl Fn(&Pending) -> bool,
+        within: impl Fn(&Pending) -> bool,
+        f: impl Fn(&Pending) -> bool,
+        map: impl Fn(&Pending) -> T,
+    ) -> Vec<T> {
+        let mut pending = self.pending.borrow_mut();
+        let mut claimed = self.claimed.borrow_mut();
+        let start = pending.partition_point(before);
+
+        pending[start..]
+            .iter_mut()
+            .enumerate()
+            .take_while(|(_, c)| within(c))
+            .filter(|(_, c)| !c.claimed && f(c))
+            .map(|(i, c)| {
+                c.claimed = true;
+                claimed.push(start + i);
+                map(c)
+            })
+            .collect()
+    }
+
+    /// Claims the comments before the node starting at the parser's next
+    /// token.
+    pub(crate) fn leading(&self, parser: Parser<'_>) -> Vec<Comment> {
+        self.leading_at(parser.cur_span())
+    }
+
+    /// Claims the comments before the node starting at `span`.
+    pub(crate) fn leading_at(&self, span: Span) -> Vec<Comment> {
+        self.claim(
+            |c| c.next < span,
+            |c| c.next == span,
+            |_| true,
+            |c| c.comment.clone(),
+        )
+    }
+
+    /// Claims the comments on the same line after the node that ends at the
+    /// parser's previous token.
+    pub(crate) fn trailing(&self, parser: Parser<'_>) -> Vec<Comment> {
+        let prev = Some(parser.prev_span());
+
+        self.claim(
+            |c| c.prev < prev,
+            |c| c.prev == prev,
+            |c| c.same_line,
+            |c| c.comment.clone(),
+        )
+    }
+
+    /// Claims the comments between the tokens at `start` and `end`, e.g. among
+    /// the immediates of a plain instruction.
+    pub(crate) fn inside(&self, start: Span, end: Span) -> Vec<Comment> {
+        self.claim(
+            |c| c.prev < Some(start),
+            |c| c.next <= end,
+            |_| true,
+            |c| c.comment.clone(),
+        )
+    }
+
+    /// Claims the comments in the list opened at `open` that its children did
+    /// not claim.
+    pub(crate) fn inner(&self, open: Span) -> Vec<InnerComment> {
+        let open = Some(open);
+        let items = |c: &Pending| {
+            c.lists.iter().find(|l| l.0 == open).map(|l| l.1)
+        };
+
+        self.claim(
+            |c| c.prev < open,
+            |c| items(c).is_some(),
+            |_| true,
+            |c| {
+                // The head of the list is not one of its children.
+                let index = items(c).unwrap_or(0).saturating_sub(1);
+
+                InnerComment::new(index, c.comment.clone(), !c.same_line)
+            },
+        )
+    }
+
+    /// Claims the comments between the top-level forms of the input that the
+    /// forms did not claim.
+    pub(crate) fn top_level(&self) -> Vec<InnerComment> {
+        self.claim(
+            |_| false,
+            |_| true,
+            |c| c.lists.len() == 1,
+            |c| InnerComment::new(c.lists[0].1, c.comment.clone(), true),
+        )
+    }
+
+    /// Claims the comments inside and after the list opened at `open`, once
+    /// it is parsed, to go with its `leading` comments.
+    pub(crate) fn around(
+        &self,
+        parser: Parser<'_>,
+        open: Span,
+        leading: Vec<Comment>,
+    ) -> Comments {
+        let inner = self.inner(open);
+        let trailing = self.trailing(parser);
+
+        Comments {
+            leading,
+            trailing,
+            inner,
+        }
+    }
+
+    /// Parses a node with `f`, claiming the comments around it and, if it is
+    /// a list, inside it.
+    pub(crate) fn parse_with_comments<'a, T>(
+        &self,
+        parser: Parser<'a>,
+        f: impl FnOnce(Parser<'a>) -> wast::parser::Result<T>,
+    ) -> wast::parser::Result<(T, Comments)> {
+        let open = parser.cur_span();
+        let leading = self.leading(parser);
+        let t = f(parser)?;
+
+        Ok((t, self.around(parser, open, leading)))
+    }
+}
+
+/// Moves the `waiting` comments, which come before the token at `next`, to
+/// `pending`, unless they are in the payload of an annotation.
+fn resolve(
+    pending: &mut Vec<Pending>,
+    waiting: &mut Vec<(Comment, bool)>,
+    lists: &[List],
+    prev: Option<Span>,
+    next: Span,
+) {
+    if lists.iter().any(|l| l.annotation) {
+        waiting.clear();
+
+        return;
+    }
+
+    for (comment, same_line) in waiting.drain(..) {
+        pending.push(Pending {
+            comment,
+            prev,
+            next,
+            same_line,
+            lists: lists.iter().map(|l| (l.open, l.items)).collect(),
+            claimed: false,
+        });
+    }
+}
@@ -0,0 +1,574 @@
+use std::{fmt, ops::Range, rc::Rc, str::FromStr, sync::Arc};
+
+use wast::{
+    lexer::{Lexer, Token},
+    parser::{ParseBuffer, Parser, Result},
+    Span,
+};
+
+use crate::{
+    span::offset_in,
+    state::{self, ParseIn, State},
+    Document, Error, ErrorKind, Expression, ExpressionParser, Module, Print,
+    PrintOptions, Section,
+};
+
+/// A lossless concrete syntax tree of a `.wat` file: every token, whitespace
+/// run and comment of the input is kept, so printing the tree reproduces the
+/// input byte for byte.
+///
+/// The tree is made of parenthesized lists and the tokens between them. The
+/// typed nodes of this crate are views over it, see [`SyntaxNode::view`].
+/// Trees are immutable: an edit returns a new tree which shares every
+/// untouched node with the old one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SyntaxTree {
+    root: Arc<GreenNode>,
+}
+
+impl SyntaxTree {
+    pub fn root(&self) -> SyntaxNode {
+        SyntaxNode(Rc::new(NodeData {
+            green:  self.root.clone(),
+            offset: 0,
+            parent: None,
+            index:  0,
+        }))
+    }
+
+    /// Parses the whole tree as a [`Document`].
+    pub fn document(&self) -> std::result::Result<Document, Error> {
+        self.root().view()
+    }
+}
+
+impl FromStr for SyntaxTree {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Error> {
+        let root = GreenNode::new(SyntaxKind::Root, build(s)?);
+
+        Ok(Self {
+            root: Arc::new(root),
+        })
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.root.write(f)
+    }
+}
+
+/// The kinds of nodes and tokens in a [`SyntaxTree`]. Tokens are those of
+/// [`wast::lexer::Token`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyntaxKind {
+    /// The whole file.
+    Root,
+
+    /// A parenthesized list, from its `(` to its `)`.
+    List,
+
+    LParen,
+    RParen,
+    Whitespace,
+    LineComment,
+    BlockComment,
+    String,
+    Id,
+    Keyword,
+    Reserved,
+    Integer,
+    Float,
+}
+
+impl SyntaxKind {
+    /// Returns `true` for whitespace and comments.
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            Self::Whitespace | Self::LineComment | Self::BlockComment
+        )
+    }
+
+    fn of(token: &Token<'_>) -> Self {
+        match token {
+            | Token::LParen(_) => Self::LParen,
+            | Token::RParen(_) => Self::RParen,
+            | Token::Whitespace(_) => Self::Whitespace,
+            | Token::LineComment(_) => Self::LineComment,
+            | Token::BlockComment(_) => Self::BlockComment,
+            | Token::String(_) => Self::String,
+            | Token::Id(_) => Self::Id,
+            | Token::Keyword(_) => Self::Keyword,
+            | Token::Reserved(_) => Self::Reserved,
+            | Token::Integer(_) => Self::Integer,
+            | Token::Float(_) => Self::Float,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct GreenNode {
+    kind:     SyntaxKind,
+    len:      usize,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> Self {
+        let len = children.iter().map(GreenElement::len).sum();
+
+        Self {
+            kind,
+            len,
+            children,
+        }
+    }
+
+    fn write<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                | GreenElement::Node(node) => node.write(w)?,
+                | GreenElement::Token(token) => w.write_str(&token.text)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct GreenToken {
+    kind: SyntaxKind,
+    text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum GreenElement {
+    Node(Arc<GreenNode>),
+    Token(Arc<GreenToken>),
+}
+
+impl GreenElement {
+    fn len(&self) -> usize {
+        match self {
+            | Self::Node(node) => node.len,
+            | Self::Token(token) => token.text.len(),
+        }
+    }
+}
+
+/// Lexes `src` into a sequence of tokens and lists.
+fn build(src: &str) -> std::result::Result<Vec<GreenElement>, Error> {
+    // The elements of each open list, outermost first, with the offset of
+    // its `(`.
+    let mut stack = vec![(0, Vec::new())];
+
+    for token in Lexer::new(src) {
+        let token = token.map_err(|err| Error::parse(err, src))?;
+        let kind = SyntaxKind::of(&token);
+        let text = token.src();
+        let offset = text.as_ptr() as usize - src.as_ptr() as usize;
+        let green = GreenElement::Token(Arc::new(GreenToken {
+            kind,
+            text: text.to_owned(),
+        }));
+
+        match kind {
+            | SyntaxKind::LParen => stack.push((offset, vec![green])),
+            | SyntaxKind::RParen if stack.len() > 1 => {
+                let (_, mut children) = stack.pop().unwrap();
+
+                children.push(green);
+
+                let list = GreenNode::new(SyntaxKind::List, children);
+
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .1
+                    .push(GreenElement::Node(Arc::new(list)));
+            },
+            | SyntaxKind::RParen => {
+                return Err(error(src, offset, "unexpected `)`"));
+            },
+            | _ => stack.last_mut().unwrap().1.push(green),
+        }
+    }
+
+    match stack.pop() {
+        | Some((_, elements)) if stack.is_empty() => Ok(elements),
+        | Some((offset, _)) => Err(error(src, offset, "unclosed `(`")),
+        | None => unreachable!(),
+    }
+}
+
+fn error(src: &str, offset: usize, message: &str) -> Error {
+    let mut err = Error::new(
+        ErrorKind::UnbalancedParens,
+        Span::from_offset(offset),
+        message.to_owned(),
+    );
+
+    err.set_text(src);
+    err
+}
+
+/// A node of a [`SyntaxTree`], knowing its position and ancestors.
+#[derive(Debug, Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+#[derive(Debug)]
+struct NodeData {
+    green:  Arc<GreenNode>,
+    offset: usize,
+    parent: Option<SyntaxNode>,
+
+    /// The position of the node among the children of its parent.
+    index: usize,
+}
+
+impl SyntaxNode {
+    pub fn kind(&self) -> SyntaxKind {
+        self.0.green.kind
+    }
+
+    /// Returns the byte range of the node in the source.
+    pub fn text_range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.len
+    }
+
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.0.parent.as_ref()
+    }
+
+    /// Returns the parent, its parent, and so on up to the root.
+    pub fn ancestors(&self) -> impl Iterator<Item = SyntaxNode> {
+        std::iter::successors(self.parent().cloned(), |n| n.parent().cloned())
+    }
+
+    /// Returns the child lists.
+    pub fn children(&self) -> impl Iterator<Item = SyntaxNode> {
+        self.children_with_tokens()
+            .into_iter()
+            .filter_map(|e| match e {
+                | SyntaxElement::Node(node) => Some(node),
+                | SyntaxElement::Token(_) => None,
+            })
+    }
+
+    /// Returns the child lists and tokens, trivia included.
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+
+        self.0
+            .green
+            .children
+            .iter()
+            .enumerate()
+            .map(|(index, child)| {
+                let element = match child {
+                    | GreenElement::Node(green) => {
+                        SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                            green: green.clone(),
+                            offset,
+                            parent: Some(self.clone()),
+                            index,
+                        })))
+                    },
+                    | GreenElement::Token(green) => {
+                        SyntaxElement::Token(SyntaxToken {
+                            green:  green.clone(),
+                            offset,
+                            parent: self.clone(),
+                        })
+                    },
+                };
+
+                offset += child.len();
+
+                element
+            })
+            .collect()
+    }
+
+    /// Returns the first token of a list that is not trivia, such as `module`
+    /// or `func`.
+    pub fn keyword(&self) -> Option<SyntaxToken> {
+        self.children_with_tokens()
+            .into_iter()
+            .filter_map(|e| match e {
+                | SyntaxElement::Token(token) => Some(token),
+                | SyntaxElement::Node(_) => None,
+            })
+            .find(|t| !t.kind().is_trivia() && t.kind() != SyntaxKind::LParen)
+            .filter(|t| t.kind() != SyntaxKind::RParen)
+    }
+
+    fn root(&self) -> SyntaxNode {
+        self.ancestors().last().unwrap_or_else(|| self.clone())
+    }
+
+    /// Returns the source text of the node.
+    pub fn text(&self) -> String {
+        self.to_string()
+    }
+
+    /// Parses the node as a typed node: a [`Document`] for the root, a
+    /// [`Module`], a [`Section`] or an [`Expression`] for a list.
+    ///
+    /// Errors point into the whole tree.
+    pub fn view<T: View>(&self) -> std::result::Result<T, Error> {
+        let text = self.text();
+        let view = ParseBuffer::new(&text).and_then(|buf| {
+            state::parse::<Viewed<T>>(&buf, State::new(&text)).0
+        });
+
+        view.map(|Viewed(t)| t).map_err(|err| {
+            let offset = self.0.offset + offset_in(err.span(), &text);
+            let mut err = Error::from(err).with_span(Span::from_offset(offset));
+
+            err.set_text(&self.root().text());
+            err
+        })
+    }
+
+    /// Returns a tree in which this node is replaced by `node`, printed with
+    /// `options`. The rest of the tree is kept as it is.
+    ///
+    /// Lines after the first are indented like the line the node starts on.
+    /// Fails if the printed text does not lex into balanced lists, which can
+    /// happen when a [`Comment`](crate::Comment) or an
+    /// [`Atom`](crate::Atom) was built from malformed text.
+    pub fn replace_with<P: Print>(
+        &self,
+        node: &P,
+        options: &PrintOptions,
+    ) -> std::result::Result<SyntaxTree, Error> {
+        let src = self.root().text();
+        let start = src[..self.0.offset].rfind('\n').map_or(0, |i| i + 1);
+        let line = &src[start..];
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let width = options.line_width().saturating_sub(indent.len());
+        let printed = node.print_with(&options.clone().with_line_width(width));
+        let text = printed
+            .split('\n')
+            .enumerate()
+            .map(|(i, line)| match line {
+                | _ if i == 0 || line.is_empty() => line.to_owned(),
+                | _ => format!("{}{}", indent, line),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Ok(self.replace_with_elements(build(&text)?))
+    }
+
+    /// Returns a tree in which this node is replaced by `elements`.
+    fn replace_with_elements(&self, elements: Vec<GreenElement>) -> SyntaxTree {
+        let parent = match self.parent() {
+            | Some(parent) => parent,
+            | None => {
+                return SyntaxTree {
+                    root: Arc::new(GreenNode::new(SyntaxKind::Root, elements)),
+                };
+            },
+        };
+        let mut children = parent.0.green.children.clone();
+
+        children.splice(self.0.index..=self.0.index, elements);
+
+        parent.replace_green(GreenNode::new(parent.kind(), children))
+    }
+
+    /// Returns a tree in which the green node of this node is `green`.
+    fn replace_green(&self, green: GreenNode) -> SyntaxTree {
+        match self.parent() {
+            | Some(parent) => {
+                let mut children = parent.0.green.children.clone();
+
+                children[self.0.index] = GreenElement::Node(Arc::new(green));
+
+                parent.replace_green(GreenNode::new(parent.kind(), children))
+            },
+            | None => SyntaxTree {
+                root: Arc::new(green),
+            },
+        }
+    }
+}
+
+impl PartialEq for SyntaxNode {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0.green, &other.0.green)
+            && self.0.offset == other.0.offset
+    }
+}
+
+impl Eq for SyntaxNode {}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.green.write(f)
+    }
+}
+
+/// A token of a [`SyntaxTree`].
+#[derive(Debug, Clone)]
+pub struct SyntaxToken {
+    green:  Arc<GreenToken>,
+    offset: usize,
+    parent: SyntaxNode,
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> SyntaxKind {
+        self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    /// Returns the byte range of the token in the source.
+    pub fn text_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text.len()
+    }
+
+    pub fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+}
+
+impl fmt::Display for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.green.text)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+/// Typed nodes that can be parsed from a [`SyntaxNode`], see
+/// [`SyntaxNode::view`].
+pub trait View: Sized {
+    #[doc(hidden)]
+    fn parse_view(parser: Parser<'_>, state: &State) -> Result<Self>;
+}
+
+impl View for Document {
+    fn parse_view(parser: Parser<'_>, state: &State) -> Result<Self> {
+        Document::parse_in(parser, state)
+    }
+}
+
+impl View for Module {
+    fn parse_view(parser: Parser<'_>, state: &State) -> Result<Self> {
+        parser.parens(|p| Module::parse_in(p, state))
+    }
+}
+
+impl View for Section {
+    fn parse_view(parser: Parser<'_>, state: &State) -> Result<Self> {
+        Section::parse_in(parser, state)
+    }
+}
+
+impl View for Expression {
+    fn parse_view(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let span = parser.cur_span();
+        let mut exprs = ExpressionParser::default().parse(parser, state)?;
+
+        match exprs.pop() {
+            | Some(expr) if exprs.is_empty() => Ok(expr),
+            | _ => Err(wast::Error::new(
+                span,
+                "expected a single expression".to_owned(),
+            )),
+        }
+    }
+}
+
+/// Parses a [`View`] as the root of a parse.
+struct Viewed<T>(T);
+
+impl<T: View> ParseIn for Viewed<T> {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        T::parse_view(parser, state).map(Viewed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    const SRC: &str = concat!(
+        ";; Header.\n",
+        "(module $m\n",
+        "\t(func $f   (result i32) (; odd ;) i32.const 0x1)\n",
+        "\t(func $g\n",
+        "\t\t(local.get 0))   ;; trailing\n",
+        ")\n",
+    );
+
+    #[test]
+    fn lossless() {
+        let tree = SRC.parse::<SyntaxTree>().unwrap();
+        let root = tree.root();
+        let module = root.children().next().unwrap();
+        let funcs = module.children().collect::<Vec<_>>();
+
+        assert_eq!(tree.to_string(), SRC);
+        assert_eq!(module.keyword().unwrap().text(), "module");
+        assert_eq!(&SRC[funcs[1].text_range()], "(func $g\n\t\t(local.get 0))");
+        assert_eq!(funcs[1].ancestors().count(), 2);
+        assert_eq!(
+            funcs[1]
+                .children()
+                .next()
+                .unwrap()
+                .view::<Expression>()
+                .unwrap()
+                .print_with(&PrintOptions::default()),
+            "(local.get 0)",
+        );
+        assert_eq!(
+            tree.document().unwrap().to_string(),
+            SRC.parse::<Document>().unwrap().to_string(),
+        );
+        assert!(funcs[0]
+            .view::<Module>()
+            .unwrap_err()
+            .to_string()
+            .contains(":3:3"));
+        assert!("(module))".parse::<SyntaxTree>().is_err());
+        assert!("(module".parse::<SyntaxTree>().is_err());
+    }
+
+    #[test]
+    fn replace() {
+        let tree = SRC.parse::<SyntaxTree>().unwrap();
+        let module = tree.root().children().next().unwrap();
+        let func = module.children().nth(1).unwrap();
+        let section = func.view::<Section>().unwrap();
+        let options = PrintOptions::default();
+        let edited = func.replace_with(&section, &options).unwrap();
+
+        assert_eq!(
+            edited.to_string(),
+            SRC.replace("$g\n\t\t(local.get", "$g (local.get"),
+        );
+
+        let unbalanced = Expr::Atom(Atom::new(")".to_owned()));
+
+        assert_eq!(
+            func.replace_with(&unbalanced, &options).unwrap_err().kind(),
+            ErrorKind::UnbalancedParens,
+        );
+    }
+}
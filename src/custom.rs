@@ -0,0 +1,282 @@
+use std::{any::Any, fmt};
+
+use wast::{
+    parser::{Parse, Parser, Result},
+    Span,
+};
+
+use crate::{
+    Atom, Comments, Expr, Expression, Index, Instruction, SExpr, Spanned,
+};
+
+/// An instruction defined outside this crate, such as an engine-specific or
+/// experimental opcode. Add it to a parse with
+/// [`ParseOptions::with_instruction`](crate::ParseOptions::with_instruction)
+/// to parse it; it is then printed, folded and queried like a built-in one.
+///
+/// Custom instructions are plain instructions: they do not open blocks.
+pub trait CustomInstruction: Any + fmt::Debug + Send + Sync {
+    /// Returns the immediates, printed after the keyword.
+    fn immediates(&self) -> Vec<Atom>;
+
+    /// Returns how many values the instruction pops and pushes, which lets
+    /// [`fold_exprs`](crate::fold_exprs) give it operands. Defaults to
+    /// unknown.
+    fn arity(&self) -> Option<(usize, usize)> {
+        None
+    }
+
+    /// Returns the indexes among the immediates, see
+    /// [`Instruction::indexes`](crate::Instruction::indexes).
+    fn indexes(&self) -> Vec<&Index> {
+        Vec::new()
+    }
+}
+
+/// The object-safe parts of [`CustomInstruction`] that come from other
+/// traits.
+trait DynInstruction: CustomInstruction {
+    fn clone_box(&self) -> Box<dyn DynInstruction>;
+
+    fn eq_dyn(&self, other: &dyn DynInstruction) -> bool;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: CustomInstruction + Clone + Eq> DynInstruction for T {
+    fn clone_box(&self) -> Box<dyn DynInstruction> {
+        Box::new(self.clone())
+    }
+
+    fn eq_dyn(&self, other: &dyn DynInstruction) -> bool {
+        other.as_any().downcast_ref::<T>() == Some(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A [`CustomInstruction`] with its keyword.
+pub struct Custom {
+    keyword:             String,
+    instr:               Box<dyn DynInstruction>,
+    pub(crate) comments: Comments,
+    pub(crate) span:     Option<Span>,
+    pub(crate) exprs:    Vec<Expression>,
+}
+
+impl Custom {
+    pub fn new<S, T>(keyword: S, instr: T) -> Self
+    where
+        S: AsRef<str>,
+        T: CustomInstruction + Clone + Eq,
+    {
+        Self {
+            keyword:  keyword.as_ref().to_owned(),
+            instr:    Box::new(instr),
+            comments: Comments::default(),
+            span:     None,
+            exprs:    Vec::new(),
+        }
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    /// Returns the instruction if it is a `T`.
+    pub fn downcast_ref<T: CustomInstruction>(&self) -> Option<&T> {
+        self.instr.as_any().downcast_ref()
+    }
+
+    /// Returns the operands of the folded instruction.
+    pub fn exprs(&self) -> &[Expression] {
+        &self.exprs
+    }
+
+    pub fn with_exprs(mut self, exprs: Vec<Expression>) -> Self {
+        self.exprs = exprs;
+        self
+    }
+
+    /// Returns a copy of the instruction without its operands.
+    pub(crate) fn without_exprs(&self) -> Self {
+        Self {
+            keyword:  self.keyword.clone(),
+            instr:    self.instr.clone_box(),
+            comments: self.comments.clone(),
+            span:     self.span,
+            exprs:    Vec::new(),
+        }
+    }
+
+    pub(crate) fn arity(&self) -> Option<(usize, usize)> {
+        self.instr.arity()
+    }
+
+    pub(crate) fn indexes(&self) -> Vec<&Index> {
+        self.instr.indexes()
+    }
+
+    pub(crate) fn as_atom(&self) -> Atom {
+        let mut s = self.keyword.clone();
+
+        for atom in self.instr.immediates() {
+            s.push(' ');
+            s.push_str(&atom.to_string());
+        }
+
+        Atom::new(s)
+    }
+}
+
+impl fmt::Debug for Custom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Custom")
+            .field("keyword", &self.keyword)
+            .field("instr", &self.instr)
+            .field("comments", &self.comments)
+            .field("span", &self.span)
+            .field("exprs", &self.exprs)
+            .finish()
+    }
+}
+
+impl Clone for Custom {
+    fn clone(&self) -> Self {
+        Self {
+            keyword:  self.keyword.clone(),
+            instr:    self.instr.clone_box(),
+            comments: self.comments.clone(),
+            span:     self.span,
+            exprs:    self.exprs.clone(),
+        }
+    }
+}
+
+impl PartialEq for Custom {
+    fn eq(&self, other: &Self) -> bool {
+        self.keyword == other.keyword
+            && self.instr.eq_dyn(&*other.instr)
+            && self.comments == other.comments
+            && self.exprs == other.exprs
+    }
+}
+
+impl Eq for Custom {}
+
+impl Spanned for Custom {
+    fn span(&self) -> Option<Span> {
+        self.span
+    }
+}
+
+impl SExpr for Custom {
+    fn car(&self) -> String {
+        self.keyword.clone()
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        let mut v = self
+            .instr
+            .immediates()
+            .into_iter()
+            .map(Expr::Atom)
+            .collect::<Vec<_>>();
+
+        v.extend(self.exprs.iter().map(|e| e.expr()));
+
+        v
+    }
+}
+
+/// Parses the immediates of a custom instruction, after its keyword.
+type ParseFn = fn(Parser<'_>) -> Result<Box<dyn DynInstruction>>;
+
+/// The custom instructions of a parse, by keyword, see
+/// [`ParseOptions`](crate::ParseOptions).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Instructions(Vec<(String, ParseFn)>);
+
+impl Instructions {
+    /// Adds `T` as the instruction spelled `keyword`, unless it is the
+    /// keyword of a built-in instruction or of one already added.
+    pub(crate) fn add<T>(
+        &mut self,
+        keyword: &str,
+    ) -> std::result::Result<(), KeywordError>
+    where
+        T: CustomInstruction + Clone + Eq + for<'a> Parse<'a>,
+    {
+        if Instruction::KEYWORDS.contains(&keyword) {
+            return Err(KeywordError::BuiltIn(keyword.to_owned()));
+        }
+
+        if self.0.iter().any(|(k, _)| k == keyword) {
+            return Err(KeywordError::Duplicate(keyword.to_owned()));
+        }
+
+        let parse: ParseFn = |parser| Ok(Box::new(parser.parse::<T>()?));
+
+        self.0.push((keyword.to_owned(), parse));
+
+        Ok(())
+    }
+
+    /// Parses a custom instruction, or returns `None` if the next token is
+    /// not the keyword of one.
+    pub(crate) fn parse(&self, parser: Parser<'_>) -> Result<Option<Custom>> {
+        let span = parser.cur_span();
+        let found = parser.step(|cursor| {
+            let found = cursor.keyword().and_then(|(keyword, rest)| {
+                self.0
+                    .iter()
+                    .find(|(k, _)| k == keyword)
+                    .map(|found| (found, rest))
+            });
+
+            Ok(match found {
+                | Some((found, rest)) => (Some(found), rest),
+                | None => (None, cursor),
+            })
+        })?;
+        let (keyword, parse) = match found {
+            | Some(found) => found,
+            | None => return Ok(None),
+        };
+        let instr = parse(parser)?;
+
+        Ok(Some(Custom {
+            keyword: keyword.clone(),
+            instr,
+            comments: Comments::default(),
+            span: Some(span),
+            exprs: Vec::new(),
+        }))
+    }
+}
+
+/// A keyword that cannot be given to a [`CustomInstruction`], see
+/// [`ParseOptions::with_instruction`](crate::ParseOptions::with_instruction).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeywordError {
+    /// The keyword of an instruction this crate models.
+    BuiltIn(String),
+
+    /// The keyword of a custom instruction added before.
+    Duplicate(String),
+}
+
+impl fmt::Display for KeywordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            | Self::BuiltIn(k) => {
+                write!(f, "`{}` is a built-in instruction", k)
+            },
+            | Self::Duplicate(k) => write!(f, "`{}` is already defined", k),
+        }
+    }
+}
+
+impl std::error::Error for KeywordError {}
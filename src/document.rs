@@ -1,41 +1,261 @@
-use std::fmt;
+use std::{fmt, ops::Range, str::FromStr};
 
-use wast::parser::{Parse, Parser, Result};
+use wast::parser::{ParseBuffer, Parser, Result};
 
-use crate::{Expr, Module, ToWat, ToWatParams};
+use crate::{
+    print::Printable,
+    query, recover, span,
+    state::{self, parse_without_state, ParseIn, State},
+    Atom, Error, Expr, InnerComment, Module, ModuleKind, Node, ParseOptions,
+    Print, PrintOptions, Section,
+};
 
+/// A `.wat` file: either a sequence of `(module ...)` forms, or the fields of
+/// a single module written at top level without the `(module ...)` wrapper.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Document {
-    module: Module,
+    modules: Vec<Module>,
+
+    /// Whether the document is a single module written without the
+    /// `(module ...)` wrapper.
+    implicit: bool,
+
+    /// The comments between top-level forms that no form claimed, such as
+    /// those at the end of the file.
+    comments: Vec<InnerComment>,
 }
 
 impl Document {
     pub fn new(module: Module) -> Self {
-        Self { module }
+        Self::with_modules(vec![module])
+    }
+
+    pub fn with_modules(modules: Vec<Module>) -> Self {
+        Self {
+            modules,
+            implicit: false,
+            comments: Vec::new(),
+        }
+    }
+
+    /// Creates a document whose module fields appear at top level, without a
+    /// `(module ...)` wrapper. Returns an error of kind [`MultipleStart`] if
+    /// `sections` has more than one start section.
+    ///
+    /// [`MultipleStart`]: crate::ErrorKind::MultipleStart
+    pub fn with_fields(
+        sections: Vec<Section>,
+    ) -> std::result::Result<Self, Error> {
+        Ok(Self::implicit(Module::with_sections(sections)?))
+    }
+
+    /// Creates a document with the fields of `module` at top level.
+    pub(crate) fn implicit(module: Module) -> Self {
+        Self {
+            modules:  vec![module],
+            implicit: true,
+            comments: Vec::new(),
+        }
+    }
+
+    pub fn modules(&self) -> &[Module] {
+        &self.modules
+    }
+
+    /// Returns the modules, to be edited in place. Modules cannot be added
+    /// or removed, since a document without the `(module ...)` wrapper holds
+    /// exactly one.
+    pub fn modules_mut(&mut self) -> &mut [Module] {
+        &mut self.modules
+    }
+
+    /// Returns `true` if the document is a single module written without the
+    /// `(module ...)` wrapper.
+    pub fn is_implicit(&self) -> bool {
+        self.implicit
+    }
+
+    /// Adds comments on their own lines between the top-level forms, each
+    /// before the form at its [`index`](InnerComment::index).
+    pub fn with_comments(mut self, comments: Vec<InnerComment>) -> Self {
+        self.comments = comments;
+        self
+    }
+
+    pub fn comments(&self) -> &[InnerComment] {
+        &self.comments
+    }
+
+    /// Rewrites every module in the canonical style, see
+    /// [`Module::canonical`]. Print the result with
+    /// [`PrintOptions::canonical`] for the output of [`format`].
+    ///
+    /// [`format`]: crate::format
+    pub fn canonical(mut self) -> Self {
+        self.modules =
+            self.modules.into_iter().map(Module::canonical).collect();
+        self
+    }
+
+    /// Returns the module fields, instructions and indexes whose source
+    /// covers byte `offset` of `src`, with their byte ranges, from the
+    /// outermost to the innermost. `src` must be the text this document was
+    /// parsed from, which may be unbalanced for a document from
+    /// [`Document::parse_recovering`]; nodes that were not parsed are never
+    /// found.
+    pub fn path_at(
+        &self,
+        src: &str,
+        offset: usize,
+    ) -> Vec<(Node<'_>, Range<usize>)> {
+        query::path_at(self, src, offset)
+    }
+
+    /// Like [`Document::path_at`], with a zero-based line and byte column.
+    pub fn path_at_linecol(
+        &self,
+        src: &str,
+        line: usize,
+        col: usize,
+    ) -> Vec<(Node<'_>, Range<usize>)> {
+        self.path_at(src, span::offset_at(src, line, col))
+    }
+
+    /// Returns the innermost node covering byte `offset` of `src`, see
+    /// [`Document::path_at`].
+    pub fn node_at(
+        &self,
+        src: &str,
+        offset: usize,
+    ) -> Option<(Node<'_>, Range<usize>)> {
+        self.path_at(src, offset).pop()
+    }
+
+    /// Parses `src` without stopping at the first error. A module field or
+    /// an instruction that fails to parse is skipped, up to its closing
+    /// parenthesis for a list, and kept as an [`Error`] node holding its
+    /// text; unbalanced parentheses are closed or dropped, and text that is
+    /// not a token is skipped up to the end of its line. Returns the document
+    /// with every error found, in source order.
+    ///
+    /// [`Error`]: crate::Section::Error
+    pub fn parse_recovering(src: &str) -> (Self, Vec<Error>) {
+        Self::parse_recovering_with(src, &ParseOptions::default())
+    }
+
+    /// Like [`Document::parse_recovering`], with `options`.
+    pub fn parse_recovering_with(
+        src: &str,
+        options: &ParseOptions,
+    ) -> (Self, Vec<Error>) {
+        recover::parse(src, options)
+    }
+
+    /// Parses `src` like [`FromStr`], with `options`.
+    pub fn parse_with(
+        src: &str,
+        options: &ParseOptions,
+    ) -> std::result::Result<Self, Error> {
+        let buf =
+            ParseBuffer::new(src).map_err(|err| Error::parse(err, src))?;
+        let state = State::new(src).with_options(options);
+        let (document, state) = state::parse::<Document>(&buf, state);
+
+        document.map_err(|err| {
+            let mut err = state.error(err);
+
+            err.set_text(src);
+            err
+        })
+    }
+}
+
+impl Printable for Document {
+    fn print_exprs(&self) -> Vec<Expr<'_>> {
+        self.print_exprs_with(&PrintOptions::default())
+    }
+
+    fn print_exprs_with(&self, options: &PrintOptions) -> Vec<Expr<'_>> {
+        let mut exprs: Vec<_> = if self.implicit {
+            self.modules
+                .iter()
+                .flat_map(|m| m.field_exprs(options))
+                .collect()
+        } else {
+            self.modules.iter().map(Module::expr).collect()
+        };
+
+        // From the last, so that the indexes of the others stay the same.
+        for comment in self.comments.iter().rev() {
+            let at = comment.index().min(exprs.len());
+            let src = comment.comment().src().to_owned();
+
+            exprs.insert(at, Expr::Atom(Atom::new(src)));
+        }
+
+        exprs
+    }
+
+    fn is_file(&self) -> bool {
+        true
     }
 }
 
 impl fmt::Display for Document {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            Expr::SExpr(Box::new(self.module.clone())).to_wat(&ToWatParams {
-                indent_size:  2,
-                indent_level: 0,
-            })
-        )
+        self.fmt_to(f, &PrintOptions::default())
     }
 }
 
-impl Parse<'_> for Document {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
-        let module = parser.parens(|p| p.parse::<Module>())?;
+/// Parses a document, keeping every annotation and comment in the input.
+///
+/// Parsing through [`wast::parser::parse`] instead drops comments. `wast`
+/// skips the annotations it was not told about before the parser sees them,
+/// so only `@custom`, `@name` and `@producers` annotations are kept, along
+/// with those the caller registers with
+/// [`Parser::register_annotation`](wast::parser::Parser::register_annotation)
+/// before parsing the document.
+impl FromStr for Document {
+    type Err = Error;
 
-        Ok(Self { module })
+    fn from_str(s: &str) -> std::result::Result<Self, Error> {
+        Self::parse_with(s, &ParseOptions::default())
     }
 }
 
+impl ParseIn for Document {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        if !parser.peek2::<wast::kw::module>() {
+            let kind = ModuleKind::parse_in(parser, state)?;
+
+            return Ok(Self {
+                modules:  vec![Module::new_unchecked(None, kind)],
+                implicit: true,
+                comments: state.comments.top_level(),
+            });
+        }
+
+        let mut modules = Vec::new();
+
+        while !parser.is_empty() {
+            let (module, comments) =
+                state.comments.parse_with_comments(parser, |p| {
+                    p.parens(|p| Module::parse_in(p, state))
+                })?;
+
+            modules.push(module.with_comments(comments));
+        }
+
+        Ok(Self {
+            modules,
+            implicit: false,
+            comments: state.comments.top_level(),
+        })
+    }
+}
+
+parse_without_state!(Document);
+
 #[cfg(test)]
 mod tests {
     use crate::*;
@@ -47,20 +267,1334 @@ mod tests {
                 &wast::parser::ParseBuffer::new("(module)").unwrap()
             )
             .unwrap(),
-            Document {
-                module: Module::with_sections(Vec::new()),
-            },
+            Document::new(Module::with_sections(Vec::new()).unwrap()),
         )
     }
 
+    #[test]
+    fn annotations_through_wast() {
+        struct WithFoo(Document);
+
+        impl<'a> wast::parser::Parse<'a> for WithFoo {
+            fn parse(
+                parser: wast::parser::Parser<'a>,
+            ) -> wast::parser::Result<Self> {
+                let _foo = parser.register_annotation("foo");
+
+                Ok(Self(parser.parse()?))
+            }
+        }
+
+        let src = r#"(module (@foo 1) (func (@name "f")) (@bar 2))"#;
+        let buf = wast::parser::ParseBuffer::new(src).unwrap();
+
+        assert_eq!(
+            wast::parser::parse::<Document>(&buf).unwrap().to_string(),
+            r#"(module (func (@name "f")))"#,
+        );
+
+        let buf = wast::parser::ParseBuffer::new(src).unwrap();
+
+        assert_eq!(
+            wast::parser::parse::<WithFoo>(&buf).unwrap().0.to_string(),
+            r#"(module (@foo 1) (func (@name "f")))"#,
+        );
+        assert_eq!(src.parse::<Document>().unwrap().to_string(), src);
+    }
+
     #[test]
     fn output_empty_module() {
+        let module = Module::with_sections(Vec::new()).unwrap();
+
+        assert_eq!(Document::new(module).to_string(), "(module)");
+    }
+
+    #[test]
+    fn start_section() {
+        let buf = wast::parser::ParseBuffer::new(
+            "(module (func $main) (start $main))",
+        )
+        .unwrap();
+        let doc = wast::parser::parse::<Document>(&buf).unwrap();
+
+        assert_eq!(doc.to_string(), "(module (func $main) (start $main))");
+    }
+
+    #[test]
+    fn multiple_start_sections() {
+        let buf = wast::parser::ParseBuffer::new(
+            "(module (func $main) (start $main) (start $main))",
+        )
+        .unwrap();
+
+        assert!(wast::parser::parse::<Document>(&buf).is_err());
+    }
+
+    #[test]
+    fn built_multiple_start_sections() {
+        let start = StartSection::new(symbolic("main"));
+        let err = Module::with_sections(vec![
+            Section::Start(start.clone()),
+            Section::Start(start.clone()),
+        ])
+        .unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::MultipleStart);
+
+        let mut module =
+            Module::with_sections(vec![Section::Start(start.clone())])
+                .unwrap();
+        let err = module
+            .insert_section(1, Section::Start(start.clone()))
+            .unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::MultipleStart);
+
+        module.replace_section(0, Section::Start(start)).unwrap();
+
+        assert_eq!(module.sections().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn memory_inline_data() {
+        let src = r#"(module (memory $m (data "abc" "def")) (memory 1 2))"#;
+        let buf = wast::parser::ParseBuffer::new(src).unwrap();
+        let doc = wast::parser::parse::<Document>(&buf).unwrap();
+
+        assert_eq!(doc.to_string(), src);
+    }
+
+    #[test]
+    fn data_escapes() {
+        let src = r#"(module (memory (data "\00\01\n\"" "\ff\fe")))"#;
+        let doc = src.parse::<Document>().unwrap();
+
+        assert_eq!(doc.to_string(), src);
+
+        let data = DataString::with_bytes(vec![vec![0x00, b'a', 0xff]]);
+        let entry = DataSectionEntry::new(
+            None,
+            Offset::new(Expression::Folded(i32_const("0"))),
+            data,
+        );
+        let module = Module::with_sections(vec![Section::Data(
+            DataSection::with_entries(vec![entry]),
+        )])
+        .unwrap();
+
+        assert_eq!(
+            Document::new(module).to_string(),
+            r#"(module (data (i32.const 0) "\00a\ff"))"#,
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        let src = concat!(
+            "(module\n",
+            "  (@producers (language \"rust\" \"1.0\"))\n",
+            "  (func $f (@name \"f\") (param $x (@name \"x\") i32))\n",
+            "  (func $g (local $y (@name \"y\") i32))\n",
+            "  (@unknown a (b 1) \"s\")\n",
+            "  (@custom \"c\" (after func) \"\\00\\ff\")\n",
+            ")",
+        );
+
+        assert_eq!(src.parse::<Document>().unwrap().to_string(), src);
+
+        // Strings are printed as written.
+        let src = concat!(
+            "(module\n",
+            "  (@foo \"\\41\")\n",
+            "  (func $f (@name \"\\41\") (param $x (@name \"\\41\") i32))\n",
+            "  (func $g (local $y (@name \"\\41\") i32))\n",
+            "  (@custom \"\\41\" \"\\42\")\n",
+            ")",
+        );
+
+        assert_eq!(src.parse::<Document>().unwrap().to_string(), src);
+
+        // `Param` parses what follows its keyword.
+        let buf = wast::parser::ParseBuffer::new("i32 i64").unwrap();
+        let param = wast::parser::parse::<Param>(&buf).unwrap();
+
+        assert_eq!(
+            param.kind(),
+            &ParamKind::Anonymous(vec![ValueType::I32, ValueType::I64]),
+        );
+    }
+
+    #[test]
+    fn module_forms() {
+        for src in &[
+            "(module $m (func $f))",
+            r#"(module $m binary "\00asm" "\01\00\00\00")"#,
+            r#"(module quote "(func)")"#,
+        ] {
+            assert_eq!(&src.parse::<Document>().unwrap().to_string(), src);
+        }
+    }
+
+    #[test]
+    fn multiple_modules() {
+        let src = "(module $a (func $f))\n(module $b)";
+
+        assert_eq!(src.parse::<Document>().unwrap().to_string(), src);
+    }
+
+    #[test]
+    fn implicit_module() {
+        let src = "(func $f)\n(start $f)";
+        let doc = src.parse::<Document>().unwrap();
+
+        assert!(doc.is_implicit());
+        assert_eq!(doc.to_string(), src);
+    }
+
+    #[test]
+    fn comments() {
+        let src = concat!(
+            ";; Header.\n",
+            "(module\n",
+            "  ;; Adds one.\n",
+            "  (func $f (param $x i32) (result i32)\n",
+            "    (i32.add\n",
+            "      (local.get $x) ;; the argument\n",
+            "      (i32.const 1))) (; done ;)\n",
+            "  ;; Trailing.\n",
+            ")",
+        );
+        let expected = concat!(
+            ";; Header.\n",
+            "(module\n",
+            "  ;; Adds one.\n",
+            "  (func\n",
+            "    $f\n",
+            "    (param $x i32)\n",
+            "    (result i32)\n",
+            "    (i32.add\n",
+            "      (local.get $x) ;; the argument\n",
+            "      (i32.const 1)\n",
+            "    )\n",
+            "  ) (; done ;)\n",
+            "  ;; Trailing.\n",
+            ")",
+        );
+
+        assert_eq!(src.parse::<Document>().unwrap().to_string(), expected);
+    }
+
+    #[test]
+    fn inner_comments() {
+        for src in &[
+            "(module (func $f (; c ;) (param i32)))",
+            "(module (func (result i32) (i32.const (; x ;) 1)))",
+            "(module (@producers (; c ;) (language \"wat\" (; v ;) \"1.0\")))",
+            "(module)\n;; End.",
+            "(func $f)\n;; End.\n(func $g)\n(; end ;)",
+            concat!(
+                "(module\n",
+                "  (func\n",
+                "    $f\n",
+                "    nop\n",
+                "    ;; Own line.\n",
+                "  )\n",
+                ")",
+            ),
+        ] {
+            assert_eq!(src.parse::<Document>().unwrap().to_string(), *src);
+        }
+
+        assert_eq!(
+            "(module (func i32.const (; x ;) 1 drop))"
+                .parse::<Document>()
+                .unwrap()
+                .to_string(),
+            "(module (func i32.const 1 (; x ;) drop))",
+        );
+
+        let module = Module::with_sections(Vec::new()).unwrap();
+        let generated = Document::new(module)
+            .with_comments(vec![InnerComment::new(
+                1,
+                Comment::line("End.").unwrap(),
+                true,
+            )]);
+
+        assert_eq!(generated.to_string(), "(module)\n;; End.");
+    }
+
+    #[test]
+    fn generated_comments() {
+        let call = |name: &str| {
+            fold(Instruction::Call(Call {
+                idx:      symbolic(name),
+                exprs:    vec![],
+                comments: Comments::default(),
+                span:     None,
+            }))
+        };
+        let func = |exprs| {
+            Section::Function(FunctionSection::with_entries(vec![
+                FunctionSectionEntry::new(
+                    Some(symbolic("f")),
+                    None,
+                    TypeUse::new(None, vec![], vec![]),
+                    exprs,
+                )
+                .with_comments(Comments::default().with_leading(
+                    Comment::line("lowered from foo.rs:42").unwrap(),
+                )),
+            ]))
+        };
+
+        let one_line = Document::with_fields(vec![func(vec![call("g")
+            .with_comments(
+                Comments::default()
+                    .with_leading(Comment::block("a").unwrap())
+                    .with_trailing(Comment::block("b").unwrap()),
+            )])])
+        .unwrap();
+
+        assert_eq!(
+            one_line.to_string(),
+            ";; lowered from foo.rs:42\n(func $f (; a ;) (call $g) (; b ;))",
+        );
+
+        let multi_line = Document::with_fields(vec![func(vec![
+            call("g").with_comments(
+                Comments::default()
+                    .with_trailing(Comment::line("tail call").unwrap()),
+            ),
+            call("h"),
+        ])])
+        .unwrap();
+
+        assert_eq!(
+            multi_line.to_string(),
+            concat!(
+                ";; lowered from foo.rs:42\n",
+                "(func\n",
+                "  $f\n",
+                "  (call $g) ;; tail call\n",
+                "  (call $h)\n",
+                ")",
+            ),
+        );
+    }
+
+    #[test]
+    fn invalid_comments() {
+        assert_eq!(Comment::line("a\nb"), None);
+        assert_eq!(Comment::block("a ;) b"), None);
+        assert_eq!(Comment::block("(; a"), None);
+        assert_eq!(
+            Comment::block("a (b) ;").map(|c| c.src().to_owned()),
+            Some("(; a (b) ; ;)".to_owned()),
+        );
+        assert_eq!(
+            Comment::line("a").map(|c| c.kind()),
+            Some(CommentKind::Line),
+        );
+    }
+
+    #[test]
+    fn print_options() {
+        let doc = "(module (func $f (i32.add (i32.const 1) (i32.const 2))) \
+                   (func $g))"
+            .parse::<Document>()
+            .unwrap();
+        let options = PrintOptions::default()
+            .with_line_width(30)
+            .with_tabs(true)
+            .with_indent_width(4)
+            .with_blank_lines(1)
+            .with_trailing_newline(true);
+
+        assert_eq!(
+            doc.print_with(&options),
+            concat!(
+                "(module\n",
+                "\t(func\n",
+                "\t\t$f\n",
+                "\t\t(i32.add\n",
+                "\t\t\t(i32.const 1)\n",
+                "\t\t\t(i32.const 2)\n",
+                "\t\t)\n",
+                "\t)\n",
+                "\n",
+                "\t(func $g)\n",
+                ")\n",
+            ),
+        );
+        assert_eq!(
+            doc.modules()[0].print_with(&PrintOptions::default()),
+            doc.to_string(),
+        );
+    }
+
+    #[test]
+    fn write_to() {
+        struct Full;
+
+        impl std::io::Write for Full {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::WriteZero.into())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let doc = "(module (func $f))".parse::<Document>().unwrap();
+        let options = PrintOptions::default();
+        let mut buf = Vec::new();
+        let mut s = String::new();
+
+        doc.write_to(&mut buf, &options).unwrap();
+        doc.fmt_to(&mut s, &options).unwrap();
+
+        assert_eq!(buf, doc.to_string().into_bytes());
+        assert_eq!(s, doc.to_string());
+        assert!(doc.write_to(&mut Full, &options).is_err());
+        assert!(doc.modules()[0].write_to(&mut Full, &options).is_err());
+    }
+
+    #[test]
+    fn end_label() {
+        let src = "(module (func block $b br $b end $b))";
+        let doc = src.parse::<Document>().unwrap();
+
+        assert_eq!(doc.to_string(), src);
+    }
+
+    #[test]
+    fn float_from_f64() {
+        assert_eq!(Float::from(1.5).src(), "1.5");
+        assert_eq!(Float::from(f64::NAN).src(), "nan");
+        assert_eq!(Float::from(-f64::NAN).src(), "-nan");
+        assert_eq!(Float::from(f64::INFINITY).src(), "inf");
+        assert_eq!(Float::from(f64::NEG_INFINITY).src(), "-inf");
+    }
+
+    #[test]
+    fn fold_unfold() {
+        let folded = concat!(
+            "(module\n",
+            "  (func $add (param i32 i32) (result i32)\n",
+            "    (i32.add (local.get 0) (local.get 1))\n",
+            "  )\n",
+            "  (func $f (param $x i32) (result i32)\n",
+            "    (local $y i32)\n",
+            "    (block $b\n",
+            "      (br_if $b (i32.eqz (local.get $x)))\n",
+            "      (local.set $y (call $add (local.get $x) (i32.const 1)))\n",
+            "    )\n",
+            "    (if (local.get $y)\n",
+            "      (then (drop (local.get $y)))\n",
+            "      (else (return)))\n",
+            "    (local.get $y)\n",
+            "  )\n",
+            ")",
+        );
+        let unfolded = concat!(
+            "(module\n",
+            "  (func $add (param i32 i32) (result i32) ",
+            "local.get 0 local.get 1 i32.add)\n",
+            "  (func\n",
+            "    $f\n",
+            "    (param $x i32)\n",
+            "    (result i32)\n",
+            "    (local $y i32)\n",
+            "    block $b\n",
+            "    local.get $x\n",
+            "    i32.eqz\n",
+            "    br_if $b\n",
+            "    local.get $x\n",
+            "    i32.const 1\n",
+            "    call $add\n",
+            "    local.set $y\n",
+            "    end\n",
+            "    local.get $y\n",
+            "    if\n",
+            "    local.get $y\n",
+            "    drop\n",
+            "    else\n",
+            "    return\n",
+            "    end\n",
+            "    local.get $y\n",
+            "  )\n",
+            ")",
+        );
+        let module = |src: &str| {
+            src.parse::<Document>().unwrap().modules()[0].clone()
+        };
+        let print =
+            |module: Module| module.print_with(&PrintOptions::default());
+
+        assert_eq!(print(module(folded).unfolded()), unfolded);
+        assert_eq!(print(module(unfolded).folded()), print(module(folded)));
+        assert_eq!(print(module(unfolded).folded().unfolded()), unfolded);
+
+        // Blocks with a block type are folded according to it.
+        let folded = concat!(
+            "(module\n",
+            "  (type $t (func (param i32) (result i32)))\n",
+            "  (func $g (param $x i32) (result i32)\n",
+            "    (drop (block (result i32) (i32.const 1)))\n",
+            "    (drop (if (type $t) (local.get $x) (i32.const 2) (then)))\n",
+            "    (if (result i32) (local.get $x)\n",
+            "      (then (i32.const 3))\n",
+            "      (else (i32.const 4)))\n",
+            "  )\n",
+            ")",
+        );
+        let unfolded = concat!(
+            "(module\n",
+            "  (type $t (func (param i32) (result i32)))\n",
+            "  (func\n",
+            "    $g\n",
+            "    (param $x i32)\n",
+            "    (result i32)\n",
+            "    block (result i32)\n",
+            "    i32.const 1\n",
+            "    end\n",
+            "    drop\n",
+            "    local.get $x\n",
+            "    i32.const 2\n",
+            "    if (type $t)\n",
+            "    end\n",
+            "    drop\n",
+            "    local.get $x\n",
+            "    if (result i32)\n",
+            "    i32.const 3\n",
+            "    else\n",
+            "    i32.const 4\n",
+            "    end\n",
+            "  )\n",
+            ")",
+        );
+
+        assert_eq!(print(module(folded).unfolded()), unfolded);
+        assert_eq!(print(module(unfolded).folded()), print(module(folded)));
+    }
+
+    #[test]
+    fn folding_policy() {
+        let src = concat!(
+            "(module\n",
+            "  (func $f (param $x i32) (result i32)\n",
+            "    local.get $x\n",
+            "    i32.const 1\n",
+            "    i32.add\n",
+            "    local.set $x\n",
+            "    local.get $x\n",
+            "    i32.const 2\n",
+            "    i32.mul\n",
+            "    i32.const 3\n",
+            "    i32.add\n",
+            "    i32.const 4\n",
+            "    i32.sub\n",
+            "    local.get $x\n",
+            "    if\n",
+            "    local.get $x\n",
+            "    drop\n",
+            "    end\n",
+            "  )\n",
+            ")",
+        );
+        let expected = concat!(
+            "(module\n",
+            "  (func\n",
+            "    $f\n",
+            "    (param $x i32)\n",
+            "    (result i32)\n",
+            "    (local.set $x (i32.add (local.get $x) (i32.const 1)))\n",
+            "    (i32.add (i32.mul (local.get $x) (i32.const 2)) ",
+            "(i32.const 3))\n",
+            "    (i32.const 4)\n",
+            "    i32.sub\n",
+            "    (if (local.get $x) (then (drop (local.get $x))))\n",
+            "  )\n",
+            ")",
+        );
+        let doc = src.parse::<Document>().unwrap();
+        let options = PrintOptions::default()
+            .with_folding(ReadableFolding::default());
+
+        assert_eq!(doc.print_with(&options), expected);
+    }
+
+    #[test]
+    fn folding_policy_signatures() {
+        let src = concat!(
+            "(func $inc (param i32) (result i32)\n",
+            "  local.get 0 i32.const 1 i32.add)\n",
+            "(func $main (result i32) i32.const 2 call $inc)",
+        );
+        let expected = concat!(
+            "(func $inc (param i32) (result i32) ",
+            "(i32.add (local.get 0) (i32.const 1)))\n",
+            "(func $main (result i32) (call $inc (i32.const 2)))",
+        );
+        let doc = src.parse::<Document>().unwrap();
+        let options = PrintOptions::default()
+            .with_folding(ReadableFolding::default());
+
+        assert_eq!(doc.print_with(&options), expected);
+    }
+
+    #[test]
+    fn canonical_format() {
+        let src = concat!(
+            ";; Entry point.\n",
+            "(module (start $main) (memory 0x0_1 +2)\n",
+            "  (elem (i32.const 0) $f) (export \"main\" (func $main))\n",
+            "  (table 1 funcref)\n",
+            "  (func $main   call $f drop)\n",
+            "  (type $t (func (result i32)))\n",
+            "  (func $f (type $t) i32.const +0x0F_F  i32.const 1_000 i32.add\n",
+            "    block $b i32.const 00 br_if $b end (; done ;)))",
+        );
+        let formatted = format(src, &PrintOptions::canonical()).unwrap();
+
+        let expected = concat!(
+            ";; Entry point.\n",
+            "(module\n",
+            "  (type $t (func (result i32)))\n",
+            "\n",
+            "  (func $main (drop (call $f)))\n",
+            "\n",
+            "  (func\n",
+            "    $f\n",
+            "    (type $t)\n",
+            "    (i32.add (i32.const 0xff) (i32.const 1000))\n",
+            "    (block $b (br_if $b (i32.const 0))) (; done ;)\n",
+            "  )\n",
+            "\n",
+            "  (table 1 funcref)\n",
+            "\n",
+            "  (memory 0x1 2)\n",
+            "\n",
+            "  (export \"main\" (func $main))\n",
+            "\n",
+            "  (start $main)\n",
+            "\n",
+            "  (elem (i32.const 0) $f)\n",
+            ")\n",
+        );
+
+        assert_eq!(formatted, expected);
+        assert_eq!(
+            format(&formatted, &PrintOptions::canonical()).unwrap(),
+            formatted,
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let src = concat!(
+            "(module\n",
+            "  (import \"env\" \"log\" (func))\n",
+            "  (func (export \"f\") (param $x i32)\n",
+            "    (drop (i32.const 42)))\n",
+            "  (memory (data \"ab\" \"c\")))",
+        );
+        let doc = src.parse::<Document>().unwrap();
+        let linecol = |span: Option<wast::Span>| span.unwrap().linecol_in(src);
+        let sections = match doc.modules()[0].kind() {
+            | ModuleKind::Text(sections) => sections,
+            | _ => unreachable!(),
+        };
+
+        assert_eq!(linecol(sections[0].span()), (1, 3));
+        assert_eq!(linecol(sections[1].span()), (2, 3));
+
+        let import = match &sections[0] {
+            | Section::Import(imports) => &imports.entries()[0],
+            | _ => unreachable!(),
+        };
+
+        assert_eq!(linecol(import.module_span()), (1, 10));
+        assert_eq!(linecol(import.name_span()), (1, 16));
+
+        let func = match &sections[1] {
+            | Section::Function(functions) => &functions.entries()[0],
+            | _ => unreachable!(),
+        };
+
+        assert_eq!(linecol(func.inline_export().unwrap().name_span()), (2, 16));
+        assert_eq!(linecol(func.type_use().span()), (2, 21));
+        assert_eq!(linecol(func.type_use().params()[0].span()), (2, 22));
+
+        let drop = &func.exprs()[0];
+
+        assert_eq!(linecol(drop.span()), (3, 5));
+
+        let constant = match drop {
+            | Expression::Folded(Instruction::Drop(drop)) => &drop.exprs[0],
+            | _ => unreachable!(),
+        };
+        let integer = match constant {
+            | Expression::Folded(Instruction::I32Const(c)) => &c.integer,
+            | _ => unreachable!(),
+        };
+
+        assert_eq!(linecol(constant.span()), (3, 11));
+        assert_eq!(linecol(integer.span()), (3, 21));
+        assert_eq!(Integer::new("1".to_owned()).span(), None);
+
+        let data = match &sections[2] {
+            | Section::Memory(memories) => match memories.entries()[0].kind() {
+                | MemoryKind::Inline(data) => data,
+                | _ => unreachable!(),
+            },
+            | _ => unreachable!(),
+        };
+
+        assert_eq!(linecol(data.span()), (4, 16));
+    }
+
+    #[test]
+    fn spans_ignored_by_eq() {
+        let a = concat!(
+            "(module (func (result i32) i32.const 1) ",
+            "(memory (data \"a\")))",
+        );
+        let b = concat!(
+            "(module\n",
+            "  (func  (result i32)\n",
+            "    i32.const  1)\n",
+            "  (memory (data  \"a\")))",
+        );
+
+        assert_eq!(a.parse::<Document>(), b.parse::<Document>());
+    }
+
+    #[test]
+    fn path_at() {
+        let src = concat!(
+            "(module\n",
+            "  (func $f (param $x i32)\n",
+            "    call $f ;; again\n",
+            "    (drop (local.get $x))))",
+        );
+        let doc = src.parse::<Document>().unwrap();
+        let text = |offset| {
+            doc.path_at(src, offset)
+                .into_iter()
+                .map(|(_, range)| &src[range])
+                .collect::<Vec<_>>()
+        };
+        let call = src.find("$f ;;").unwrap();
+        let func = &src[10..src.len() - 1];
+
+        assert_eq!(text(call), vec![src, func, "call $f", "$f"]);
+        assert!(matches!(
+            doc.node_at(src, call - 2),
+            Some((Node::Instruction(Instruction::Call(_)), _)),
+        ));
+        assert_eq!(text(src.find(";;").unwrap()).len(), 2);
+        assert_eq!(
+            doc.path_at_linecol(src, 3, 22)
+                .into_iter()
+                .map(|(_, range)| &src[range])
+                .collect::<Vec<_>>(),
+            vec![src, func, "(drop (local.get $x))", "(local.get $x)", "$x"],
+        );
+        assert!(doc.path_at(src, src.len()).is_empty());
+
+        // A document parsed from unbalanced source.
+        let src = "(module\n  (func $f (call $f)\n  (func $g (call $f)))";
+        let (doc, errors) = Document::parse_recovering(src);
+        let path = doc.path_at(src, src.find("$f)").unwrap());
+
+        assert!(!errors.is_empty());
+        assert!(matches!(
+            path.as_slice(),
+            [
+                (Node::Module(_), _),
+                (Node::Function(_), _),
+                (Node::Instruction(Instruction::Call(_)), _),
+                (Node::Index(_), _),
+            ],
+        ));
+        assert_eq!(&src[path[0].1.clone()], src);
+        assert_eq!(&src[path[3].1.clone()], "$f");
+    }
+
+    #[test]
+    fn errors() {
+        let err = |src: &str| src.parse::<Document>().unwrap_err();
+
+        let instr = err("(module\n  (func\n    \"i32.add\"))");
+
+        assert_eq!(instr.kind(), ErrorKind::ExpectedInstruction);
+        assert_eq!(instr.line_col(), Some((2, 4)));
+        assert!(instr.expected().is_empty());
+        assert_eq!(
+            instr.to_string(),
+            concat!(
+                "expected an instruction\n",
+                " --> <anon>:3:5\n",
+                "  |\n",
+                "3 |     \"i32.add\"))\n",
+                "  |     ^^^^^^^^^",
+            ),
+        );
+
+        let unknown = "(module\n  (func\n    i32.plus))".parse::<Document>();
+        let unknown = unknown.unwrap().to_string();
+
+        assert_eq!(unknown, "(module (func i32.plus))");
+
+        let table = "(module (table 1 funcref))".parse::<Document>();
+        let sections = match table.unwrap().modules()[0].kind() {
+            | ModuleKind::Text(sections) => sections.clone(),
+            | _ => unreachable!(),
+        };
+
+        assert!(matches!(sections.as_slice(), [Section::Unknown(_)]));
+
+        let section = err("(module ($table 1 funcref))");
+
+        assert_eq!(section.kind(), ErrorKind::UnknownSection);
+        assert!(section.expected().iter().any(|e| e == "func"));
+        assert_eq!(
+            err("(global i32 (i32.const 1) (i32.const 2))").kind(),
+            ErrorKind::TooManyInitExprs,
+        );
+        assert_eq!(err("(start 0) (start 1)").kind(), ErrorKind::MultipleStart);
+        assert_eq!(err("(memory 1x)").kind(), ErrorKind::InvalidInteger);
+        assert_eq!(err("(func \"").kind(), ErrorKind::Lex);
+
+        let token = err("(module (import \"a\" \"b\" (table)))");
+
+        assert_eq!(token.kind(), ErrorKind::UnexpectedToken);
+        assert_eq!(token.expected(), ["func"]);
+        assert_eq!(
+            "(module))".parse::<SyntaxTree>().unwrap_err().kind(),
+            ErrorKind::UnbalancedParens,
+        );
+
+        let integer = err("(module\n\t(func (; é ;) (i32.const 1x)))");
+
+        assert_eq!(integer.kind(), ErrorKind::InvalidInteger);
+        assert_eq!(integer.line_col(), Some((1, 27)));
+        assert_eq!(
+            integer.to_string(),
+            concat!(
+                "could not parse integer\n",
+                " --> <anon>:2:27\n",
+                "  |\n",
+                "2 | \t(func (; é ;) (i32.const 1x)))\n",
+                "  | \t                         ^^",
+            ),
+        );
+    }
+
+    #[test]
+    fn parse_recovering() {
+        let src = concat!(
+            "(module\n",
+            "  (func $f (result i32)\n",
+            "    i32.const 1\n",
+            "    \"i32.add\" 2\n",
+            "    i32.plus 2\n",
+            "    (i32.mul (i32.const 3) (i32.minus) (i32.const $x))) ;; tail\n",
+            "  (table 1 funcref)\n",
+            "  (memory)\n",
+            "  (func $g)\n",
+            "  (func $h (param i32)\n",
+        );
+        let (doc, errors) = Document::parse_recovering(src);
+        let kinds = errors.iter().map(Error::kind).collect::<Vec<_>>();
+
+        assert_eq!(
+            kinds,
+            [
+                ErrorKind::UnbalancedParens,
+                ErrorKind::ExpectedInstruction,
+                ErrorKind::InvalidInteger,
+                ErrorKind::InvalidInteger,
+                ErrorKind::UnbalancedParens,
+            ],
+        );
+        assert_eq!(errors[1].line_col(), Some((3, 4)));
+        assert_eq!(errors[4].message(), "unclosed `(`");
+
+        let sections = match doc.modules()[0].kind() {
+            | ModuleKind::Text(sections) => sections,
+            | _ => unreachable!(),
+        };
+
+        let malformed = |expr: &Expression| match expr {
+            | Expression::Unfolded(Instruction::Error(m)) => {
+                m.text().to_owned()
+            },
+            | _ => panic!("not malformed: {:?}", expr),
+        };
+        let unknown = |expr: &Expression| match expr {
+            | Expression::Unfolded(Instruction::Unknown(u))
+            | Expression::Folded(Instruction::Unknown(u)) => {
+                u.keyword().to_owned()
+            },
+            | _ => panic!("not unknown: {:?}", expr),
+        };
+
+        let (f, table, memory, g) = match sections.as_slice() {
+            | [
+                Section::Function(f),
+                Section::Unknown(t),
+                Section::Error(m),
+                Section::Function(g),
+            ] => (f, t, m, g),
+            | _ => panic!("unexpected sections: {:?}", sections),
+        };
+        let exprs = f.entries()[0].exprs();
+
+        assert_eq!(malformed(&exprs[1]), "\"i32.add\" 2");
+        assert_eq!(unknown(&exprs[2]), "i32.plus");
+
+        match exprs[3] {
+            | Expression::Folded(ref mul) => {
+                assert_eq!(unknown(&mul.exprs()[1]), "i32.minus");
+                assert_eq!(malformed(&mul.exprs()[2]), "(i32.const $x)");
+            },
+            | _ => panic!("not folded: {:?}", exprs[3]),
+        }
+
+        assert_eq!(table.keyword(), "table");
+        assert_eq!(memory.text(), "(memory)");
+        assert_eq!(g.entries().len(), 2);
+
+        assert_eq!(
+            doc.to_string(),
+            concat!(
+                "(module\n",
+                "  (func\n",
+                "    $f\n",
+                "    (result i32)\n",
+                "    i32.const 1\n",
+                "    \"i32.add\" 2\n",
+                "    i32.plus 2\n",
+                "    (i32.mul (i32.const 3) (i32.minus) (i32.const $x))\n",
+                "  ) ;; tail\n",
+                "  (table 1 funcref)\n",
+                "  (memory)\n",
+                "  (func $g)\n",
+                "  (func $h (param i32))\n",
+                ")",
+            ),
+        );
+
+        let (doc, errors) = Document::parse_recovering("(func i32.add)");
+
+        assert!(errors.is_empty());
+        assert_eq!(doc, "(func i32.add)".parse::<Document>().unwrap());
+    }
+
+    #[test]
+    fn parse_recovering_lex_errors() {
+        let src = concat!(
+            "(module\n",
+            "  (memory (data \"a\\qb\"))\n",
+            "  (func $f\n",
+            "    nop (; open\n",
+            "    nop)\n",
+            "  (func $g))",
+        );
+        let (doc, errors) = Document::parse_recovering(src);
+
+        assert_eq!(
+            errors.iter().map(Error::kind).collect::<Vec<_>>(),
+            [ErrorKind::Lex, ErrorKind::Lex],
+        );
+        assert_eq!(errors[0].line_col(), Some((1, 19)));
+        assert_eq!(errors[1].line_col(), Some((3, 8)));
+        assert_eq!(
+            doc.to_string(),
+            "(module (memory (data \"\")) (func $f nop nop) (func $g))",
+        );
+    }
+
+    #[test]
+    fn unknown() {
+        let src = concat!(
+            "(module\n",
+            "  (type $t (func))\n",
+            "  ;; A table.\n",
+            "  (table 1 funcref)\n",
+            "  (elem (i32.const 0) $f)\n",
+            "  (func $f (result i32)\n",
+            "    local.get 0\n",
+            "    i32.popcnt\n",
+            "    i32.store8 offset=4 align=1\n",
+            "    (i32.popcnt (local.get 0))\n",
+            "    (call_indirect (type $t) (i32.const 0))\n",
+            "    i32.const 1\n",
+            "    i32.const 2\n",
+            "    i32.add)\n",
+            "  (func $g\n",
+            "    v128.const i32x4 1 2 3 4\n",
+            "    i32.const 0\n",
+            "    select (result i32)\n",
+            "    call_indirect (type $t)))",
+        );
+        let doc = src.parse::<Document>().unwrap();
+        let sections = match doc.modules()[0].kind() {
+            | ModuleKind::Text(sections) => sections,
+            | _ => unreachable!(),
+        };
+
+        match sections[1] {
+            | Section::Unknown(ref table) => {
+                assert_eq!(table.keyword(), "table");
+                assert_eq!(table.items().len(), 2);
+                assert_eq!(
+                    table.comments().leading(),
+                    [Comment::line("A table.").unwrap()],
+                );
+            },
+            | _ => panic!("not unknown: {:?}", sections[1]),
+        }
+
         assert_eq!(
-            Document {
-                module: Module::with_sections(Vec::new()),
+            doc.to_string(),
+            concat!(
+                "(module\n",
+                "  (type $t (func))\n",
+                "  ;; A table.\n",
+                "  (table 1 funcref)\n",
+                "  (elem (i32.const 0) $f)\n",
+                "  (func\n",
+                "    $f\n",
+                "    (result i32)\n",
+                "    local.get 0\n",
+                "    i32.popcnt\n",
+                "    i32.store8 offset=4 align=1\n",
+                "    (i32.popcnt (local.get 0))\n",
+                "    (call_indirect (type $t) (i32.const 0))\n",
+                "    i32.const 1\n",
+                "    i32.const 2\n",
+                "    i32.add\n",
+                "  )\n",
+                "  (func\n",
+                "    $g\n",
+                "    v128.const i32x4 1 2 3 4\n",
+                "    i32.const 0\n",
+                "    select (result i32)\n",
+                "    call_indirect (type $t)\n",
+                "  )\n",
+                ")",
+            ),
+        );
+
+        // Immediates that are lists or keywords stay with the instruction.
+        let immediates = match sections[3] {
+            | Section::Function(ref f) => f.entries()[1]
+                .exprs()
+                .iter()
+                .map(|e| match e {
+                    | Expression::Unfolded(Instruction::Unknown(u)) => {
+                        (u.keyword(), u.items().len())
+                    },
+                    | _ => ("", 0),
+                })
+                .collect::<Vec<_>>(),
+            | _ => panic!("not a function: {:?}", sections[3]),
+        };
+
+        assert_eq!(
+            immediates,
+            [
+                ("v128.const", 5),
+                ("", 0),
+                ("select", 1),
+                ("call_indirect", 1),
+            ],
+        );
+
+        let exprs = match sections[3] {
+            | Section::Function(ref f) => f.entries()[0].exprs().to_vec(),
+            | _ => panic!("not a function: {:?}", sections[3]),
+        };
+
+        match exprs[2] {
+            | Expression::Unfolded(Instruction::Unknown(ref store)) => {
+                assert_eq!(store.keyword(), "i32.store8");
+                assert_eq!(store.items().len(), 2);
+            },
+            | _ => panic!("not unknown: {:?}", exprs[2]),
+        }
+
+        // The operands of unknown instructions are not known, so only the
+        // runs of instructions between them are folded.
+        let folded = fold_exprs(exprs.clone());
+
+        assert_eq!(folded.len(), 6);
+        assert_eq!(folded[1..5], exprs[1..5]);
+        assert!(matches!(
+            folded[0],
+            Expression::Folded(Instruction::LocalGet(_)),
+        ));
+        assert!(matches!(
+            folded[5],
+            Expression::Folded(Instruction::I32Add(_)),
+        ));
+        let canonical = doc.canonical().to_string();
+
+        assert!(canonical.contains(concat!(
+            "    (local.get 0)\n",
+            "    i32.popcnt\n",
+        )));
+        assert!(
+            canonical.contains("    (i32.add (i32.const 1) (i32.const 2))\n")
+        );
+
+        // A run that closes a block it does not open is kept as it is, as
+        // the block may be opened by an unknown instruction.
+        let src = "(func try i32.const 1 drop end i32.const 2 drop)";
+        let doc = src.parse::<Document>().unwrap();
+        let exprs = match doc.modules()[0].kind() {
+            | ModuleKind::Text(sections) => match sections[0] {
+                | Section::Function(ref f) => f.entries()[0].exprs().to_vec(),
+                | _ => unreachable!(),
+            },
+            | _ => unreachable!(),
+        };
+
+        assert_eq!(fold_exprs(exprs.clone()), exprs);
+        assert!(canonical.ends_with(concat!(
+            "  ;; A table.\n",
+            "  (table 1 funcref)\n",
+            "  (elem (i32.const 0) $f)\n",
+            ")",
+        )));
+    }
+
+    #[test]
+    fn unknown_trivia() {
+        let src = concat!(
+            "(module\n",
+            "  (table 1 (; min ;) funcref)\n",
+            "  (export \"\\u{41}\\41\" (func 0))\n",
+            "  (func\n",
+            "    (some.op (; a ;) (other.op \"\\u{42}\") ;; b\n",
+            "    )))",
+        );
+        let doc = src.parse::<Document>().unwrap();
+
+        assert_eq!(
+            doc.to_string(),
+            concat!(
+                "(module\n",
+                "  (table 1 (; min ;) funcref)\n",
+                "  (export \"\\u{41}\\41\" (func 0))\n",
+                "  (func\n",
+                "    (some.op (; a ;)\n",
+                "      (other.op \"\\u{42}\") ;; b\n",
+                "    )\n",
+                "  )\n",
+                ")",
+            ),
+        );
+
+        let sections = match doc.modules()[0].kind() {
+            | ModuleKind::Text(sections) => sections,
+            | _ => unreachable!(),
+        };
+
+        match sections[0] {
+            | Section::Unknown(ref table) => {
+                assert_eq!(table.items().len(), 3);
+                assert_eq!(
+                    table.items()[1],
+                    TokenTree::Comment(Comment::block("min").unwrap()),
+                );
+            },
+            | _ => panic!("not unknown: {:?}", sections[0]),
+        }
+    }
+
+    #[test]
+    fn generic_exprs() {
+        let src = concat!(
+            ";; Tables.\n",
+            "(table $t 1 funcref) ;; One.\n",
+            "((a b) \"c\" (@x 1.5) ())\n",
+        );
+        let exprs = Expr::parse_all(src).unwrap();
+
+        assert_eq!(
+            exprs.iter().map(Expr::to_string).collect::<Vec<_>>(),
+            [
+                ";; Tables.\n(table $t 1 funcref) ;; One.",
+                "((a b) \"c\" (@x 1.5) ())",
+            ],
+        );
+
+        let module = Expr::list(
+            "module",
+            vec![
+                Expr::atom("$m").unwrap(),
+                Expr::list("memory", vec![Expr::atom("1").unwrap()])
+                    .with_comments(
+                        Comments::default()
+                            .with_trailing(Comment::block("one page").unwrap()),
+                    ),
+                Expr::list("tag", vec![Expr::atom("$e").unwrap()]),
+            ],
+        );
+
+        assert_eq!(
+            module.print_with(&PrintOptions::default().with_line_width(20)),
+            "(module\n  $m\n  (memory 1) (; one page ;)\n  (tag $e)\n)",
+        );
+        assert_eq!(
+            "(module $m (memory 1) (; one page ;) (tag $e))"
+                .parse::<Expr>()
+                .unwrap()
+                .to_string(),
+            module.to_string(),
+        );
+        assert_eq!("(a".parse::<Expr>().unwrap_err().message(), "expected `)`");
+        assert!("a b".parse::<Expr>().is_err());
+        assert!(Expr::atom("\"a b\"").is_some());
+        assert!(Expr::atom(")").is_none());
+        assert!(Expr::atom("a b").is_none());
+        assert!(Expr::atom(";; a").is_none());
+        assert!(Expr::atom("").is_none());
+    }
+
+    #[test]
+    fn custom_instructions() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Mix {
+            func: Index,
+        }
+
+        impl CustomInstruction for Mix {
+            fn immediates(&self) -> Vec<Atom> {
+                self.func.as_atoms()
+            }
+
+            fn arity(&self) -> Option<(usize, usize)> {
+                Some((2, 1))
+            }
+
+            fn indexes(&self) -> Vec<&Index> {
+                vec![&self.func]
+            }
+        }
+
+        impl wast::parser::Parse<'_> for Mix {
+            fn parse(
+                parser: wast::parser::Parser<'_>,
+            ) -> wast::parser::Result<Self> {
+                Ok(Self {
+                    func: parser.parse()?,
+                })
             }
-            .to_string(),
-            "(module)",
+        }
+
+        let options = ParseOptions::default()
+            .with_instruction::<Mix>("ext.mix")
+            .unwrap();
+
+        let src = concat!(
+            "(module\n",
+            "  (func $f (result i32)\n",
+            "    i32.const 1\n",
+            "    i32.const 2\n",
+            "    ext.mix $f\n",
+            "    (ext.mix $f (i32.const 3) (i32.const 4))\n",
+            "    i32.add))",
+        );
+        let doc = Document::parse_with(src, &options).unwrap();
+        let body = |doc: &Document| match doc.modules()[0].kind() {
+            | ModuleKind::Text(sections) => match sections[0] {
+                | Section::Function(ref f) => f.entries()[0].exprs().to_vec(),
+                | _ => panic!("not a function: {:?}", sections[0]),
+            },
+            | _ => unreachable!(),
+        };
+        let exprs = body(&doc);
+
+        match exprs[2] {
+            | Expression::Unfolded(Instruction::Custom(ref mix)) => {
+                assert_eq!(mix.keyword(), "ext.mix");
+                assert_eq!(
+                    mix.downcast_ref::<Mix>(),
+                    Some(&Mix {
+                        func: symbolic("f"),
+                    }),
+                );
+            },
+            | _ => panic!("not custom: {:?}", exprs[2]),
+        }
+
+        match exprs[3] {
+            | Expression::Folded(ref instr) => {
+                assert_eq!(instr.exprs().len(), 2);
+                assert_eq!(instr.indexes(), [&symbolic("f")]);
+            },
+            | _ => panic!("not folded: {:?}", exprs[3]),
+        }
+
+        assert_eq!(
+            doc.to_string(),
+            concat!(
+                "(module\n",
+                "  (func\n",
+                "    $f\n",
+                "    (result i32)\n",
+                "    i32.const 1\n",
+                "    i32.const 2\n",
+                "    ext.mix $f\n",
+                "    (ext.mix $f (i32.const 3) (i32.const 4))\n",
+                "    i32.add\n",
+                "  )\n",
+                ")",
+            ),
+        );
+        assert_eq!(
+            fold_exprs(exprs)
+                .iter()
+                .map(|e| e.expr().print_with(&PrintOptions::default()))
+                .collect::<Vec<_>>(),
+            [concat!(
+                "(i32.add\n",
+                "  (ext.mix $f (i32.const 1) (i32.const 2))\n",
+                "  (ext.mix $f (i32.const 3) (i32.const 4))\n",
+                ")",
+            )],
+        );
+
+        let (recovered, errors) =
+            Document::parse_recovering_with(src, &options);
+
+        assert!(errors.is_empty());
+        assert_eq!(recovered, doc);
+
+        // Without the options, the instruction is not known.
+        let unknown = body(&src.parse::<Document>().unwrap());
+
+        assert!(matches!(
+            unknown[2],
+            Expression::Unfolded(Instruction::Unknown(_)),
+        ));
+        assert_eq!(
+            options.clone().with_instruction::<Mix>("i32.add").unwrap_err(),
+            KeywordError::BuiltIn("i32.add".to_owned()),
+        );
+        assert_eq!(
+            options
+                .with_instruction::<Mix>("ext.mix")
+                .unwrap_err()
+                .to_string(),
+            "`ext.mix` is already defined",
         );
     }
 }
@@ -0,0 +1,337 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use wast::{lexer::Lexer, Span};
+
+use crate::span::offset_in;
+
+/// An error from parsing WebAssembly text.
+///
+/// Once the source is known, see [`Error::set_text`], the error displays the
+/// line and column it points at with an annotated snippet of the source:
+///
+/// ```text
+/// unexpected token
+///  --> env.wat:2:22
+///   |
+/// 2 |   (import "env" "t" (table))
+///   |                      ^^^^^ expected `func`
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    inner: Box<Inner>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Inner {
+    kind:     ErrorKind,
+    span:     Span,
+    message:  String,
+    expected: Vec<String>,
+    source:   Option<Source>,
+    path:     Option<PathBuf>,
+}
+
+/// What kind of mistake an [`Error`] reports.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// Text that is not a token, such as an unterminated string.
+    Lex,
+
+    /// A token other than the expected ones.
+    UnexpectedToken,
+
+    /// The input ended where more tokens were expected.
+    UnexpectedEnd,
+
+    /// A `)` without a matching `(`, or a `(` without a matching `)`.
+    UnbalancedParens,
+
+    /// A token that cannot start an instruction, such as an integer or a
+    /// string. Instructions this crate does not know are keywords like the
+    /// others, and are parsed as [`Unknown`](crate::Unknown).
+    ExpectedInstruction,
+
+    /// A list that cannot be a module field, such as one that does not start
+    /// with a keyword.
+    UnknownSection,
+
+    /// `()` where a module field is expected.
+    EmptySection,
+
+    /// A data offset without an expression.
+    EmptyInitExpr,
+
+    /// A global or a data offset with more than one expression.
+    TooManyInitExprs,
+
+    /// A malformed integer literal.
+    InvalidInteger,
+
+    /// A second start function in a module.
+    MultipleStart,
+
+    /// Any other error.
+    Other,
+}
+
+impl ErrorKind {
+    /// Returns the message of the errors of this kind raised while parsing.
+    fn raised_message(self) -> Option<&'static str> {
+        match self {
+            | Self::ExpectedInstruction => Some("expected an instruction"),
+            | Self::UnknownSection => Some("unexpected section"),
+            | Self::EmptySection => Some("empty section"),
+            | Self::EmptyInitExpr => Some("init_expr is empty"),
+            | Self::TooManyInitExprs => {
+                Some("only one init_expr operator is expected")
+            },
+            | Self::InvalidInteger => Some("could not parse integer"),
+            | Self::MultipleStart => Some("multiple start sections"),
+            | _ => None,
+        }
+    }
+}
+
+/// The part of the source an error points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Source {
+    line:    usize,
+    col:     usize,
+    snippet: String,
+
+    /// The length of the token the error points at, in characters.
+    len: usize,
+}
+
+/// At most this many expected tokens are displayed.
+const MAX_DISPLAYED_EXPECTED: usize = 8;
+
+impl Error {
+    pub(crate) fn new(kind: ErrorKind, span: Span, message: String) -> Self {
+        Self {
+            inner: Box::new(Inner {
+                kind,
+                span,
+                message,
+                expected: Vec::new(),
+                source: None,
+                path: None,
+            }),
+        }
+    }
+
+    /// Converts an error from parsing `text`.
+    pub(crate) fn parse(err: wast::Error, text: &str) -> Self {
+        let mut err = Self::from(err);
+
+        err.set_text(text);
+        err
+    }
+
+    /// Converts an error raised as `kind` while parsing.
+    pub(crate) fn raised(err: wast::Error, kind: ErrorKind) -> Self {
+        let (message, expected) = split_expected(&err.message());
+        let mut raised = Self::new(kind, err.span(), message);
+
+        raised.inner.expected = expected;
+        raised
+    }
+
+    /// Moves the error to `span`, forgetting its source.
+    pub(crate) fn with_span(mut self, span: Span) -> Self {
+        self.inner.span = span;
+        self.inner.source = None;
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.inner.kind
+    }
+
+    pub fn span(&self) -> Span {
+        self.inner.span
+    }
+
+    /// Returns the message, without the expected tokens.
+    pub fn message(&self) -> &str {
+        &self.inner.message
+    }
+
+    /// Returns the tokens that would have been accepted where the error
+    /// points, such as `func` or `i32.add`. The list is empty when it is not
+    /// known.
+    pub fn expected(&self) -> &[String] {
+        &self.inner.expected
+    }
+
+    /// Returns the zero-based line and byte column of the error, once the
+    /// source is known.
+    pub fn line_col(&self) -> Option<(usize, usize)> {
+        self.inner.source.as_ref().map(|s| (s.line, s.col))
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.inner.path.as_deref()
+    }
+
+    /// Records `text`, the source that was parsed, to display the error with
+    /// a snippet. Errors returned by this crate already have their text set.
+    pub fn set_text(&mut self, text: &str) {
+        let (line, col) = self.inner.span.linecol_in(text);
+        let offset = offset_in(self.inner.span, text);
+        let start = offset - col;
+        let snippet = text[start..].lines().next().unwrap_or("");
+        let len = match Lexer::new(&snippet[col.min(snippet.len())..]).next()
+        {
+            | Some(Ok(token)) => token.src().chars().count().max(1),
+            | _ => 1,
+        };
+
+        self.inner.source = Some(Source {
+            line,
+            col,
+            snippet: snippet.to_owned(),
+            len,
+        });
+    }
+
+    /// Records the path of the file that was parsed, which is displayed with
+    /// the error.
+    pub fn set_path(&mut self, path: &Path) {
+        self.inner.path = Some(path.to_owned());
+    }
+
+    fn fmt_expected(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expected = self.expected();
+        let quoted = expected
+            .iter()
+            .take(MAX_DISPLAYED_EXPECTED)
+            .map(|e| format!("`{}`", e))
+            .collect::<Vec<_>>();
+
+        match quoted.as_slice() {
+            | [] => Ok(()),
+            | [one] => write!(f, "expected {}", one),
+            | [init @ .., last] if expected.len() == quoted.len() => {
+                write!(f, "expected {} or {}", init.join(", "), last)
+            },
+            | _ => write!(f, "expected one of {}, ...", quoted.join(", ")),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = match self.inner.source {
+            | Some(ref source) => source,
+            | None => {
+                write!(f, "{}", self.inner.message)?;
+
+                if !self.inner.expected.is_empty() {
+                    write!(f, ", ")?;
+                    self.fmt_expected(f)?;
+                }
+
+                return Ok(());
+            },
+        };
+        let line = (source.line + 1).to_string();
+        let gutter = " ".repeat(line.len());
+        let before = source.snippet.get(..source.col).unwrap_or("");
+        // Tabs are kept so that the caret lines up however wide they are
+        // displayed.
+        let indent = before
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let path = self
+            .path()
+            .map_or("<anon>".into(), |p| p.display().to_string());
+
+        writeln!(f, "{}", self.inner.message)?;
+        writeln!(
+            f,
+            "{}--> {}:{}:{}",
+            gutter,
+            path,
+            source.line + 1,
+            before.chars().count() + 1,
+        )?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", line, source.snippet)?;
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            indent,
+            "^".repeat(source.len),
+        )?;
+
+        if !self.inner.expected.is_empty() {
+            write!(f, " ")?;
+            self.fmt_expected(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Converts an error raised by `wast`. The errors this crate raises are
+/// converted with [`State::error`](crate::state::State::error), which knows
+/// their kind.
+impl From<wast::Error> for Error {
+    fn from(err: wast::Error) -> Self {
+        let (message, expected) = split_expected(&err.message());
+        let kind = if err.lex_error().is_some() {
+            ErrorKind::Lex
+        } else if message == "unexpected end of input" {
+            ErrorKind::UnexpectedEnd
+        } else if !expected.is_empty() {
+            ErrorKind::UnexpectedToken
+        } else {
+            ErrorKind::Other
+        };
+
+        Self::raised(err, kind)
+    }
+}
+
+/// Splits the tokens listed by `wast` out of a message such as "unexpected
+/// token, expected one of: `a`, `b`".
+fn split_expected(message: &str) -> (String, Vec<String>) {
+    let (message, list) = match message.split_once(", expected ") {
+        | Some(split) => split,
+        | None => return (message.to_owned(), Vec::new()),
+    };
+    let list = list.strip_prefix("one of: ").unwrap_or(list);
+    let expected = list
+        .split(", ")
+        .flat_map(|s| s.split(" or "))
+        .map(|s| s.trim_matches('`').to_owned())
+        .collect();
+
+    (message.to_owned(), expected)
+}
+
+/// Returns the message of an error of `kind` raised while parsing, listing
+/// the `expected` tokens the way `wast` does.
+pub(crate) fn message(kind: ErrorKind, expected: &[&str]) -> String {
+    let mut message = kind.raised_message().unwrap_or_default().to_owned();
+
+    if !expected.is_empty() {
+        let quoted = expected
+            .iter()
+            .map(|e| format!("`{}`", e))
+            .collect::<Vec<_>>();
+
+        message.push_str(", expected one of: ");
+        message.push_str(&quoted.join(", "));
+    }
+
+    message
+}
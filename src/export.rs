@@ -1,16 +1,33 @@
 use wast::parser::{Cursor, Parse, Parser, Peek, Result};
 
-use crate::{Atom, Expr, FunctionSectionEntry, SExpr};
+use crate::{
+    span::ignore_spans, Atom, Expr, FunctionSectionEntry, SExpr,
+};
 
 /// https://webassembly.github.io/spec/core/text/modules.html#text-global-abbrev
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct InlineExport {
-    name: String,
+    name:      String,
+    name_span: Option<wast::Span>,
 }
 
+ignore_spans!(InlineExport { name; name_span });
+
 impl InlineExport {
     pub fn new(name: String) -> Self {
-        Self { name }
+        Self {
+            name,
+            name_span: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the span of the export name string.
+    pub fn name_span(&self) -> Option<wast::Span> {
+        self.name_span
     }
 }
 
@@ -19,7 +36,7 @@ impl SExpr for InlineExport {
         "export".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         vec![Expr::Atom(Atom::new(format!(r#""{}""#, self.name)))]
     }
 }
@@ -28,9 +45,10 @@ impl Parse<'_> for InlineExport {
     fn parse(parser: Parser<'_>) -> Result<Self> {
         parser.parse::<wast::kw::export>()?;
 
+        let name_span = Some(parser.cur_span());
         let name = parser.parse::<String>()?;
 
-        Ok(Self { name })
+        Ok(Self { name, name_span })
     }
 }
 
@@ -51,10 +69,10 @@ impl SExpr for Export {
         "export".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         vec![
             Expr::Atom(Atom::new(format!(r#""{}""#, self.name))),
-            Expr::SExpr(Box::new(self.desc.clone())),
+            Expr::SExpr(Box::new(&self.desc)),
         ]
     }
 }
@@ -92,7 +110,7 @@ impl SExpr for ExportDesc {
         }
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         match self {
             Self::Func(f) => f.cdr(),
         }
@@ -1,7 +1,15 @@
-use wast::parser::{Parse, Parser, Result};
+use wast::parser::{Parser, Result};
 
 use crate::{
-    Atom, Expr, Index, Indexes, Integer, SExpr, SymbolicIndex, ValueType,
+    canonical::Canonicalize,
+    error::ErrorKind,
+    print::Printable,
+    query::Indexed,
+    recover,
+    state::{parse_without_state, ParseIn, State},
+    Annotations, Atom, Comment, Comments, Custom, Expr, Float, Index, Indexes,
+    Integer, Malformed, SExpr, Spanned, SymbolicIndex, TypeUse, Unknown,
+    ValueType,
 };
 
 pub fn fold(i: Instruction) -> Expression {
@@ -10,29 +18,37 @@ pub fn fold(i: Instruction) -> Expression {
 
 pub fn global_get<S: AsRef<str>>(s: S) -> Instruction {
     Instruction::GlobalGet(GlobalGet {
-        idx:   Index::Symbolic(SymbolicIndex::new(s.as_ref().to_owned())),
-        exprs: vec![],
+        idx:      Index::Symbolic(SymbolicIndex::new(s.as_ref().to_owned())),
+        exprs:    vec![],
+        comments: Comments::default(),
+        span:     None,
     })
 }
 
 pub fn i32_const<S: AsRef<str>>(s: S) -> Instruction {
     Instruction::I32Const(I32Const {
-        integer: Integer::new(s.as_ref().to_owned()),
-        exprs:   vec![],
+        integer:  Integer::new(s.as_ref().to_owned()),
+        exprs:    vec![],
+        comments: Comments::default(),
+        span:     None,
     })
 }
 
 pub fn i64_const<S: AsRef<str>>(s: S) -> Instruction {
     Instruction::I64Const(I64Const {
-        integer: Integer::new(s.as_ref().to_owned()),
-        exprs:   vec![],
+        integer:  Integer::new(s.as_ref().to_owned()),
+        exprs:    vec![],
+        comments: Comments::default(),
+        span:     None,
     })
 }
 
 pub fn local_get<S: AsRef<str>>(s: S) -> Instruction {
     Instruction::LocalGet(LocalGet {
-        idx:   Index::Symbolic(SymbolicIndex::new(s.as_ref().to_owned())),
-        exprs: vec![],
+        idx:      Index::Symbolic(SymbolicIndex::new(s.as_ref().to_owned())),
+        exprs:    vec![],
+        comments: Comments::default(),
+        span:     None,
     })
 }
 
@@ -46,6 +62,10 @@ enum Paren {
 struct Level {
     expr:     Expression,
     subexprs: Vec<Expression>,
+    leading:  Vec<Comment>,
+
+    /// The `(` of the folded instruction.
+    open: wast::Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -55,10 +75,33 @@ pub enum Expression {
 }
 
 impl Expression {
-    pub(crate) fn expr(&self) -> Expr {
+    pub(crate) fn expr(&self) -> Expr<'_> {
+        match self {
+            Self::Unfolded(i) => i.comments().wrap(Expr::Atom(i.as_atom())),
+            Self::Folded(Instruction::Unknown(u)) => u.expr(),
+            Self::Folded(i) => {
+                i.comments().wrap(Expr::SExpr(Box::new(i)))
+            },
+        }
+    }
+
+    /// Attaches comments, printed around the expression.
+    pub fn with_comments(mut self, comments: Comments) -> Self {
+        *self.comments_mut() = comments;
+        self
+    }
+
+    pub fn comments(&self) -> &Comments {
+        match self {
+            Self::Unfolded(i) => i.comments(),
+            Self::Folded(i) => i.comments(),
+        }
+    }
+
+    pub fn comments_mut(&mut self) -> &mut Comments {
         match self {
-            Self::Unfolded(i) => Expr::Atom(i.as_atom()),
-            Self::Folded(i) => Expr::SExpr(Box::new(i.clone())),
+            Self::Unfolded(i) => i.comments_mut(),
+            Self::Folded(i) => i.comments_mut(),
         }
     }
 
@@ -70,6 +113,28 @@ impl Expression {
     }
 }
 
+impl Spanned for Expression {
+    fn span(&self) -> Option<wast::Span> {
+        match self {
+            | Self::Unfolded(i) | Self::Folded(i) => i.span(),
+        }
+    }
+}
+
+impl Canonicalize for Expression {
+    fn canonicalize(&mut self) {
+        match self {
+            | Self::Unfolded(i) | Self::Folded(i) => i.canonicalize(),
+        }
+    }
+}
+
+impl Printable for Expression {
+    fn print_exprs(&self) -> Vec<Expr<'_>> {
+        vec![self.expr()]
+    }
+}
+
 #[derive(Default)]
 pub(crate) struct ExpressionParser {
     exprs: Vec<Expression>,
@@ -77,43 +142,73 @@ pub(crate) struct ExpressionParser {
 }
 
 impl ExpressionParser {
-    pub fn parse(mut self, parser: Parser) -> Result<Vec<Expression>> {
+    pub fn parse(
+        mut self,
+        parser: Parser,
+        state: &State,
+    ) -> Result<Vec<Expression>> {
         while !parser.is_empty() || !self.stack.is_empty() {
-            match self.paren(parser)? {
-                Paren::Left => {
-                    let instr = parser.parse::<Instruction>()?;
-                    self.stack.push(Level {
-                        expr:     Expression::Folded(instr),
-                        subexprs: Vec::new(),
-                    });
-                },
-                Paren::None => {
-                    let instr = parser.parse::<Instruction>()?;
-                    let expr = Expression::Unfolded(instr);
+            let step = recover::attempt(parser, state, |p| self.step(p, state));
 
-                    match self.stack.last_mut() {
-                        Some(level) => level.subexprs.push(expr),
-                        None => self.exprs.push(expr),
-                    }
-                },
-                Paren::Right => match self.stack.pop() {
-                    Some(mut level) => {
-                        level.expr.subexprs().append(&mut level.subexprs);
-
-                        if let Some(top) = self.stack.last_mut() {
-                            top.subexprs.push(level.expr);
-                        } else {
-                            self.exprs.push(level.expr);
-                        }
-                    },
-                    None => {},
-                },
+            if let Err(malformed) = step? {
+                self.push(Expression::Unfolded(Instruction::Error(malformed)));
             }
         }
 
         Ok(self.exprs.clone())
     }
 
+    /// Parses either an instruction, or the `)` closing a folded one.
+    fn step(&mut self, parser: Parser, state: &State) -> Result<()> {
+        match self.paren(parser)? {
+            Paren::Left => {
+                let open = parser.prev_span();
+                let leading = state.comments.leading_at(open);
+                let instr = parse_folded(parser, state)?;
+                self.stack.push(Level {
+                    expr: Expression::Folded(instr),
+                    subexprs: Vec::new(),
+                    leading,
+                    open,
+                });
+            },
+            Paren::None => {
+                let start = parser.cur_span();
+                let leading = state.comments.leading(parser);
+                let instr = Instruction::parse_in(parser, state)?;
+                let mut trailing =
+                    state.comments.inside(start, parser.prev_span());
+                let mut expr = Expression::Unfolded(instr);
+
+                trailing.extend(state.comments.trailing(parser));
+
+                *expr.comments_mut() = Comments::new(leading, trailing);
+                self.push(expr);
+            },
+            Paren::Right => {
+                if let Some(mut level) = self.stack.pop() {
+                    level.expr.subexprs().append(&mut level.subexprs);
+                    *level.expr.comments_mut() = state.comments.around(
+                        parser,
+                        level.open,
+                        level.leading,
+                    );
+                    self.push(level.expr);
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Adds `expr` to the innermost folded instruction being parsed.
+    fn push(&mut self, expr: Expression) {
+        match self.stack.last_mut() {
+            Some(level) => level.subexprs.push(expr),
+            None => self.exprs.push(expr),
+        }
+    }
+
     /// Parses either `(`, `)`, or nothing.
     fn paren(&self, parser: Parser) -> Result<Paren> {
         parser.step(|cursor| {
@@ -129,6 +224,15 @@ impl ExpressionParser {
     }
 }
 
+/// Parses the instruction of a folded expression, after its `(`. An
+/// [`Unknown`] instruction takes the rest of the list.
+pub(crate) fn parse_folded(
+    parser: Parser<'_>,
+    state: &State,
+) -> Result<Instruction> {
+    Instruction::parse_with(parser, state, true)
+}
+
 #[macro_export]
 macro_rules! instructions {
     (pub enum Instruction {
@@ -149,15 +253,89 @@ macro_rules! instructions {
             $(
                 $name($name),
             )*
+
+            /// An instruction that this crate does not model, see
+            /// [`Unknown`].
+            Unknown(Unknown),
+
+            /// An instruction defined outside this crate, see
+            /// [`CustomInstruction`](crate::CustomInstruction).
+            Custom(Custom),
+
+            /// An instruction that failed to parse, see [`Malformed`].
+            Error(Malformed),
         }
 
 
         impl Instruction {
+            /// The keywords of the instructions this crate models.
+            pub(crate) const KEYWORDS: &'static [&'static str] =
+                &[$($instr),*];
+
+            /// Returns the operands and body of a folded instruction.
+            pub fn exprs(&self) -> &[Expression] {
+                match self {
+                    $(
+                        Self::$name(i) => &i.exprs,
+                    )*
+                    Self::Unknown(u) => &u.exprs,
+                    Self::Custom(c) => &c.exprs,
+                    Self::Error(m) => &m.exprs,
+                }
+            }
+
+            /// Returns a copy of the instruction without its operands and
+            /// body.
+            pub(crate) fn without_exprs(&self) -> Self {
+                match self {
+                    $(
+                        Self::$name(i) => Self::$name($name {
+                            $(
+                                $field_name: i.$field_name.clone(),
+                            )*
+                            exprs: Vec::new(),
+                            comments: i.comments.clone(),
+                            span: i.span,
+                        }),
+                    )*
+                    Self::Unknown(u) => Self::Unknown(u.clone()),
+                    Self::Custom(c) => Self::Custom(c.without_exprs()),
+                    Self::Error(m) => Self::Error(m.clone()),
+                }
+            }
+
             pub fn subexprs(&mut self) -> &mut Vec<Expression> {
                 match self {
                     $(
                         Self::$name(i) => &mut i.exprs,
                     )*
+                    Self::Unknown(u) => &mut u.exprs,
+                    Self::Custom(c) => &mut c.exprs,
+                    Self::Error(m) => &mut m.exprs,
+                }
+            }
+        }
+
+        impl Instruction {
+            pub fn comments(&self) -> &Comments {
+                match self {
+                    $(
+                        Self::$name(i) => &i.comments,
+                    )*
+                    Self::Unknown(u) => &u.comments,
+                    Self::Custom(c) => &c.comments,
+                    Self::Error(m) => &m.comments,
+                }
+            }
+
+            pub fn comments_mut(&mut self) -> &mut Comments {
+                match self {
+                    $(
+                        Self::$name(i) => &mut i.comments,
+                    )*
+                    Self::Unknown(u) => &mut u.comments,
+                    Self::Custom(c) => &mut c.comments,
+                    Self::Error(m) => &mut m.comments,
                 }
             }
         }
@@ -168,21 +346,98 @@ macro_rules! instructions {
                     $(
                         Self::$name(i) => i.as_atom(),
                     )*
+                    Self::Unknown(u) => u.as_atom(),
+                    Self::Custom(c) => c.as_atom(),
+                    Self::Error(m) => Atom::new(m.text().to_owned()),
                 }
             }
         }
 
-        impl Parse<'_> for Instruction {
-            fn parse(parser: Parser<'_>) -> Result<Self> {
+        impl Instruction {
+            /// Returns the indexes among the immediates, such as the label of
+            /// `br` or the function of `call`.
+            pub fn indexes(&self) -> Vec<&Index> {
+                let mut v = Vec::new();
+
+                match self {
+                    $(
+                        Self::$name(i) => i.indexes(&mut v),
+                    )*
+                    Self::Custom(c) => v.extend(c.indexes()),
+                    Self::Unknown(_) | Self::Error(_) => {},
+                }
+
+                v
+            }
+        }
+
+        impl Spanned for Instruction {
+            fn span(&self) -> Option<wast::Span> {
+                match self {
+                    $(
+                        Self::$name(i) => i.span,
+                    )*
+                    Self::Unknown(u) => u.span,
+                    Self::Custom(c) => c.span,
+                    Self::Error(m) => m.span,
+                }
+            }
+        }
+
+        impl Canonicalize for Instruction {
+            fn canonicalize(&mut self) {
+                match self {
+                    $(
+                        Self::$name(i) => i.canonicalize(),
+                    )*
+                    Self::Unknown(_) | Self::Custom(_) | Self::Error(_) => {},
+                }
+            }
+        }
+
+        impl ParseIn for Instruction {
+            fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+                Self::parse_with(parser, state, false)
+            }
+        }
+
+        parse_without_state!(Instruction);
+
+        impl Instruction {
+            /// Parses an instruction. An [`Unknown`] one takes the rest of
+            /// the list if it is `folded`.
+            fn parse_with(
+                parser: Parser<'_>,
+                state: &State,
+                folded: bool,
+            ) -> Result<Self> {
                 let mut l = parser.lookahead1();
 
                 $(
                     if l.peek::<kw::$keyword>() {
-                        return Ok(Self::$name(parser.parse()?));
+                        return Ok(Self::$name($name::parse_in(parser, state)?));
                     }
                 )*
 
-                Err(l.error())
+                if let Some(c) = state.instructions().parse(parser)? {
+                    return Ok(Self::Custom(c));
+                }
+
+                if folded && parser.peek::<Unknown>() {
+                    return Ok(Self::Unknown(Unknown::parse_list(
+                        parser, state,
+                    )?));
+                }
+
+                if parser.peek::<Unknown>() {
+                    return Ok(Self::Unknown(Unknown::parse_in(parser, state)?));
+                }
+
+                Err(state.raise(
+                    ErrorKind::ExpectedInstruction,
+                    parser.cur_span(),
+                    &[],
+                ))
             }
         }
 
@@ -192,67 +447,96 @@ macro_rules! instructions {
                     $(
                         Self::$name(i) => i.car(),
                     )*
+                    Self::Unknown(u) => u.car(),
+                    Self::Custom(c) => c.car(),
+                    Self::Error(m) => m.text().to_owned(),
                 }
             }
 
-            fn cdr(&self) -> Vec<Expr> {
+            fn cdr(&self) -> Vec<Expr<'_>> {
                 match self {
                     $(
                         Self::$name(i) => i.cdr(),
                     )*
+                    Self::Unknown(u) => u.cdr(),
+                    Self::Custom(c) => c.cdr(),
+                    Self::Error(_) => Vec::new(),
                 }
             }
         }
 
         $(
-            #[derive(Debug, Clone, PartialEq, Eq)]
+            #[derive(Debug, Clone)]
             pub struct $name {
                 $(
                     pub $field_name: $field_type,
                 )*
                 pub exprs: Vec<Expression>,
+                pub comments: Comments,
+                pub span: Option<wast::Span>,
+            }
+
+            /// Ignores the span, see [`SymbolicIndex`].
+            impl PartialEq for $name {
+                fn eq(&self, other: &Self) -> bool {
+                    $(self.$field_name == other.$field_name &&)*
+                        self.exprs == other.exprs
+                        && self.comments == other.comments
+                }
             }
 
+            impl Eq for $name {}
+
             impl $name {
                 pub fn as_atom(&self) -> Atom {
                     #[allow(unused_mut)]
-                    let mut s = std::string::String::new();
+                    let mut s = std::string::String::from($instr);
 
                     $(
-                        s.push(' ');
-                        s.push_str(
-                            &self
-                                .$field_name
-                                .as_atoms()
-                                .iter()
-                                .map(ToString::to_string)
-                                .collect::<Vec<std::string::String>>()
-                                .join(" ")
-                        );
+                        for expr in self.$field_name.immediates() {
+                            s.push(' ');
+                            s.push_str(&expr.to_string());
+                        }
                     )*
 
                     Atom::new(s)
                 }
             }
 
+            impl Spanned for $name {
+                fn span(&self) -> Option<wast::Span> {
+                    self.span
+                }
+            }
+
+            impl Indexed for $name {
+                #[allow(unused_variables)]
+                fn indexes<'a>(&'a self, v: &mut Vec<&'a Index>) {
+                    $(
+                        self.$field_name.indexes(v);
+                    )*
+                }
+            }
+
+            impl Canonicalize for $name {
+                fn canonicalize(&mut self) {
+                    $(
+                        self.$field_name.canonicalize();
+                    )*
+                    self.exprs.canonicalize();
+                }
+            }
+
             impl SExpr for $name {
                 fn car(&self) -> std::string::String {
                     format!("{}", $instr)
                 }
 
-                fn cdr(&self) -> Vec<Expr> {
+                fn cdr(&self) -> Vec<Expr<'_>> {
                     let mut v = Vec::new();
 
                     $(
-                        v.append(
-                            &mut self
-                                .clone()
-                                .$field_name
-                                .as_atoms()
-                                .iter()
-                                .map(|a| Expr::Atom(a.clone()))
-                                .collect()
-                        );
+                        v.append(&mut self.$field_name.immediates());
                     )*
 
                     v.append(
@@ -267,12 +551,16 @@ macro_rules! instructions {
                 }
             }
 
-            impl Parse<'_> for $name {
-                fn parse(parser: Parser<'_>) -> Result<Self> {
+            impl ParseIn for $name {
+                #[allow(unused_variables)]
+                fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+                    let span = Some(parser.cur_span());
+
                     parser.parse::<kw::$keyword>()?;
 
                     $(
-                        let $field_name = parser.parse::<$field_type>()?;
+                        let $field_name =
+                            <$field_type>::parse_immediates(parser, state)?;
                     )*
 
                     Ok(Self {
@@ -280,22 +568,29 @@ macro_rules! instructions {
                             $field_name,
                         )*
                         exprs: Vec::new(),
+                        comments: Comments::default(),
+                        span,
                     })
                 }
             }
+
+            parse_without_state!($name);
         )*
     };
 }
 
 instructions!(
     pub enum Instruction {
-        Block      : block       : "block"       { idx: Option<Index> },
+        Block      : block       : "block"       { idx: Option<Index>, block_type: TypeUse },
         Br         : br          : "br"          { idx: Index },
         BrIf       : br_if       : "br_if"       { idx: Index },
         BrTable    : br_table    : "br_table"    { idxs: Indexes },
         Call       : call        : "call"        { idx: Index },
         Drop       : drop        : "drop"        {},
         Else       : r#else      : "else"        {},
+        End        : end         : "end"         { idx: Option<Index> },
+        F32Const   : f32_const   : "f32.const"   { float: Float },
+        F64Const   : f64_const   : "f64.const"   { float: Float },
         GlobalGet  : global_get  : "global.get"  { idx: Index },
         GlobalSet  : global_set  : "global.set"  { idx: Index },
         I32Add     : i32_add     : "i32.add"     {},
@@ -312,12 +607,12 @@ instructions!(
         I32ShrU    : i32_shr_u   : "i32.shr_u"   {},
         I32Sub     : i32_sub     : "i32.sub"     {},
         I64Const   : i64_const   : "i64.const"   { integer: Integer },
-        If         : r#if        : "if"          {},
-        Local      : local       : "local"       { idx: Index, value_type: ValueType },
+        If         : r#if        : "if"          { idx: Option<Index>, block_type: TypeUse },
+        Local      : local       : "local"       { idx: Index, annotations: Annotations, value_type: ValueType },
         LocalGet   : local_get   : "local.get"   { idx: Index },
         LocalSet   : local_set   : "local.set"   { idx: Index },
         LocalTee   : local_tee   : "local.tee"   { idx: Index },
-        Loop       : r#loop      : "loop"        { idx: Option<Index> },
+        Loop       : r#loop      : "loop"        { idx: Option<Index>, block_type: TypeUse },
         MemoryGrow : memory_grow : "memory.grow" {},
         Return     : r#return    : "return"      {},
         Then       : then        : "then"        {},
@@ -328,14 +623,80 @@ pub trait AsAtoms {
     fn as_atoms(&self) -> Vec<Atom>;
 }
 
+/// The immediates of an instruction, printed after its keyword. Most are
+/// atoms; annotations are lists.
+trait Immediates {
+    fn immediates(&self) -> Vec<Expr<'_>>;
+}
+
+impl<T: AsAtoms> Immediates for T {
+    fn immediates(&self) -> Vec<Expr<'_>> {
+        self.as_atoms().into_iter().map(Expr::Atom).collect()
+    }
+}
+
+impl Immediates for Annotations {
+    fn immediates(&self) -> Vec<Expr<'_>> {
+        self.exprs()
+    }
+}
+
+impl Immediates for TypeUse {
+    fn immediates(&self) -> Vec<Expr<'_>> {
+        self.exprs()
+    }
+}
+
+/// Parses the immediates of an instruction, see [`Immediates`].
+trait ParseImmediates: Sized {
+    fn parse_immediates(parser: Parser<'_>, state: &State) -> Result<Self>;
+}
+
+/// Implements [`ParseImmediates`] for immediates parsed without the state.
+macro_rules! parse_immediates {
+    ($($node:ty),* $(,)?) => {
+        $(
+            impl ParseImmediates for $node {
+                fn parse_immediates(
+                    parser: Parser<'_>,
+                    _: &State,
+                ) -> Result<Self> {
+                    parser.parse()
+                }
+            }
+        )*
+    };
+}
+
+parse_immediates!(Float, Index, Option<Index>, Indexes, ValueType);
+
+impl ParseImmediates for Annotations {
+    fn parse_immediates(parser: Parser<'_>, state: &State) -> Result<Self> {
+        Self::parse_in(parser, state)
+    }
+}
+
+impl ParseImmediates for Integer {
+    fn parse_immediates(parser: Parser<'_>, state: &State) -> Result<Self> {
+        Self::parse_in(parser, state)
+    }
+}
+
+/// The block type of `block`, `loop` and `if`.
+impl ParseImmediates for TypeUse {
+    fn parse_immediates(parser: Parser<'_>, state: &State) -> Result<Self> {
+        Self::parse_in(parser, state)
+    }
+}
+
 impl AsAtoms for String {
     fn as_atoms(&self) -> Vec<Atom> {
         vec![Atom::new(format!(r#""{}""#, self))]
     }
 }
 
-impl<T: AsAtoms + Clone> AsAtoms for Option<T> {
+impl<T: AsAtoms> AsAtoms for Option<T> {
     fn as_atoms(&self) -> Vec<Atom> {
-        self.clone().map_or(Vec::new(), |x| x.as_atoms())
+        self.as_ref().map_or(Vec::new(), AsAtoms::as_atoms)
     }
 }
@@ -0,0 +1,78 @@
+use std::fmt;
+
+use wast::parser::{Cursor, Parse, Parser, Peek, Result};
+
+use crate::{AsAtoms, Atom};
+
+/// A floating point literal. Only the source spelling is kept, so `0x1p-1`,
+/// `nan:0x200000` and `1` are all printed back the way they were written.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Float {
+    src: String,
+}
+
+impl Float {
+    pub fn new(src: String) -> Self {
+        Self { src }
+    }
+
+    /// Returns the original source text for this float.
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+}
+
+impl AsAtoms for Float {
+    fn as_atoms(&self) -> Vec<Atom> {
+        vec![Atom::new(self.src.to_owned())]
+    }
+}
+
+impl fmt::Display for Float {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.src)
+    }
+}
+
+impl Parse<'_> for Float {
+    fn parse(parser: Parser<'_>) -> Result<Self> {
+        parser.step(|cursor| {
+            if let Some((f, rest)) = cursor.float() {
+                return Ok((Self::new(f.src().to_owned()), rest));
+            }
+
+            // Integer literals are valid float literals too.
+            if let Some((i, rest)) = cursor.integer() {
+                return Ok((Self::new(i.src().to_owned()), rest));
+            }
+
+            Err(cursor.error("could not parse float"))
+        })
+    }
+}
+
+impl Peek for Float {
+    fn peek(cursor: Cursor<'_>) -> bool {
+        cursor.float().is_some() || cursor.integer().is_some()
+    }
+
+    fn display() -> &'static str {
+        "float"
+    }
+}
+
+/// Spells `f` as a literal. NaNs are spelled `nan` and infinities `inf`,
+/// since `f64`'s `Display` spells them `NaN` and `inf`.
+impl From<f64> for Float {
+    fn from(f: f64) -> Self {
+        let src = match f {
+            | f if f.is_nan() && f.is_sign_negative() => "-nan".to_owned(),
+            | f if f.is_nan() => "nan".to_owned(),
+            | f if f.is_infinite() && f < 0.0 => "-inf".to_owned(),
+            | f if f.is_infinite() => "inf".to_owned(),
+            | f => f.to_string(),
+        };
+
+        Self::new(src)
+    }
+}
@@ -0,0 +1,1089 @@
+use std::{fmt, mem::take};
+
+use crate::{
+    Atom, Block, Comments, End, Expr, Expression, FunctionSectionEntry, If,
+    ImportDesc, ImportSectionEntry, Index, Instruction, Loop, SExpr, Section,
+    Then, TypeSectionEntry, TypeUse,
+};
+
+/// Converts folded expressions into the flat sequence of instructions they
+/// stand for. `block`, `loop` and `if` are closed with `end`. Local
+/// declarations and [`Unknown`](crate::Unknown) instructions are kept as they
+/// are.
+pub fn unfold_exprs(exprs: Vec<Expression>) -> Vec<Expression> {
+    let mut flat = Vec::new();
+
+    for expr in exprs {
+        unfold_into(expr, &mut flat);
+    }
+
+    flat
+}
+
+/// Nests a sequence of instructions into folded expressions, making each
+/// instruction's operands the preceding expressions that produce its
+/// inputs. Calls are not given operands, as their arity is unknown without
+/// the module; see [`Module::folded`](crate::Module::folded).
+///
+/// Expressions are unfolded first, so any mix of forms is accepted. Folding
+/// never reorders instructions, so the result is equivalent to the input even
+/// where operands cannot be matched to an instruction. Expressions holding
+/// [`Unknown`](crate::Unknown) instructions, whose structure is not known,
+/// are returned as they are. The runs of instructions between them are
+/// folded if each block opened in a run is also closed in it.
+pub fn fold_exprs(exprs: Vec<Expression>) -> Vec<Expression> {
+    fold_with(exprs, &Signatures::default())
+}
+
+/// Decides how function bodies are laid out when printed with
+/// [`PrintOptions::with_folding`](crate::PrintOptions::with_folding).
+///
+/// Runs of flat instructions are folded as far as their arities allow before
+/// the policy is consulted, for each instruction from the outside in.
+pub trait FoldingPolicy: fmt::Debug + Send + Sync {
+    /// Returns `true` if `instr` is printed folded, with its operands and,
+    /// for `block`, `loop` and `if`, its body nested inside it. Otherwise its
+    /// operands are laid out before it and it is printed as a flat
+    /// instruction.
+    ///
+    /// `shape` is that of `instr` with the operands it is folded with, which
+    /// are not among its sub-expressions if it was written flat.
+    fn fold(&self, instr: &Instruction, shape: Shape) -> bool;
+}
+
+/// The size of a folded instruction, see [`FoldingPolicy::fold`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shape {
+    depth: usize,
+    size:  usize,
+}
+
+impl Shape {
+    /// The shape of an instruction without operands.
+    const LEAF: Self = Self { depth: 1, size: 1 };
+
+    /// Returns the number of levels of nesting, 1 for an instruction without
+    /// operands.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the number of instructions, counting the instruction itself.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the shape of an instruction whose operands have the given
+    /// shapes.
+    fn of(operands: impl Iterator<Item = Self>) -> Self {
+        operands.fold(Self::LEAF, |shape, operand| Self {
+            depth: shape.depth.max(operand.depth + 1),
+            size:  shape.size + operand.size,
+        })
+    }
+
+    /// Returns the shape of an instruction as written.
+    fn measure(instr: &Instruction) -> Self {
+        Self::of(instr.exprs().iter().map(|expr| match expr {
+            | Expression::Folded(i) => Self::measure(i),
+            | Expression::Unfolded(_) => Self::LEAF,
+        }))
+    }
+}
+
+/// The default [`FoldingPolicy`]. Folds expressions that are short and
+/// shallow, prints longer chains of operations flat, and always keeps
+/// `block`, `loop` and `if` structured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadableFolding {
+    max_depth: usize,
+    max_size:  usize,
+}
+
+impl Default for ReadableFolding {
+    fn default() -> Self {
+        Self::new(3, 6)
+    }
+}
+
+impl ReadableFolding {
+    /// Creates a policy that folds expressions at most `max_depth` levels
+    /// deep and made of at most `max_size` instructions.
+    pub fn new(max_depth: usize, max_size: usize) -> Self {
+        Self {
+            max_depth,
+            max_size,
+        }
+    }
+}
+
+impl FoldingPolicy for ReadableFolding {
+    fn fold(&self, instr: &Instruction, shape: Shape) -> bool {
+        match instr {
+            | Instruction::Block(_)
+            | Instruction::Loop(_)
+            | Instruction::If(_) => true,
+            | _ => {
+                shape.depth <= self.max_depth && shape.size <= self.max_size
+            },
+        }
+    }
+}
+
+pub(crate) fn fold_with(
+    exprs: Vec<Expression>,
+    signatures: &Signatures,
+) -> Vec<Expression> {
+    let exprs = exprs.into_iter().map(|e| {
+        let opaque = is_opaque(&e);
+
+        (e, opaque)
+    });
+
+    between_opaque(exprs.collect(), |run| {
+        let flat = unfold_exprs(run);
+
+        fold_nodes(&flat, signatures)
+            .0
+            .into_iter()
+            .map(Node::into_expression)
+            .collect()
+    })
+}
+
+/// Returns `true` if `instr` was not parsed into a known instruction. Such
+/// an instruction may open or close a block, so instructions around it
+/// cannot be nested safely.
+fn is_opaque_instr(instr: &Instruction) -> bool {
+    matches!(instr, Instruction::Unknown(_) | Instruction::Error(_))
+}
+
+/// Returns `true` if `expr` holds opaque instructions, see
+/// [`is_opaque_instr`].
+fn is_opaque(expr: &Expression) -> bool {
+    match expr {
+        | Expression::Folded(instr) | Expression::Unfolded(instr) => {
+            is_opaque_instr(instr) || instr.exprs().iter().any(is_opaque)
+        },
+    }
+}
+
+/// An item of a sequence of instructions, see [`between_opaque`].
+trait Item {
+    /// Returns the instruction if the item is a flat one.
+    fn flat(&self) -> Option<&Instruction>;
+}
+
+impl Item for Expression {
+    fn flat(&self) -> Option<&Instruction> {
+        match self {
+            | Self::Unfolded(instr) => Some(instr),
+            | Self::Folded(_) => None,
+        }
+    }
+}
+
+impl Item for Node<'_> {
+    fn flat(&self) -> Option<&Instruction> {
+        match self {
+            | Self::Instr {
+                instr,
+                folded: false,
+                ..
+            } => Some(instr),
+            | _ => None,
+        }
+    }
+}
+
+/// Applies `f` to the items, or, if some of them are opaque, to each run of
+/// items between those. The items come with whether they are opaque. Opaque
+/// items are kept as they are, and so are runs that close a block they do
+/// not open or open one they do not close, as it may belong to an opaque
+/// instruction.
+fn between_opaque<T: Item>(
+    items: Vec<(T, bool)>,
+    f: impl Fn(Vec<T>) -> Vec<T>,
+) -> Vec<T> {
+    if !items.iter().any(|(_, opaque)| *opaque) {
+        return f(items.into_iter().map(|(item, _)| item).collect());
+    }
+
+    let mut out = Vec::new();
+    let mut run = Vec::new();
+    let flush = |run: Vec<T>, out: &mut Vec<T>| {
+        if is_balanced(&run) {
+            out.append(&mut f(run));
+        } else {
+            out.extend(run);
+        }
+    };
+
+    for (item, opaque) in items {
+        if opaque {
+            flush(take(&mut run), &mut out);
+            out.push(item);
+        } else {
+            run.push(item);
+        }
+    }
+
+    flush(run, &mut out);
+    out
+}
+
+/// Returns `true` if every `block`, `loop` and `if` opened in `items` is
+/// closed by an `end` in it, and every `end` and `else` matches one.
+fn is_balanced<T: Item>(items: &[T]) -> bool {
+    let mut depth: usize = 0;
+
+    for instr in items.iter().filter_map(Item::flat) {
+        match instr {
+            | Instruction::Block(_)
+            | Instruction::Loop(_)
+            | Instruction::If(_) => depth += 1,
+            | Instruction::Else(_) if depth == 0 => return false,
+            | Instruction::End(_) => match depth.checked_sub(1) {
+                | Some(d) => depth = d,
+                | None => return false,
+            },
+            | _ => {},
+        }
+    }
+
+    depth == 0
+}
+
+/// Lays out `exprs`, a sequence of instructions such as a function body,
+/// with `policy`. Runs of flat instructions are folded first, giving calls
+/// operands according to `signatures`. The result borrows the instructions
+/// of `exprs`.
+pub(crate) fn relayout<'a>(
+    exprs: &'a [Expression],
+    policy: &dyn FoldingPolicy,
+    signatures: &Signatures,
+) -> Vec<Node<'a>> {
+    lay_out(fold_nodes(exprs, signatures).0, policy)
+}
+
+/// Like [`relayout`], returning the expressions laid out.
+pub(crate) fn relayout_exprs(
+    exprs: &[Expression],
+    policy: &dyn FoldingPolicy,
+    signatures: &Signatures,
+) -> Vec<Expression> {
+    relayout(exprs, policy, signatures)
+        .into_iter()
+        .map(Node::into_expression)
+        .collect()
+}
+
+/// An instruction of a body as laid out, borrowing it from the body. The
+/// operands and body of an instruction that is folded are its children,
+/// which are not the sub-expressions of the instruction if it was written
+/// flat.
+#[derive(Debug)]
+pub(crate) enum Node<'a> {
+    /// An expression kept as it is, such as one holding opaque
+    /// instructions.
+    Kept(&'a Expression),
+
+    Instr {
+        instr:    &'a Instruction,
+        folded:   bool,
+        children: Vec<Node<'a>>,
+
+        /// Comments moved to the instruction, from the `end` of a block or
+        /// from the `then` of an `if` laid out flat.
+        extra: Vec<&'a Comments>,
+        shape: Shape,
+    },
+
+    /// The `then` of an `if` that was written flat.
+    Then(Vec<Node<'a>>, Shape),
+
+    /// The `end` of a block that was written folded.
+    End,
+}
+
+impl<'a> Node<'a> {
+    fn flat(instr: &'a Instruction, extra: Vec<&'a Comments>) -> Self {
+        Self::Instr {
+            instr,
+            folded: false,
+            children: Vec::new(),
+            extra,
+            shape: Shape::LEAF,
+        }
+    }
+
+    fn folded(
+        instr: &'a Instruction,
+        children: Vec<Self>,
+        extra: Vec<&'a Comments>,
+    ) -> Self {
+        Self::Instr {
+            instr,
+            folded: true,
+            shape: Shape::of(children.iter().map(Self::shape)),
+            children,
+            extra,
+        }
+    }
+
+    fn then(children: Vec<Self>) -> Self {
+        let shape = Shape::of(children.iter().map(Self::shape));
+
+        Self::Then(children, shape)
+    }
+
+    fn shape(&self) -> Shape {
+        match self {
+            | Self::Kept(Expression::Folded(instr)) => Shape::measure(instr),
+            | Self::Instr { shape, .. } | Self::Then(_, shape) => *shape,
+            | _ => Shape::LEAF,
+        }
+    }
+
+    /// Returns the comments of the instruction, with those moved to it.
+    fn comments(&self) -> Vec<&'a Comments> {
+        match self {
+            | Self::Instr { instr, extra, .. } => {
+                let mut comments = vec![instr.comments()];
+
+                comments.extend(extra);
+                comments
+            },
+            | _ => Vec::new(),
+        }
+    }
+
+    /// Returns the number of values the node leaves on the stack, if known.
+    fn results(&self, signatures: &Signatures) -> Option<usize> {
+        match self {
+            | Self::Kept(expr) => match expr {
+                | Expression::Folded(instr) | Expression::Unfolded(instr) => {
+                    arity(instr, signatures).1
+                },
+            },
+            | Self::Instr { instr, .. } => arity(instr, signatures).1,
+            | Self::Then(..) | Self::End => None,
+        }
+    }
+
+    /// Returns the node as printed.
+    pub(crate) fn expr(&self) -> Expr<'_> {
+        match self {
+            | Self::Kept(expr) => expr.expr(),
+            | Self::Instr {
+                instr,
+                folded,
+                extra,
+                ..
+            } => {
+                let expr = match folded {
+                    | true => Expr::SExpr(Box::new(self)),
+                    | false => Expr::Atom(instr.as_atom()),
+                };
+
+                with_extra(instr.comments(), extra, expr)
+            },
+            | Self::Then(..) => Expr::SExpr(Box::new(self)),
+            | Self::End => Expr::Atom(Atom::new("end".to_owned())),
+        }
+    }
+
+    /// Like [`expr`](Self::expr), for a node that is not kept around while
+    /// printing.
+    pub(crate) fn into_expr(self) -> Expr<'a> {
+        match self {
+            | Self::Kept(expr) => expr.expr(),
+            | Self::Instr {
+                instr,
+                folded,
+                ref extra,
+                ..
+            } => {
+                let extra = extra.clone();
+                let expr = match folded {
+                    | true => Expr::SExpr(Box::new(self)),
+                    | false => Expr::Atom(instr.as_atom()),
+                };
+
+                with_extra(instr.comments(), &extra, expr)
+            },
+            | node => Expr::SExpr(Box::new(node)),
+        }
+    }
+
+    /// Returns the node as an expression that owns its instructions.
+    fn into_expression(self) -> Expression {
+        match self {
+            | Self::Kept(expr) => expr.clone(),
+            | Self::Instr {
+                instr,
+                folded,
+                children,
+                extra,
+                ..
+            } => {
+                let mut instr = instr.without_exprs();
+
+                for comments in extra {
+                    instr.comments_mut().append(comments.clone());
+                }
+
+                *instr.subexprs() =
+                    children.into_iter().map(Self::into_expression).collect();
+
+                match folded {
+                    | true => Expression::Folded(instr),
+                    | false => Expression::Unfolded(instr),
+                }
+            },
+            | Self::Then(children, _) => {
+                Expression::Folded(Instruction::Then(Then {
+                    exprs:    children
+                        .into_iter()
+                        .map(Self::into_expression)
+                        .collect(),
+                    comments: Default::default(),
+                    span:     None,
+                }))
+            },
+            | Self::End => end(),
+        }
+    }
+}
+
+/// Wraps `expr` with `comments` and the `extra` comments moved to them.
+fn with_extra<'a>(
+    comments: &'a Comments,
+    extra: &[&Comments],
+    expr: Expr<'a>,
+) -> Expr<'a> {
+    if extra.is_empty() {
+        return comments.wrap(expr);
+    }
+
+    let mut comments = comments.clone();
+
+    for c in extra {
+        comments.append((*c).clone());
+    }
+
+    comments.wrap_owned(expr)
+}
+
+impl SExpr for Node<'_> {
+    fn car(&self) -> String {
+        match self {
+            | Self::Instr { instr, .. } => instr.car(),
+            | _ => "then".to_owned(),
+        }
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        match self {
+            | Self::Instr {
+                instr,
+                children,
+                ..
+            } => {
+                let mut v = instr.cdr();
+
+                // The immediates come before the sub-expressions, which are
+                // replaced by the children.
+                v.truncate(v.len() - instr.exprs().len());
+                v.extend(children.iter().map(Self::expr));
+                v
+            },
+            | Self::Then(children, _) => {
+                children.iter().map(Self::expr).collect()
+            },
+            | _ => Vec::new(),
+        }
+    }
+}
+
+/// Folds the runs of flat instructions in `exprs` and in the sub-expressions
+/// of the folded ones. Returns the folded nodes, and whether `exprs` hold
+/// opaque instructions.
+fn fold_nodes<'a>(
+    exprs: &'a [Expression],
+    signatures: &Signatures,
+) -> (Vec<Node<'a>>, bool) {
+    let mut items = Vec::new();
+
+    for expr in exprs {
+        items.push(match expr {
+            | Expression::Unfolded(instr) => {
+                (Node::flat(instr, Vec::new()), is_opaque_instr(instr))
+            },
+            | Expression::Folded(instr) => {
+                let (children, opaque) = fold_nodes(instr.exprs(), signatures);
+                let opaque = opaque || is_opaque_instr(instr);
+
+                match opaque || matches!(instr, Instruction::Local(_)) {
+                    | true => (Node::Kept(expr), opaque),
+                    | false => {
+                        (Node::folded(instr, children, Vec::new()), false)
+                    },
+                }
+            },
+        });
+    }
+
+    let opaque = items.iter().any(|(_, opaque)| *opaque);
+
+    (between_opaque(items, |run| fold_runs(run, signatures)), opaque)
+}
+
+/// Folds the runs of flat instructions in `nodes`, leaving folded ones as
+/// they are.
+fn fold_runs<'a>(
+    nodes: Vec<Node<'a>>,
+    signatures: &Signatures,
+) -> Vec<Node<'a>> {
+    let mut flat = nodes.into_iter();
+    let mut folded = Vec::new();
+
+    loop {
+        let (mut nodes, end) = fold_seq(&mut flat, signatures);
+
+        folded.append(&mut nodes);
+
+        // `end` and `else` without a matching block are kept as they are.
+        match end {
+            | Some(node) => folded.push(node),
+            | None => return folded,
+        }
+    }
+}
+
+/// Lays out `nodes` with `policy`, see [`relayout`].
+fn lay_out<'a>(
+    nodes: Vec<Node<'a>>,
+    policy: &dyn FoldingPolicy,
+) -> Vec<Node<'a>> {
+    let mut out = Vec::new();
+
+    for node in nodes {
+        lay_out_into(node, policy, &mut out);
+    }
+
+    out
+}
+
+fn lay_out_into<'a>(
+    node: Node<'a>,
+    policy: &dyn FoldingPolicy,
+    out: &mut Vec<Node<'a>>,
+) {
+    let (instr, mut children, extra, shape) = match node {
+        | Node::Instr {
+            instr,
+            folded: true,
+            children,
+            extra,
+            shape,
+        } => (instr, children, extra, shape),
+        | node => return out.push(node),
+    };
+
+    if !policy.fold(instr, shape) {
+        return flatten_node(instr, children, extra, policy, out);
+    }
+
+    match instr {
+        | Instruction::Block(_) | Instruction::Loop(_) => {
+            children = lay_out(children, policy)
+        },
+        | Instruction::If(_) => {
+            let mut hoisting = true;
+
+            for child in take(&mut children) {
+                match child {
+                    | Node::Then(body, shape) => {
+                        children.push(Node::Then(lay_out(body, policy), shape))
+                    },
+                    | Node::Instr {
+                        instr,
+                        folded: true,
+                        children: body,
+                        extra,
+                        shape,
+                    } if matches!(
+                        instr,
+                        Instruction::Then(_) | Instruction::Else(_),
+                    ) =>
+                    {
+                        children.push(Node::Instr {
+                        instr,
+                        folded: true,
+                            children: lay_out(body, policy),
+                            extra,
+                            shape,
+                        })
+                    },
+                    // Conditions the policy does not fold are laid out before
+                    // the `if`. Only leading ones can be, so that the order of
+                    // instructions is kept.
+                    | Node::Instr {
+                        instr: condition,
+                        folded: true,
+                        shape,
+                        ..
+                    } if hoisting && !policy.fold(condition, shape) => {
+                        lay_out_into(child, policy, out)
+                    },
+                    | condition => {
+                        hoisting = false;
+                        children.push(condition);
+                    },
+                }
+            }
+        },
+        | _ => {},
+    }
+
+    out.push(Node::Instr {
+        instr,
+        folded: true,
+        children,
+        extra,
+        shape,
+    });
+}
+
+/// Writes an instruction to `out` as a flat one after its operands, laying
+/// out its operands and body with `policy`.
+fn flatten_node<'a>(
+    instr: &'a Instruction,
+    children: Vec<Node<'a>>,
+    mut extra: Vec<&'a Comments>,
+    policy: &dyn FoldingPolicy,
+    out: &mut Vec<Node<'a>>,
+) {
+    match instr {
+        | Instruction::Block(_) | Instruction::Loop(_) => {
+            out.push(Node::flat(instr, extra));
+            out.append(&mut lay_out(children, policy));
+            out.push(Node::End);
+        },
+        | Instruction::If(_) => {
+            let mut conditions = Vec::new();
+            let mut then = Vec::new();
+            let mut r#else = None;
+
+            for child in children {
+                match child {
+                    | Node::Then(body, _) => then = body,
+                    | Node::Instr {
+                        instr: Instruction::Then(t),
+                        children: body,
+                        extra: then_extra,
+                        ..
+                    } => {
+                        // There is no `then` keyword in the flat form.
+                        extra.push(&t.comments);
+                        extra.extend(then_extra);
+                        then = body;
+                    },
+                    | Node::Instr {
+                        instr: e @ Instruction::Else(_),
+                        children: body,
+                        extra: else_extra,
+                        ..
+                    } => r#else = Some((e, else_extra, body)),
+                    | condition => conditions.push(condition),
+                }
+            }
+
+            out.append(&mut lay_out(conditions, policy));
+            out.push(Node::flat(instr, extra));
+            out.append(&mut lay_out(then, policy));
+
+            if let Some((e, else_extra, body)) = r#else {
+                out.push(Node::flat(e, else_extra));
+                out.append(&mut lay_out(body, policy));
+            }
+
+            out.push(Node::End);
+        },
+        | _ => {
+            out.append(&mut lay_out(children, policy));
+            out.push(Node::flat(instr, extra));
+        },
+    }
+}
+
+fn end() -> Expression {
+    Expression::Unfolded(Instruction::End(End {
+        idx:      None,
+        exprs:    Vec::new(),
+        comments: Default::default(),
+        span:     None,
+    }))
+}
+
+fn unfold_into(expr: Expression, flat: &mut Vec<Expression>) {
+    match expr {
+        | Expression::Folded(
+            instr @ (Instruction::Local(_) | Instruction::Unknown(_)),
+        ) => flat.push(Expression::Folded(instr)),
+        | Expression::Folded(instr) => flatten(instr, flat, &|exprs, flat| {
+            exprs.into_iter().for_each(|e| unfold_into(e, flat))
+        }),
+        | Expression::Unfolded(instr) => {
+            flat.push(Expression::Unfolded(instr))
+        },
+    }
+}
+
+/// Writes `instr` to `flat` as a flat instruction after its operands. The
+/// operands and, for `block`, `loop` and `if`, the body are laid out by
+/// `children`.
+fn flatten(
+    mut instr: Instruction,
+    flat: &mut Vec<Expression>,
+    children: &dyn Fn(Vec<Expression>, &mut Vec<Expression>),
+) {
+    let exprs = take(instr.subexprs());
+
+    match instr {
+        | Instruction::Block(_) | Instruction::Loop(_) => {
+            flat.push(Expression::Unfolded(instr));
+            children(exprs, flat);
+            flat.push(end());
+        },
+        | Instruction::If(mut i) => {
+            let mut conditions = Vec::new();
+            let mut then = Vec::new();
+            let mut r#else = None;
+
+            for expr in exprs {
+                match expr {
+                    | Expression::Folded(Instruction::Then(t)) => {
+                        // There is no `then` keyword in the flat form.
+                        i.comments.append(t.comments);
+                        then = t.exprs;
+                    },
+                    | Expression::Folded(Instruction::Else(e)) => {
+                        r#else = Some(e)
+                    },
+                    | condition => conditions.push(condition),
+                }
+            }
+
+            children(conditions, flat);
+            flat.push(Expression::Unfolded(Instruction::If(i)));
+            children(then, flat);
+
+            if let Some(mut e) = r#else {
+                let exprs = take(&mut e.exprs);
+
+                flat.push(Expression::Unfolded(Instruction::Else(e)));
+                children(exprs, flat);
+            }
+
+            flat.push(end());
+        },
+        | instr => {
+            children(exprs, flat);
+            flat.push(Expression::Unfolded(instr));
+        },
+    }
+}
+
+/// Folds nodes from `flat` up to the next `end` or `else`, which is returned
+/// along with the folded nodes.
+fn fold_seq<'a, I: Iterator<Item = Node<'a>>>(
+    flat: &mut I,
+    signatures: &Signatures,
+) -> (Vec<Node<'a>>, Option<Node<'a>>) {
+    // The folded nodes, with the number of values each leaves on the stack
+    // if known.
+    let mut stack: Vec<(Node<'a>, Option<usize>)> = Vec::new();
+    let into_nodes = |stack: Vec<(Node<'a>, Option<usize>)>| {
+        stack.into_iter().map(|(n, _)| n).collect()
+    };
+
+    while let Some(node) = flat.next() {
+        let (instr, mut extra) = match node {
+            | Node::Instr {
+                instr,
+                folded: false,
+                extra,
+                ..
+            } => (instr, extra),
+            | node => {
+                let results = node.results(signatures);
+
+                stack.push((node, results));
+
+                continue;
+            },
+        };
+
+        match instr {
+            | Instruction::End(_) | Instruction::Else(_) => {
+                return (into_nodes(stack), Some(Node::flat(instr, extra)));
+            },
+            | Instruction::Block(_) | Instruction::Loop(_) => {
+                let (body, end) = fold_seq(flat, signatures);
+
+                if let Some(end) = end {
+                    extra.append(&mut end.comments());
+                }
+
+                let results = arity(instr, signatures).1;
+
+                stack.push((Node::folded(instr, body, extra), results));
+            },
+            | Instruction::If(_) => {
+                let (params, results) = arity(instr, signatures);
+                let mut children = take_operands(&mut stack, params);
+                let (body, mut end) = fold_seq(flat, signatures);
+
+                children.push(Node::then(body));
+
+                if let Some(Node::Instr {
+                    instr: e @ Instruction::Else(_),
+                    extra: else_extra,
+                    ..
+                }) = end
+                {
+                    let (body, else_end) = fold_seq(flat, signatures);
+
+                    children.push(Node::folded(e, body, else_extra));
+                    end = else_end;
+                }
+
+                if let Some(end) = end {
+                    extra.append(&mut end.comments());
+                }
+
+                stack.push((Node::folded(instr, children, extra), results));
+            },
+            | instr => {
+                let (params, results) = arity(instr, signatures);
+                let operands = take_operands(&mut stack, params);
+
+                stack.push((Node::folded(instr, operands, extra), results));
+            },
+        }
+    }
+
+    (into_nodes(stack), None)
+}
+
+/// Takes the nodes at the top of `stack` that together produce at most `n`
+/// values.
+fn take_operands<'a>(
+    stack: &mut Vec<(Node<'a>, Option<usize>)>,
+    n: usize,
+) -> Vec<Node<'a>> {
+    let mut start = stack.len();
+    let mut count = 0;
+
+    while start > 0 {
+        match stack[start - 1].1 {
+            | Some(k) if k > 0 && count + k <= n => {
+                count += k;
+                start -= 1;
+            },
+            | _ => break,
+        }
+    }
+
+    stack.drain(start..).map(|(n, _)| n).collect()
+}
+
+/// Returns how many values `instr` pops and pushes, with `None` for a count
+/// that is not known.
+fn arity(
+    instr: &Instruction,
+    signatures: &Signatures,
+) -> (usize, Option<usize>) {
+    match instr {
+        | Instruction::Call(c) => match signatures.get(&c.idx) {
+            | Some((params, results)) => (params, Some(results)),
+            | None => (0, None),
+        },
+        | Instruction::Custom(c) => match c.arity() {
+            | Some((params, results)) => (params, Some(results)),
+            | None => (0, None),
+        },
+        // A block takes its parameters from the instructions before it,
+        // which cannot be folded into it, so its results cannot be told
+        // apart from them.
+        | Instruction::Block(Block { block_type, .. })
+        | Instruction::Loop(Loop { block_type, .. }) => {
+            match signatures.type_use_arity(block_type) {
+                | (0, results) => (0, Some(results)),
+                | _ => (0, None),
+            }
+        },
+        // The operands of an `if` are its parameters and the condition.
+        | Instruction::If(If { block_type, .. }) => {
+            let (params, results) = signatures.type_use_arity(block_type);
+
+            (params + 1, Some(results))
+        },
+        | Instruction::Then(_)
+        | Instruction::Else(_)
+        | Instruction::Unknown(_)
+        | Instruction::Error(_) => (0, None),
+        | Instruction::Br(_)
+        | Instruction::End(_)
+        | Instruction::Local(_)
+        | Instruction::Return(_) => (0, Some(0)),
+        | Instruction::BrIf(_)
+        | Instruction::BrTable(_)
+        | Instruction::Drop(_)
+        | Instruction::GlobalSet(_)
+        | Instruction::LocalSet(_) => (1, Some(0)),
+        | Instruction::F32Const(_)
+        | Instruction::F64Const(_)
+        | Instruction::GlobalGet(_)
+        | Instruction::I32Const(_)
+        | Instruction::I64Const(_)
+        | Instruction::LocalGet(_) => (0, Some(1)),
+        | Instruction::I32Eqz(_)
+        | Instruction::I32Load(_)
+        | Instruction::LocalTee(_)
+        | Instruction::MemoryGrow(_) => (1, Some(1)),
+        | Instruction::I32Add(_)
+        | Instruction::I32Eq(_)
+        | Instruction::I32GtU(_)
+        | Instruction::I32LtS(_)
+        | Instruction::I32LtU(_)
+        | Instruction::I32Mul(_)
+        | Instruction::I32Ne(_)
+        | Instruction::I32RemU(_)
+        | Instruction::I32ShrU(_)
+        | Instruction::I32Sub(_) => (2, Some(1)),
+    }
+}
+
+/// The number of parameters and results of each function of a module, in
+/// index space order.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Signatures {
+    types: Vec<(Option<Index>, (usize, usize))>,
+    funcs: Vec<(Option<Index>, (usize, usize))>,
+}
+
+impl Signatures {
+    pub(crate) fn new(sections: &[Section]) -> Self {
+        let mut signatures = Self::default();
+
+        for section in sections {
+            if let Section::Type(types) = section {
+                types.entries().iter().for_each(|e| signatures.add_type(e));
+            }
+        }
+
+        // Imported functions come first in the index space.
+        for section in sections {
+            if let Section::Import(imports) = section {
+                imports
+                    .entries()
+                    .iter()
+                    .for_each(|e| signatures.add_import(e));
+            }
+        }
+
+        for section in sections {
+            if let Section::Function(functions) = section {
+                functions
+                    .entries()
+                    .iter()
+                    .for_each(|e| signatures.add_function(e));
+            }
+        }
+
+        signatures
+    }
+
+    /// Adds a type, after those added before.
+    pub(crate) fn add_type(&mut self, entry: &TypeSectionEntry) {
+        let func_type = entry.func_type();
+        let arity = (
+            func_type.params().iter().map(|p| p.len()).sum(),
+            func_type
+                .results()
+                .iter()
+                .map(|r| r.value_types().len())
+                .sum(),
+        );
+
+        self.types.push((entry.idx().cloned(), arity));
+    }
+
+    /// Adds an imported function, after the functions added before.
+    pub(crate) fn add_import(&mut self, entry: &ImportSectionEntry) {
+        let ImportDesc::Func(f) = entry.desc();
+        let arity = self.type_use_arity(f.type_use());
+
+        self.funcs.push((f.idx().cloned(), arity));
+    }
+
+    /// Adds a function, after those added before.
+    pub(crate) fn add_function(&mut self, entry: &FunctionSectionEntry) {
+        let arity = self.type_use_arity(entry.type_use());
+
+        self.funcs.push((entry.idx().cloned(), arity));
+    }
+
+    fn get(&self, idx: &Index) -> Option<(usize, usize)> {
+        position(idx, self.funcs.iter().map(|(i, _)| i.as_ref()))
+            .and_then(|i| self.funcs.get(i))
+            .map(|(_, arity)| *arity)
+    }
+
+    /// Returns the number of parameters and results of `type_use`, with
+    /// those of the type it refers to if it declares none.
+    fn type_use_arity(&self, type_use: &TypeUse) -> (usize, usize) {
+        let inline = (
+            type_use.params().iter().map(|p| p.len()).sum(),
+            type_use.results().iter().map(|r| r.value_types().len()).sum(),
+        );
+
+        if inline != (0, 0) {
+            return inline;
+        }
+
+        type_use
+            .type_def()
+            .and_then(|t| {
+                position(&t.idx, self.types.iter().map(|(i, _)| i.as_ref()))
+            })
+            .and_then(|i| self.types.get(i))
+            .map_or(inline, |(_, arity)| *arity)
+    }
+}
+
+/// Resolves `idx` in an index space whose entries have the given names.
+fn position<'a>(
+    idx: &Index,
+    mut names: impl Iterator<Item = Option<&'a Index>>,
+) -> Option<usize> {
+    match idx {
+        | Index::Numeric(n) => {
+            let (val, base) = n.val();
+            let src = n.src().replace('_', "");
+            let (digits, base) = match val {
+                | Some(val) => (val.to_owned(), base.unwrap_or(10)),
+                | None => match src.strip_prefix("0x") {
+                    | Some(hex) => (hex.to_owned(), 16),
+                    | None => (src, 10),
+                },
+            };
+
+            usize::from_str_radix(&digits, base).ok()
+        },
+        | Index::Symbolic(_) => names.position(|name| name == Some(idx)),
+    }
+}
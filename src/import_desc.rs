@@ -1,6 +1,9 @@
-use wast::parser::{Parse, Parser, Result};
+use wast::parser::{Parser, Result};
 
-use crate::{Atom, Expr, Index, SExpr, TypeUse};
+use crate::{
+    state::{parse_without_state, ParseIn, State},
+    Atom, Expr, Index, SExpr, TypeUse,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ImportDesc {
@@ -14,19 +17,19 @@ impl SExpr for ImportDesc {
         }
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         match self {
             Self::Func(d) => d.cdr(),
         }
     }
 }
 
-impl Parse<'_> for ImportDesc {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl ParseIn for ImportDesc {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
         let mut l = parser.lookahead1();
 
         if l.peek::<wast::kw::func>() {
-            Ok(Self::Func(parser.parse::<ImportDescFunc>()?))
+            Ok(Self::Func(ImportDescFunc::parse_in(parser, state)?))
         } else {
             Err(l.error())
         }
@@ -44,6 +47,14 @@ impl ImportDescFunc {
     pub fn new(idx: Option<Index>, type_use: TypeUse) -> Self {
         Self { idx, type_use }
     }
+
+    pub fn idx(&self) -> Option<&Index> {
+        self.idx.as_ref()
+    }
+
+    pub fn type_use(&self) -> &TypeUse {
+        &self.type_use
+    }
 }
 
 impl SExpr for ImportDescFunc {
@@ -51,7 +62,7 @@ impl SExpr for ImportDescFunc {
         "func".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         let mut v = Vec::new();
 
         if let Some(ref idx) = self.idx {
@@ -64,13 +75,15 @@ impl SExpr for ImportDescFunc {
     }
 }
 
-impl Parse<'_> for ImportDescFunc {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl ParseIn for ImportDescFunc {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
         parser.parse::<wast::kw::func>()?;
 
         let idx = parser.parse::<Option<Index>>()?;
-        let type_use = parser.parse::<TypeUse>()?;
+        let type_use = TypeUse::parse_in(parser, state)?;
 
         Ok(Self { idx, type_use })
     }
 }
+
+parse_without_state!(ImportDesc, ImportDescFunc);
@@ -2,7 +2,10 @@ use std::fmt;
 
 use wast::parser::{Cursor, Parse, Parser, Peek, Result};
 
-use crate::{AsAtoms, Atom, Integer, Sign};
+use crate::{
+    canonical::Canonicalize, query::Indexed, span::ignore_spans, AsAtoms,
+    Atom, Integer, Sign, Spanned,
+};
 
 pub fn symbolic<S: AsRef<str>>(s: S) -> Index {
     Index::Symbolic(SymbolicIndex::new(s.as_ref().to_owned()))
@@ -18,7 +21,7 @@ impl fmt::Display for Index {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Numeric(i) => write!(f, "{}", i.src()),
-            Self::Symbolic(i) => write!(f, "{}", i.to_string()),
+            Self::Symbolic(i) => write!(f, "{}", i),
         }
     }
 }
@@ -44,6 +47,29 @@ impl AsAtoms for Index {
     }
 }
 
+impl Spanned for Index {
+    fn span(&self) -> Option<wast::Span> {
+        match self {
+            | Self::Numeric(i) => i.span(),
+            | Self::Symbolic(i) => i.span(),
+        }
+    }
+}
+
+impl Indexed for Index {
+    fn indexes<'a>(&'a self, v: &mut Vec<&'a Index>) {
+        v.push(self);
+    }
+}
+
+impl Canonicalize for Index {
+    fn canonicalize(&mut self) {
+        if let Self::Numeric(i) = self {
+            i.i.canonicalize();
+        }
+    }
+}
+
 impl Peek for Index {
     fn peek(cursor: Cursor<'_>) -> bool {
         cursor.id().is_some() || cursor.integer().is_some()
@@ -74,6 +100,18 @@ impl IntoIterator for Indexes {
     }
 }
 
+impl Indexed for Indexes {
+    fn indexes<'a>(&'a self, v: &mut Vec<&'a Index>) {
+        self.idxs.indexes(v);
+    }
+}
+
+impl Canonicalize for Indexes {
+    fn canonicalize(&mut self) {
+        self.idxs.canonicalize();
+    }
+}
+
 impl AsAtoms for Indexes {
     fn as_atoms(&self) -> Vec<Atom> {
         self.idxs.iter().map(|i| Atom::new(i.to_string())).collect()
@@ -92,12 +130,14 @@ impl Parse<'_> for Indexes {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone)]
 pub struct NumericIndex {
     i:    Integer,
     span: Option<wast::Span>,
 }
 
+ignore_spans!(NumericIndex { i; span }, Hash);
+
 impl NumericIndex {
     pub fn new(i: Integer) -> Self {
         Self { i, span: None }
@@ -124,6 +164,12 @@ impl NumericIndex {
     }
 }
 
+impl Spanned for NumericIndex {
+    fn span(&self) -> Option<wast::Span> {
+        self.span
+    }
+}
+
 impl AsAtoms for NumericIndex {
     fn as_atoms(&self) -> Vec<Atom> {
         vec![Atom::new(self.i.to_string())]
@@ -174,6 +220,12 @@ impl SymbolicIndex {
     }
 }
 
+impl Spanned for SymbolicIndex {
+    fn span(&self) -> Option<wast::Span> {
+        self.span
+    }
+}
+
 impl std::hash::Hash for SymbolicIndex {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.name.hash(state);
@@ -1,17 +1,26 @@
 use std::fmt;
 
-use wast::parser::{Cursor, Parse, Parser, Peek, Result};
+use wast::parser::{Cursor, Parser, Peek, Result};
 
-use crate::{AsAtoms, Atom};
+use crate::{
+    canonical::Canonicalize,
+    error::ErrorKind,
+    span::ignore_spans,
+    state::{parse_without_state, ParseIn, State},
+    AsAtoms, Atom, Spanned,
+};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone)]
 pub struct Integer {
     pub(crate) sign: Option<Sign>,
     pub(crate) src:  String,
     pub(crate) val:  Option<String>,
     pub(crate) hex:  Option<bool>,
+    pub(crate) span: Option<wast::Span>,
 }
 
+ignore_spans!(Integer { sign, src, val, hex; span }, Hash);
+
 impl Integer {
     pub fn new(src: String) -> Self {
         Self {
@@ -19,6 +28,7 @@ impl Integer {
             src,
             val: None,
             hex: None,
+            span: None,
         }
     }
 
@@ -35,13 +45,44 @@ impl Integer {
     /// Returns the value string that can be parsed for this integer, as well as
     /// the base that it should be parsed in
     pub fn val(&self) -> (Option<&String>, Option<u32>) {
-        let hex = if let Some(h) = self.hex {
-            Some(if h { 16 } else { 10 })
+        let hex = self.hex.map(|h| if h { 16 } else { 10 });
+
+        (self.val.as_ref(), hex)
+    }
+}
+
+/// Drops `+` signs, underscores and leading zeros, and spells hexadecimal
+/// digits in lowercase. The base of the integer is kept.
+impl Canonicalize for Integer {
+    fn canonicalize(&mut self) {
+        let (val, hex) = match (&self.val, self.hex) {
+            | (Some(val), Some(hex)) => (val, hex),
+            | _ => return,
+        };
+        let digits = val.trim_start_matches('-').trim_start_matches('0');
+        let digits = if digits.is_empty() {
+            "0".to_owned()
         } else {
-            None
+            digits.to_ascii_lowercase()
+        };
+        let sign = match self.sign {
+            | Some(Sign::Neg) => "-",
+            | _ => "",
         };
+        let prefix = if hex { "0x" } else { "" };
 
-        (self.val.as_ref(), hex)
+        if sign.is_empty() {
+            self.sign = None;
+        }
+
+        self.src = format!("{}{}{}", sign, prefix, digits);
+        self.val = Some(format!("{}{}", sign, digits));
+    }
+}
+
+impl Spanned for Integer {
+    fn span(&self) -> Option<wast::Span> {
+        self.span
     }
 }
 
@@ -57,15 +98,17 @@ impl fmt::Display for Integer {
     }
 }
 
-impl Parse<'_> for Integer {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl ParseIn for Integer {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let span = Some(parser.cur_span());
+
         parser.step(|cursor| match cursor.integer() {
             Some((s, cur)) => {
                 let src = s.src().to_owned();
                 let mut sign = None;
                 let (val_ref, base) = s.val();
                 let val = Some(val_ref.to_owned());
-                let hex = Some(if base == 16 { true } else { false });
+                let hex = Some(base == 16);
 
                 if let Some(si) = s.sign() {
                     match si {
@@ -80,15 +123,22 @@ impl Parse<'_> for Integer {
                         src,
                         val,
                         hex,
+                        span,
                     },
                     cur,
                 ))
             },
-            None => Err(parser.error("could not parse integer")),
+            None => Err(state.raise(
+                ErrorKind::InvalidInteger,
+                parser.cur_span(),
+                &[],
+            )),
         })
     }
 }
 
+parse_without_state!(Integer);
+
 impl Peek for Integer {
     fn peek(cursor: Cursor<'_>) -> bool {
         cursor.integer().is_some()
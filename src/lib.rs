@@ -1,129 +1,112 @@
+pub use annotation::{Annotation, AnnotationItem, Annotations};
+pub use canonical::format;
+pub use comment::{Comment, CommentKind, Comments, InnerComment};
+pub use custom::{Custom, CustomInstruction, KeywordError};
+pub use cst::{
+    SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, SyntaxTree, View,
+};
 pub use document::Document;
+pub use error::{Error, ErrorKind};
 pub use export::{Export, InlineExport};
+pub use float::Float;
+pub use fold::{
+    fold_exprs, unfold_exprs, FoldingPolicy, ReadableFolding, Shape,
+};
 pub use expression::{
     fold, global_get, i32_const, i64_const, local_get, AsAtoms, Block, Br,
-    BrIf, BrTable, Call, Drop, Else, Expression, GlobalGet, GlobalSet, I32Add,
-    I32Const, I32Eq, I32Eqz, I32GtU, I32Load, I32LtS, I32LtU, I32Mul, I32Ne,
-    I32RemU, I32ShrU, I32Sub, I64Const, If, Instruction, Local, LocalGet,
-    LocalSet, LocalTee, Loop, MemoryGrow, Return, Then,
+    BrIf, BrTable, Call, Drop, Else, End, Expression, F32Const, F64Const,
+    GlobalGet, GlobalSet, I32Add, I32Const, I32Eq, I32Eqz, I32GtU, I32Load,
+    I32LtS, I32LtU, I32Mul, I32Ne, I32RemU, I32ShrU, I32Sub, I64Const, If,
+    Instruction, Local, LocalGet, LocalSet, LocalTee, Loop, MemoryGrow, Return,
+    Then,
 };
 pub use import_desc::{ImportDesc, ImportDescFunc};
 pub use index::{symbolic, Index, Indexes, NumericIndex, SymbolicIndex};
 pub use integer::{Integer, Sign};
-pub use module::Module;
+pub use module::{Module, ModuleKind};
+pub use module_writer::ModuleWriter;
 pub use named_value_type::NamedValueType;
-pub use param::Param;
+pub use param::{Param, ParamKind};
+pub use print::{Print, PrintOptions};
+pub use query::Node;
+pub use recover::Malformed;
 pub use result::Result;
+pub use rewrite::{text_edits, TextEdit};
+pub use script::{
+    Action, AssertExhaustion, AssertModule, AssertReturn, AssertTrap, Command,
+    Get, Invoke, NanPattern, Register, ResultPattern, Script, TrapSubject,
+};
+pub use span::Spanned;
 pub use section::{
-    DataSection, DataSectionEntry, DataString, FunctionSection,
-    FunctionSectionEntry, GlobalSection, GlobalSectionEntry, ImportSection,
-    ImportSectionEntry, MemorySection, MemorySectionEntry, Offset, Section,
-    TypeSection, TypeSectionEntry,
+    CustomPlace, CustomPlaceAnchor, CustomSection, DataSection,
+    DataSectionEntry, DataString, FunctionSection, FunctionSectionEntry,
+    GlobalSection, GlobalSectionEntry, ImportSection, ImportSectionEntry,
+    MemoryKind, MemorySection, MemorySectionEntry, Offset, Section,
+    StartSection, TypeSection, TypeSectionEntry,
 };
-pub use type_use::TypeUse;
+pub use state::ParseOptions;
+pub use token_tree::TokenTree;
+pub use type_use::{Type, TypeUse};
 pub use types::{
     FuncType, GlobalType, GlobalTypeMut, Limits, MemType, ValueType,
 };
+pub use unknown::Unknown;
 
+mod annotation;
+mod canonical;
+mod comment;
+mod cst;
+mod custom;
 mod document;
+mod error;
 mod export;
 mod expression;
+mod float;
+mod fold;
 mod import_desc;
 mod index;
 mod integer;
 mod module;
+mod module_writer;
 mod named_value_type;
 mod param;
+mod print;
+mod query;
+mod recover;
 mod result;
+mod rewrite;
+mod script;
 mod section;
+mod sexpr;
+mod span;
+mod state;
+mod token_tree;
 mod type_use;
 mod types;
+mod unknown;
 
-use std::{fmt, io};
+use std::{borrow::Cow, fmt};
 
 pub(crate) use expression::ExpressionParser;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct ToWatParams {
-    indent_size:  usize,
-    indent_level: usize,
-}
-
-impl ToWatParams {
-    fn indent(&self) -> usize {
-        self.indent_size * self.indent_level
-    }
-}
-
-trait ToWat {
-    fn write_wat<W: io::Write>(
-        &self,
-        w: &mut W,
-        p: &ToWatParams,
-    ) -> io::Result<()>;
-
-    fn to_wat(&self, p: &ToWatParams) -> String {
-        let mut buf = Vec::new();
-
-        self.write_wat(&mut buf, p).unwrap();
-
-        String::from_utf8_lossy(&buf).to_string()
-    }
-}
-
-pub enum Expr {
+/// A node of the tree that is printed. Nodes borrow from the AST they
+/// describe, so building them does not copy subtrees.
+///
+/// Expressions can also be built by hand with [`Expr::atom`] and
+/// [`Expr::list`], or parsed from any text with [`Expr::parse_all`], to print
+/// constructs that the typed AST does not cover.
+pub enum Expr<'a> {
     Atom(Atom),
-    SExpr(Box<dyn SExpr>),
+    SExpr(Box<dyn SExpr + 'a>),
+    Commented(Cow<'a, Comments>, Box<Expr<'a>>),
 }
 
-impl ToWat for Expr {
-    fn write_wat<W: io::Write>(
-        &self,
-        w: &mut W,
-        p: &ToWatParams,
-    ) -> io::Result<()> {
+impl Expr<'_> {
+    fn is_atom(&self) -> bool {
         match self {
-            Self::Atom(a) => {
-                write!(w, "{}{}", " ".repeat(p.indent()), a.to_string())
-            },
-            Self::SExpr(se) => {
-                let open = format!("{}({}", " ".repeat(p.indent()), se.car());
-
-                if se.cdr().len() == 0 {
-                    return write!(w, "{})", &open);
-                }
-
-                let cdr = se
-                    .cdr()
-                    .iter()
-                    .map(|expr| {
-                        expr.to_wat(&ToWatParams {
-                            indent_size:  2,
-                            indent_level: 0,
-                        })
-                    })
-                    .collect::<Vec<String>>()
-                    .join(" ");
-
-                if format!("{} {})", open, cdr).len() <= 80 {
-                    return write!(w, "{} {})", open, cdr);
-                }
-
-                writeln!(w, "{}", open)?;
-
-                for expr in se.cdr() {
-                    expr.write_wat(
-                        w,
-                        &ToWatParams {
-                            indent_size:  p.indent_size,
-                            indent_level: p.indent_level + 1,
-                        },
-                    )?;
-                    write!(w, "\n")?;
-                }
-
-                write!(w, "{})", " ".repeat(p.indent()))
-            },
+            | Self::Atom(_) => true,
+            | Self::SExpr(_) => false,
+            | Self::Commented(_, expr) => expr.is_atom(),
         }
     }
 }
@@ -146,5 +129,38 @@ impl fmt::Display for Atom {
 pub trait SExpr {
     fn car(&self) -> String;
 
-    fn cdr(&self) -> Vec<Expr>;
+    fn cdr(&self) -> Vec<Expr<'_>>;
+
+    /// Returns the children as laid out with `options`. Nodes whose layout
+    /// depends on the options, such as function bodies under a
+    /// [`FoldingPolicy`], override this.
+    fn cdr_with(&self, options: &PrintOptions) -> Vec<Expr<'_>> {
+        let _ = options;
+
+        self.cdr()
+    }
+
+    /// Returns `true` if the children are module fields, which are separated
+    /// by [`PrintOptions::blank_lines`] blank lines when printed one per line.
+    fn has_fields(&self) -> bool {
+        false
+    }
+}
+
+impl<T: SExpr + ?Sized> SExpr for &T {
+    fn car(&self) -> String {
+        (**self).car()
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        (**self).cdr()
+    }
+
+    fn cdr_with(&self, options: &PrintOptions) -> Vec<Expr<'_>> {
+        (**self).cdr_with(options)
+    }
+
+    fn has_fields(&self) -> bool {
+        (**self).has_fields()
+    }
 }
@@ -1,29 +1,293 @@
-use wast::parser::{Parse, Parser, Result};
+use std::rc::Rc;
 
-use crate::{Expr, SExpr, Section};
+use wast::{
+    parser::{Parser, Result},
+    Span,
+};
 
+use crate::{
+    annotation::escape,
+    canonical::{self, Canonicalize},
+    error::{self, ErrorKind},
+    fold::{self, Signatures},
+    print::Printable,
+    recover,
+    state::{parse_without_state, ParseIn, State},
+    Atom, Comments, Error, Expr, Expression, PrintOptions, ReadableFolding,
+    SExpr, Section, SymbolicIndex,
+};
+
+/// https://webassembly.github.io/spec/core/text/modules.html#text-module
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Module {
-    sections: Vec<Section>,
+    idx:      Option<SymbolicIndex>,
+    kind:     ModuleKind,
+    comments: Comments,
 }
 
 impl Module {
-    pub fn with_sections(sections: Vec<Section>) -> Self {
-        Self { sections }
+    /// Returns an error of kind [`MultipleStart`] if `kind` has more than one
+    /// start section.
+    ///
+    /// [`MultipleStart`]: ErrorKind::MultipleStart
+    pub fn new(
+        idx: Option<SymbolicIndex>,
+        kind: ModuleKind,
+    ) -> std::result::Result<Self, Error> {
+        if let ModuleKind::Text(ref sections) = kind {
+            if start_count(sections) > 1 {
+                return Err(multiple_start());
+            }
+        }
+
+        Ok(Self::new_unchecked(idx, kind))
+    }
+
+    /// Returns an error of kind [`MultipleStart`] if `sections` has more
+    /// than one start section.
+    ///
+    /// [`MultipleStart`]: ErrorKind::MultipleStart
+    pub fn with_sections(
+        sections: Vec<Section>,
+    ) -> std::result::Result<Self, Error> {
+        Self::new(None, ModuleKind::Text(sections))
+    }
+
+    /// Creates a `(module binary "...")` module from its encoded bytes.
+    pub fn binary(data: Vec<Vec<u8>>) -> Self {
+        Self::new_unchecked(None, ModuleKind::Binary(data))
+    }
+
+    /// Creates a `(module quote "...")` module from its quoted source text.
+    pub fn quote(data: Vec<Vec<u8>>) -> Self {
+        Self::new_unchecked(None, ModuleKind::Quote(data))
+    }
+
+    /// Creates a module whose start sections were already checked.
+    pub(crate) fn new_unchecked(
+        idx: Option<SymbolicIndex>,
+        kind: ModuleKind,
+    ) -> Self {
+        Self {
+            idx,
+            kind,
+            comments: Comments::default(),
+        }
+    }
+
+    pub fn with_idx(mut self, idx: SymbolicIndex) -> Self {
+        self.idx = Some(idx);
+        self
+    }
+
+    pub fn idx(&self) -> Option<&SymbolicIndex> {
+        self.idx.as_ref()
+    }
+
+    pub fn kind(&self) -> &ModuleKind {
+        &self.kind
+    }
+
+    /// Returns the fields of a text module, or `None` for a `binary` or
+    /// `quote` module.
+    pub fn sections(&self) -> Option<&[Section]> {
+        match self.kind {
+            | ModuleKind::Text(ref sections) => Some(sections),
+            | _ => None,
+        }
+    }
+
+    /// Inserts `section` at `index` among the fields of a text module.
+    /// Returns an error of kind [`MultipleStart`] if it is a start section
+    /// and the module already has one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the module is not a text module, or if `index` is greater
+    /// than its number of fields.
+    ///
+    /// [`MultipleStart`]: ErrorKind::MultipleStart
+    pub fn insert_section(
+        &mut self,
+        index: usize,
+        section: Section,
+    ) -> std::result::Result<(), Error> {
+        let sections = self.text_sections_mut();
+
+        if let Section::Start(_) = section {
+            if start_count(sections) > 0 {
+                return Err(multiple_start());
+            }
+        }
+
+        sections.insert(index, section);
+
+        Ok(())
+    }
+
+    /// Replaces the field at `index` of a text module with `section` and
+    /// returns the former one. Returns an error of kind [`MultipleStart`]
+    /// if `section` is a start section and another field is one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the module is not a text module, or if `index` is out of
+    /// bounds.
+    ///
+    /// [`MultipleStart`]: ErrorKind::MultipleStart
+    pub fn replace_section(
+        &mut self,
+        index: usize,
+        section: Section,
+    ) -> std::result::Result<Section, Error> {
+        let sections = self.text_sections_mut();
+
+        if let Section::Start(_) = section {
+            let others = sections
+                .iter()
+                .enumerate()
+                .filter(|&(i, s)| i != index && matches!(s, Section::Start(_)))
+                .count();
+
+            if others > 0 {
+                return Err(multiple_start());
+            }
+        }
+
+        Ok(std::mem::replace(&mut sections[index], section))
+    }
+
+    /// Removes and returns the field at `index` of a text module.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the module is not a text module, or if `index` is out of
+    /// bounds.
+    pub fn remove_section(&mut self, index: usize) -> Section {
+        self.text_sections_mut().remove(index)
+    }
+
+    fn text_sections_mut(&mut self) -> &mut Vec<Section> {
+        match self.kind {
+            | ModuleKind::Text(ref mut sections) => sections,
+            | _ => panic!("not a text module"),
+        }
+    }
+
+    /// Attaches comments, printed around the `(module ...)` form.
+    pub fn with_comments(mut self, comments: Comments) -> Self {
+        self.comments = comments;
+        self
+    }
+
+    pub fn comments(&self) -> &Comments {
+        &self.comments
+    }
+
+    /// Folds the body of every function, see [`fold_exprs`]. Calls to
+    /// functions of the module are given operands according to their types.
+    ///
+    /// [`fold_exprs`]: crate::fold_exprs
+    pub fn folded(mut self) -> Self {
+        if let ModuleKind::Text(ref mut sections) = self.kind {
+            let signatures = Signatures::new(sections);
+
+            map_bodies(sections, |exprs| fold::fold_with(exprs, &signatures));
+        }
+
+        self
+    }
+
+    /// Unfolds the body of every function, see [`unfold_exprs`].
+    ///
+    /// [`unfold_exprs`]: crate::unfold_exprs
+    pub fn unfolded(mut self) -> Self {
+        if let ModuleKind::Text(ref mut sections) = self.kind {
+            map_bodies(sections, fold::unfold_exprs);
+        }
+
+        self
+    }
+
+    /// Rewrites the module in the canonical style:
+    ///
+    /// - fields are ordered by kind as the sections of the binary format:
+    ///   types, imports, functions, tables, memories, globals, exports, the
+    ///   start function, elements and data, then custom sections, other
+    ///   annotations and other [`Unknown`](crate::Unknown) fields; fields of
+    ///   the same kind keep their order;
+    /// - integers are spelled without `+`, underscores or leading zeros, with
+    ///   lowercase hexadecimal digits;
+    /// - function bodies are folded, then laid out with the default
+    ///   [`ReadableFolding`] policy.
+    ///
+    /// Only the layout of function bodies changes, not the instructions they
+    /// are made of, so the output canonicalizes to itself.
+    pub fn canonical(mut self) -> Self {
+        if let ModuleKind::Text(ref mut sections) = self.kind {
+            let signatures = Signatures::new(sections);
+            let policy = ReadableFolding::default();
+
+            sections.sort_by_key(canonical::rank);
+            sections.canonicalize();
+            map_bodies(sections, |exprs| {
+                let exprs = fold::fold_with(exprs, &signatures);
+
+                fold::relayout_exprs(&exprs, &policy, &signatures)
+            });
+        }
+
+        self
+    }
+
+    /// Returns the module with its comments, as printed in a document.
+    pub(crate) fn expr(&self) -> Expr<'_> {
+        self.comments.wrap(Expr::SExpr(Box::new(self)))
+    }
+
+    /// Returns the module's fields, as printed for a module without the
+    /// `(module ...)` wrapper.
+    pub(crate) fn field_exprs(&self, options: &PrintOptions) -> Vec<Expr<'_>> {
+        self.kind.exprs_with(options)
     }
 }
 
-impl Parse<'_> for Module {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
-        parser.parse::<wast::kw::module>()?;
+fn multiple_start() -> Error {
+    let message = error::message(ErrorKind::MultipleStart, &[]);
 
-        let mut sections = Vec::new();
+    Error::new(ErrorKind::MultipleStart, Span::from_offset(0), message)
+}
 
-        while !parser.is_empty() {
-            sections.push(parser.parse::<Section>()?)
+fn start_count(sections: &[Section]) -> usize {
+    sections
+        .iter()
+        .filter(|s| matches!(s, Section::Start(_)))
+        .count()
+}
+
+fn map_bodies(
+    sections: &mut [Section],
+    f: impl Fn(Vec<Expression>) -> Vec<Expression>,
+) {
+    for section in sections {
+        if let Section::Function(functions) = section {
+            for entry in functions.entries_mut() {
+                let exprs = std::mem::take(entry.exprs_mut());
+
+                *entry.exprs_mut() = f(exprs);
+            }
         }
+    }
+}
+
+impl ParseIn for Module {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        parser.parse::<wast::kw::module>()?;
+
+        let idx = parser.parse::<Option<SymbolicIndex>>()?;
+        let kind = ModuleKind::parse_in(parser, state)?;
 
-        Ok(Self { sections })
+        Ok(Self::new_unchecked(idx, kind))
     }
 }
 
@@ -32,7 +296,142 @@ impl SExpr for Module {
         "module".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
-        self.sections.iter().flat_map(|s| s.exprs()).collect()
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        let mut v = Vec::new();
+
+        if let Some(ref idx) = self.idx {
+            v.push(Expr::Atom(Atom::new(idx.to_string())));
+        }
+
+        v.append(&mut self.kind.exprs());
+
+        v
+    }
+
+    fn cdr_with(&self, options: &PrintOptions) -> Vec<Expr<'_>> {
+        let mut v = Vec::new();
+
+        if let Some(ref idx) = self.idx {
+            v.push(Expr::Atom(Atom::new(idx.to_string())));
+        }
+
+        v.append(&mut self.kind.exprs_with(options));
+
+        v
+    }
+
+    fn has_fields(&self) -> bool {
+        matches!(self.kind, ModuleKind::Text(_))
+    }
+}
+
+impl Printable for Module {
+    fn print_exprs(&self) -> Vec<Expr<'_>> {
+        vec![self.expr()]
+    }
+}
+
+/// The ways a module can be spelled. `binary` and `quote` modules are mostly
+/// found in spec test scripts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleKind {
+    Text(Vec<Section>),
+    Binary(Vec<Vec<u8>>),
+    Quote(Vec<Vec<u8>>),
+}
+
+impl ModuleKind {
+    fn exprs(&self) -> Vec<Expr<'_>> {
+        match self {
+            | Self::Text(sections) => {
+                sections.iter().flat_map(|s| s.exprs()).collect()
+            },
+            | Self::Binary(data) => Self::data_exprs("binary", data),
+            | Self::Quote(data) => Self::data_exprs("quote", data),
+        }
     }
+
+    /// Like [`exprs`](Self::exprs), laying out function bodies with the
+    /// signatures of the module if `options` fold them.
+    fn exprs_with(&self, options: &PrintOptions) -> Vec<Expr<'_>> {
+        match self {
+            | Self::Text(sections) if options.folding().is_some() => {
+                let signatures = Rc::new(Signatures::new(sections));
+
+                sections.iter().flat_map(|s| s.exprs_in(&signatures)).collect()
+            },
+            | _ => self.exprs(),
+        }
+    }
+
+    fn data_exprs(keyword: &str, data: &[Vec<u8>]) -> Vec<Expr<'static>> {
+        let mut v = vec![Expr::Atom(Atom::new(keyword.to_owned()))];
+
+        v.append(
+            &mut data
+                .iter()
+                .map(|d| Expr::Atom(Atom::new(format!(r#""{}""#, escape(d)))))
+                .collect(),
+        );
+
+        v
+    }
+}
+
+impl ParseIn for ModuleKind {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        if parser.peek::<wast::kw::binary>() {
+            parser.parse::<wast::kw::binary>()?;
+
+            return Ok(Self::Binary(parse_data(parser)?));
+        }
+
+        if parser.peek::<wast::kw::quote>() {
+            parser.parse::<wast::kw::quote>()?;
+
+            return Ok(Self::Quote(parse_data(parser)?));
+        }
+
+        let mut sections = Vec::new();
+        let mut has_start = false;
+
+        while !parser.is_empty() {
+            let span = parser.cur_span();
+            let section = recover::attempt(parser, state, |p| {
+                Section::parse_in(p, state)
+            });
+            let section = match section? {
+                | Ok(section) => section,
+                | Err(malformed) => Section::Error(malformed),
+            };
+
+            if let Section::Start(_) = section {
+                if has_start {
+                    return Err(state.raise(
+                        ErrorKind::MultipleStart,
+                        span,
+                        &[],
+                    ));
+                }
+
+                has_start = true;
+            }
+
+            sections.push(section);
+        }
+
+        Ok(Self::Text(sections))
+    }
+}
+
+parse_without_state!(Module, ModuleKind);
+
+fn parse_data(parser: Parser<'_>) -> Result<Vec<Vec<u8>>> {
+    let mut data = Vec::new();
+
+    while !parser.is_empty() {
+        data.push(parser.parse::<&[u8]>()?.to_vec());
+    }
+
+    Ok(data)
 }
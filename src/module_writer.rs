@@ -0,0 +1,304 @@
+use std::{io, rc::Rc};
+
+use crate::{
+    fold::Signatures,
+    print::{self, Printable},
+    CustomSection, DataSectionEntry, Expr, FunctionSectionEntry,
+    GlobalSectionEntry, ImportSectionEntry, MemorySectionEntry, PrintOptions,
+    Section, StartSection, SymbolicIndex, TypeSectionEntry,
+};
+
+/// Prints a module field by field, without holding the whole [`Module`] in
+/// memory.
+///
+/// Fields are written as soon as they are given, formatted as they would be
+/// in a printed [`Module`] whose fields do not fit on one line. When the
+/// options fold function bodies, calls are given operands according to the
+/// types of the functions written so far: a call to a function written later
+/// is left as it would be outside a module. Output is written piece by
+/// piece, so `w` should be buffered.
+///
+/// [`Module`]: crate::Module
+pub struct ModuleWriter<W: io::Write> {
+    w:       W,
+    options: PrintOptions,
+    idx:     Option<SymbolicIndex>,
+
+    /// Whether the `(module` header has been written.
+    started: bool,
+
+    /// Whether the last thing written is a field, rather than the header.
+    after_field: bool,
+
+    /// Whether a start section has been written.
+    has_start: bool,
+
+    /// The signatures of the functions written so far.
+    signatures: Rc<Signatures>,
+}
+
+impl<W: io::Write> ModuleWriter<W> {
+    /// Creates a writer for a module named `idx`. Nothing is written until
+    /// the first field or [`finish`](Self::finish).
+    pub fn new(
+        w: W,
+        idx: Option<SymbolicIndex>,
+        options: PrintOptions,
+    ) -> Self {
+        Self {
+            w,
+            options,
+            idx,
+            started:     false,
+            after_field: false,
+            has_start:   false,
+            signatures:  Rc::default(),
+        }
+    }
+
+    pub fn write_type(&mut self, entry: &TypeSectionEntry) -> io::Result<()> {
+        self.signatures_mut().add_type(entry);
+        self.write_field(entry)
+    }
+
+    pub fn write_import(
+        &mut self,
+        entry: &ImportSectionEntry,
+    ) -> io::Result<()> {
+        self.signatures_mut().add_import(entry);
+        self.write_field(entry)
+    }
+
+    pub fn write_function(
+        &mut self,
+        entry: &FunctionSectionEntry,
+    ) -> io::Result<()> {
+        self.signatures_mut().add_function(entry);
+
+        let signatures = Rc::clone(&self.signatures);
+
+        self.write_exprs(vec![entry.expr_in(&signatures)])
+    }
+
+    pub fn write_memory(
+        &mut self,
+        entry: &MemorySectionEntry,
+    ) -> io::Result<()> {
+        self.write_field(entry)
+    }
+
+    pub fn write_global(
+        &mut self,
+        entry: &GlobalSectionEntry,
+    ) -> io::Result<()> {
+        self.write_field(entry)
+    }
+
+    /// Writes the start section. A module has at most one, so a second one
+    /// is an [`InvalidInput`](io::ErrorKind::InvalidInput) error.
+    pub fn write_start(&mut self, start: &StartSection) -> io::Result<()> {
+        self.claim_start()?;
+        self.write_field(start)
+    }
+
+    pub fn write_data(&mut self, entry: &DataSectionEntry) -> io::Result<()> {
+        self.write_field(entry)
+    }
+
+    pub fn write_custom(&mut self, custom: &CustomSection) -> io::Result<()> {
+        self.write_field(custom)
+    }
+
+    /// Writes every field of `section`. A second start section is an
+    /// [`InvalidInput`](io::ErrorKind::InvalidInput) error.
+    pub fn write_section(&mut self, section: &Section) -> io::Result<()> {
+        match section {
+            | Section::Type(types) => {
+                for entry in types.entries() {
+                    self.signatures_mut().add_type(entry);
+                }
+            },
+            | Section::Import(imports) => {
+                for entry in imports.entries() {
+                    self.signatures_mut().add_import(entry);
+                }
+            },
+            | Section::Function(functions) => {
+                for entry in functions.entries() {
+                    self.signatures_mut().add_function(entry);
+                }
+            },
+            | Section::Start(_) => self.claim_start()?,
+            | _ => {},
+        }
+
+        let signatures = Rc::clone(&self.signatures);
+
+        self.write_exprs(section.exprs_in(&signatures))
+    }
+
+    /// Closes the module and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.started {
+            self.w.write_all(b"\n)")?;
+        } else {
+            match self.idx {
+                | Some(ref idx) => write!(self.w, "(module {})", idx)?,
+                | None => self.w.write_all(b"(module)")?,
+            }
+        }
+
+        if self.options.trailing_newline() {
+            self.w.write_all(b"\n")?;
+        }
+
+        self.w.flush()?;
+
+        Ok(self.w)
+    }
+
+    fn claim_start(&mut self) -> io::Result<()> {
+        if self.has_start {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "multiple start sections",
+            ));
+        }
+
+        self.has_start = true;
+
+        Ok(())
+    }
+
+    /// Returns the signatures to add to. They are only shared with the
+    /// fields being written, so this does not copy them.
+    fn signatures_mut(&mut self) -> &mut Signatures {
+        Rc::make_mut(&mut self.signatures)
+    }
+
+    fn write_field<P: Printable>(&mut self, field: &P) -> io::Result<()> {
+        self.write_exprs(field.print_exprs())
+    }
+
+    fn write_exprs(&mut self, exprs: Vec<Expr<'_>>) -> io::Result<()> {
+        let indent = self.options.indent(1);
+
+        for expr in exprs {
+            if !self.started {
+                self.w.write_all(b"(module")?;
+
+                if let Some(ref idx) = self.idx {
+                    write!(self.w, "\n{}{}", indent, idx)?;
+                }
+
+                self.started = true;
+            }
+
+            // Fields are separated by blank lines, but the identifier of the
+            // module stays right below its keyword.
+            let blank_lines = if self.after_field {
+                self.options.blank_lines()
+            } else {
+                0
+            };
+
+            write!(self.w, "{}{}", "\n".repeat(blank_lines + 1), indent)?;
+            print::write_nested(&expr, &mut self.w, &self.options, 1)?;
+
+            self.after_field = true;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn matches_module() {
+        let src = concat!(
+            "(module $m\n",
+            "  (type $t (func (param i32)))\n",
+            "  ;; The entry point.\n",
+            "  (func $f (type $t) (param $x i32) (drop (local.get $x)))\n",
+            "  (start $f)\n",
+            ")",
+        );
+        let doc = src.parse::<Document>().unwrap();
+        let module = &doc.modules()[0];
+        let options = PrintOptions::default().with_blank_lines(1);
+        let mut writer = ModuleWriter::new(
+            Vec::new(),
+            module.idx().cloned(),
+            options.clone(),
+        );
+
+        match module.kind() {
+            | ModuleKind::Text(sections) => {
+                for section in sections {
+                    writer.write_section(section).unwrap();
+                }
+            },
+            | _ => unreachable!(),
+        }
+
+        let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        assert_eq!(written, module.print_with(&options));
+
+        // Calls are folded with the signatures of the functions written so
+        // far.
+        let src = concat!(
+            "(module\n",
+            "  (type $t (func (param i32) (result i32)))\n",
+            "  (func $f (type $t) local.get 0)\n",
+            "  (func $g (result i32) i32.const 1 call $f)\n",
+            ")",
+        );
+        let doc = src.parse::<Document>().unwrap();
+        let module = &doc.modules()[0];
+        let options =
+            PrintOptions::default().with_folding(ReadableFolding::default());
+        let mut writer = ModuleWriter::new(Vec::new(), None, options.clone());
+        let sections = module.sections().unwrap();
+
+        writer.write_section(&sections[0]).unwrap();
+
+        match sections[1] {
+            | Section::Function(ref functions) => {
+                for entry in functions.entries() {
+                    writer.write_function(entry).unwrap();
+                }
+            },
+            | _ => unreachable!(),
+        }
+
+        let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+        assert!(written.contains("(call $f (i32.const 1))"), "{}", written);
+        assert_eq!(written, module.print_with(&options));
+    }
+
+    #[test]
+    fn multiple_start_sections() {
+        let start = StartSection::new(symbolic("main"));
+        let mut writer =
+            ModuleWriter::new(Vec::new(), None, PrintOptions::default());
+
+        writer.write_start(&start).unwrap();
+
+        let err = writer.write_section(&Section::Start(start)).unwrap_err();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn empty() {
+        let writer =
+            ModuleWriter::new(Vec::new(), None, PrintOptions::default());
+
+        assert_eq!(writer.finish().unwrap(), b"(module)");
+    }
+}
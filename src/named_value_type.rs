@@ -13,8 +13,8 @@ impl NamedValueType {
         Self { idx, value_type }
     }
 
-    pub fn as_exprs(&self) -> Vec<Expr> {
-        self.as_atoms().into_iter().map(|a| Expr::Atom(a)).collect()
+    pub fn as_exprs(&self) -> Vec<Expr<'_>> {
+        self.as_atoms().into_iter().map(Expr::Atom).collect()
     }
 }
 
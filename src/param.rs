@@ -1,12 +1,86 @@
-use wast::parser::{Parse, Parser, Result};
+use wast::parser::{Parser, Result};
 
-use crate::{Expr, NamedValueType, SExpr, ValueType};
+use crate::{
+    span::ignore_spans,
+    state::{parse_without_state, ParseIn, State},
+    Annotations, Expr, NamedValueType, SExpr, Spanned,
+    SymbolicIndex, ValueType,
+};
 
 /// https://webassembly.github.io/spec/core/text/types.html#text-functype
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Param {
-    Named(NamedValueType),
-    Anonymous(Vec<ValueType>),
+#[derive(Debug, Clone)]
+pub struct Param {
+    kind:        ParamKind,
+    annotations: Annotations,
+    span:        Option<wast::Span>,
+}
+
+ignore_spans!(Param { kind, annotations; span });
+
+impl Param {
+    pub fn named(n: NamedValueType) -> Self {
+        Self {
+            kind:        ParamKind::Named(n),
+            annotations: Annotations::default(),
+            span:        None,
+        }
+    }
+
+    pub fn anonymous(value_types: Vec<ValueType>) -> Self {
+        Self {
+            kind:        ParamKind::Anonymous(value_types),
+            annotations: Annotations::default(),
+            span:        None,
+        }
+    }
+
+    /// Attaches annotations such as `(@name "x")`, printed after the
+    /// parameter's identifier if it has one.
+    pub fn with_annotations(mut self, annotations: Annotations) -> Self {
+        self.annotations = annotations;
+        self
+    }
+
+    pub fn kind(&self) -> &ParamKind {
+        &self.kind
+    }
+
+    pub fn annotations(&self) -> &Annotations {
+        &self.annotations
+    }
+
+    /// Returns the number of values the parameter declaration declares.
+    pub fn len(&self) -> usize {
+        match self.kind {
+            | ParamKind::Named(_) => 1,
+            | ParamKind::Anonymous(ref value_types) => value_types.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Parses the inside of a `(param ...)` list, including the keyword.
+    pub(crate) fn parse_list(
+        parser: Parser<'_>,
+        state: &State,
+    ) -> Result<Self> {
+        let span = Some(parser.cur_span());
+
+        parser.parse::<wast::kw::param>()?;
+
+        Ok(Self {
+            span,
+            ..Self::parse_in(parser, state)?
+        })
+    }
+}
+
+impl Spanned for Param {
+    fn span(&self) -> Option<wast::Span> {
+        self.span
+    }
 }
 
 impl SExpr for Param {
@@ -14,28 +88,64 @@ impl SExpr for Param {
         "param".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
-        match self {
-            | Self::Named(n) => n.as_exprs(),
-            | Self::Anonymous(vv) => {
-                vv.iter().map(ValueType::as_expr).collect()
-            },
-        }
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        let mut v = self.kind.exprs();
+        let at = match self.kind {
+            | ParamKind::Named(_) => 1,
+            | ParamKind::Anonymous(_) => 0,
+        };
+
+        v.splice(at..at, self.annotations.exprs());
+
+        v
     }
 }
 
-impl Parse<'_> for Param {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
-        if parser.peek::<ValueType>() {
+/// Parses what follows the `param` keyword.
+impl ParseIn for Param {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        if parser.peek::<SymbolicIndex>() {
+            let idx = parser.parse::<SymbolicIndex>()?;
+            let annotations = Annotations::parse_in(parser, state)?;
+            let value_type = parser.parse::<ValueType>()?;
+
+            Ok(Self {
+                kind: ParamKind::Named(NamedValueType::new(idx, value_type)),
+                annotations,
+                span: None,
+            })
+        } else {
+            let annotations = Annotations::parse_in(parser, state)?;
             let mut v = Vec::new();
 
             while !parser.is_empty() {
                 v.push(parser.parse::<ValueType>()?);
             }
 
-            Ok(Self::Anonymous(v))
-        } else {
-            Ok(Self::Named(parser.parse::<NamedValueType>()?))
+            Ok(Self {
+                kind: ParamKind::Anonymous(v),
+                annotations,
+                span: None,
+            })
+        }
+    }
+}
+
+parse_without_state!(Param);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamKind {
+    Named(NamedValueType),
+    Anonymous(Vec<ValueType>),
+}
+
+impl ParamKind {
+    fn exprs(&self) -> Vec<Expr<'_>> {
+        match self {
+            | Self::Named(n) => n.as_exprs(),
+            | Self::Anonymous(vv) => {
+                vv.iter().map(ValueType::as_expr).collect()
+            },
         }
     }
 }
@@ -0,0 +1,575 @@
+use std::{borrow::Cow, fmt, io, sync::Arc};
+
+use crate::{CommentKind, Expr, FoldingPolicy, InnerComment, SExpr};
+
+/// Options controlling how nodes are printed.
+///
+/// The defaults match the output of `Display`: two-space indentation, lines of
+/// at most 80 columns, no blank lines between fields, no trailing newline,
+/// and function bodies printed as they are.
+#[derive(Debug, Clone)]
+pub struct PrintOptions {
+    line_width:       usize,
+    indent_width:     usize,
+    use_tabs:         bool,
+    blank_lines:      usize,
+    trailing_newline: bool,
+    folding:          Option<Arc<dyn FoldingPolicy>>,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            line_width:       80,
+            indent_width:     2,
+            use_tabs:         false,
+            blank_lines:      0,
+            trailing_newline: false,
+            folding:          None,
+        }
+    }
+}
+
+impl PrintOptions {
+    /// The options used by [`format`](crate::format): the defaults, with a
+    /// blank line between module fields and a trailing newline.
+    pub fn canonical() -> Self {
+        Self::default()
+            .with_blank_lines(1)
+            .with_trailing_newline(true)
+    }
+
+    /// Sets the number of columns an expression may take before it is broken
+    /// over several lines.
+    pub fn with_line_width(mut self, line_width: usize) -> Self {
+        self.line_width = line_width;
+        self
+    }
+
+    /// Sets the number of columns per indentation level. With tabs, this is
+    /// the width a tab counts for when measuring lines.
+    pub fn with_indent_width(mut self, indent_width: usize) -> Self {
+        self.indent_width = indent_width;
+        self
+    }
+
+    /// Indents with one tab per level instead of spaces.
+    pub fn with_tabs(mut self, use_tabs: bool) -> Self {
+        self.use_tabs = use_tabs;
+        self
+    }
+
+    /// Sets the number of blank lines between module fields, and between the
+    /// top-level forms of a document or script.
+    pub fn with_blank_lines(mut self, blank_lines: usize) -> Self {
+        self.blank_lines = blank_lines;
+        self
+    }
+
+    /// Ends printed documents and scripts with a newline.
+    pub fn with_trailing_newline(mut self, trailing_newline: bool) -> Self {
+        self.trailing_newline = trailing_newline;
+        self
+    }
+
+    /// Lays out function bodies with `policy`, deciding for each instruction
+    /// whether it is printed folded or flat.
+    pub fn with_folding<P: FoldingPolicy + 'static>(
+        mut self,
+        policy: P,
+    ) -> Self {
+        self.folding = Some(Arc::new(policy));
+        self
+    }
+
+    pub fn line_width(&self) -> usize {
+        self.line_width
+    }
+
+    pub fn indent_width(&self) -> usize {
+        self.indent_width
+    }
+
+    pub fn use_tabs(&self) -> bool {
+        self.use_tabs
+    }
+
+    pub fn blank_lines(&self) -> usize {
+        self.blank_lines
+    }
+
+    pub fn trailing_newline(&self) -> bool {
+        self.trailing_newline
+    }
+
+    pub fn folding(&self) -> Option<&dyn FoldingPolicy> {
+        self.folding.as_deref()
+    }
+
+    /// Returns the indentation at `level`.
+    pub(crate) fn indent(&self, level: usize) -> String {
+        if self.use_tabs {
+            "\t".repeat(level)
+        } else {
+            " ".repeat(self.indent_width * level)
+        }
+    }
+}
+
+/// A node that can be printed with [`PrintOptions`].
+///
+/// Output is written piece by piece, so writers such as files should be
+/// wrapped in a [`std::io::BufWriter`].
+pub trait Print {
+    /// Writes the node to `w`.
+    fn write_to<W: io::Write>(
+        &self,
+        w: &mut W,
+        options: &PrintOptions,
+    ) -> io::Result<()>;
+
+    /// Writes the node to `w`, e.g. a `String` or a `fmt::Formatter`.
+    fn fmt_to<W: fmt::Write>(
+        &self,
+        w: &mut W,
+        options: &PrintOptions,
+    ) -> fmt::Result;
+
+    fn print_with(&self, options: &PrintOptions) -> String {
+        let mut s = String::new();
+
+        // Writing to a `String` cannot fail.
+        self.fmt_to(&mut s, options).unwrap();
+
+        s
+    }
+}
+
+impl Print for Expr<'_> {
+    fn write_to<W: io::Write>(
+        &self,
+        w: &mut W,
+        options: &PrintOptions,
+    ) -> io::Result<()> {
+        write_expr(self, &mut IoOutput(w), options, 0)
+    }
+
+    fn fmt_to<W: fmt::Write>(
+        &self,
+        w: &mut W,
+        options: &PrintOptions,
+    ) -> fmt::Result {
+        write_expr(self, &mut FmtOutput(w), options, 0)
+    }
+}
+
+/// A node that is printed as a sequence of expressions, such as a module
+/// field, a section or a whole document.
+pub(crate) trait Printable {
+    fn print_exprs(&self) -> Vec<Expr<'_>>;
+
+    /// Returns the expressions as printed with `options`. Nodes that know
+    /// more than their children about the layout, such as a document whose
+    /// fields need the signatures of their module, override this.
+    fn print_exprs_with(&self, options: &PrintOptions) -> Vec<Expr<'_>> {
+        let _ = options;
+
+        self.print_exprs()
+    }
+
+    /// Returns `true` if the node is a whole file, which ends with a newline
+    /// if [`PrintOptions::trailing_newline`] is set.
+    fn is_file(&self) -> bool {
+        false
+    }
+}
+
+impl<T: Printable> Print for T {
+    fn write_to<W: io::Write>(
+        &self,
+        w: &mut W,
+        options: &PrintOptions,
+    ) -> io::Result<()> {
+        write_exprs(self, &mut IoOutput(w), options)
+    }
+
+    fn fmt_to<W: fmt::Write>(
+        &self,
+        w: &mut W,
+        options: &PrintOptions,
+    ) -> fmt::Result {
+        write_exprs(self, &mut FmtOutput(w), options)
+    }
+}
+
+/// Where printed text goes. Abstracts over `io::Write` and `fmt::Write`.
+trait Output {
+    type Error;
+
+    fn write(&mut self, s: &str) -> Result<(), Self::Error>;
+}
+
+struct IoOutput<'a, W>(&'a mut W);
+
+impl<W: io::Write> Output for IoOutput<'_, W> {
+    type Error = io::Error;
+
+    fn write(&mut self, s: &str) -> io::Result<()> {
+        self.0.write_all(s.as_bytes())
+    }
+}
+
+struct FmtOutput<'a, W>(&'a mut W);
+
+impl<W: fmt::Write> Output for FmtOutput<'_, W> {
+    type Error = fmt::Error;
+
+    fn write(&mut self, s: &str) -> fmt::Result {
+        self.0.write_str(s)
+    }
+}
+
+/// Writes the expressions of `node`, separated like module fields.
+fn write_exprs<P: Printable + ?Sized, O: Output>(
+    node: &P,
+    out: &mut O,
+    options: &PrintOptions,
+) -> Result<(), O::Error> {
+    let separator = "\n".repeat(options.blank_lines + 1);
+
+    for (i, expr) in node.print_exprs_with(options).iter().enumerate() {
+        if i > 0 {
+            out.write(&separator)?;
+        }
+
+        write_expr(expr, out, options, 0)?;
+    }
+
+    if node.is_file() && options.trailing_newline {
+        out.write("\n")?;
+    }
+
+    Ok(())
+}
+
+/// Writes `expr` to `w` as if it started at the indentation of `level`,
+/// which the caller has already written.
+pub(crate) fn write_nested<W: io::Write>(
+    expr: &Expr<'_>,
+    w: &mut W,
+    options: &PrintOptions,
+    level: usize,
+) -> io::Result<()> {
+    write_expr(expr, &mut IoOutput(w), options, level)
+}
+
+/// Lays out `expr` at `level` and writes it to `out`.
+///
+/// The expression tree is flattened into a stream of tokens, the width of
+/// every group is measured in one scan over the stream, and the stream is
+/// then written out, deciding the layout of each group from its width. Each
+/// step is linear in the size of the output.
+fn write_expr<O: Output>(
+    expr: &Expr<'_>,
+    out: &mut O,
+    options: &PrintOptions,
+    level: usize,
+) -> Result<(), O::Error> {
+    let mut tokens = Vec::new();
+
+    push_tokens(expr, &mut tokens, options);
+
+    let widths = measure(&tokens);
+
+    layout(&tokens, &widths, out, options, level)
+}
+
+/// A layout instruction.
+#[derive(Debug)]
+enum Token {
+    Text(Cow<'static, str>),
+
+    /// A line break if the enclosing group is broken, `flat` otherwise.
+    /// Breaks between the children of an S-expression are indented one level
+    /// deeper than the group, the break before the closing `)` is not.
+    Break {
+        flat:   &'static str,
+        nested: bool,
+
+        /// Whether the break separates two module fields.
+        fields: bool,
+    },
+
+    /// A line break at the indentation of the children of the enclosing
+    /// group. Forces the group to break.
+    HardBreak,
+
+    /// Forces the enclosing group to break, e.g. after a line comment.
+    BreakParent,
+
+    /// Starts a group, which is laid out either on one line or broken with
+    /// each child on its own line.
+    Begin,
+
+    End,
+}
+
+fn push_tokens(
+    expr: &Expr<'_>,
+    tokens: &mut Vec<Token>,
+    options: &PrintOptions,
+) {
+    match expr {
+        | Expr::Atom(a) => tokens.push(Token::Text(Cow::Owned(a.0.clone()))),
+        | Expr::SExpr(se) => push_list(&**se, &[], tokens, options),
+        | Expr::Commented(comments, expr) => {
+            for comment in comments.leading() {
+                tokens.push(Token::Text(Cow::Owned(comment.src().to_owned())));
+                tokens.push(match comment.kind() {
+                    | CommentKind::Line => Token::HardBreak,
+                    | CommentKind::Block => Token::Text(Cow::Borrowed(" ")),
+                });
+            }
+
+            // Only a list has children for inner comments to go between,
+            // anything else is followed by them.
+            let after = match **expr {
+                | Expr::SExpr(ref se) => {
+                    push_list(&**se, comments.inner(), tokens, options);
+
+                    &[][..]
+                },
+                | ref expr => {
+                    push_tokens(expr, tokens, options);
+
+                    comments.inner()
+                },
+            };
+            let trailing = after
+                .iter()
+                .map(InnerComment::comment)
+                .chain(comments.trailing());
+            let mut after_line_comment = false;
+
+            for comment in trailing {
+                tokens.push(if after_line_comment {
+                    Token::HardBreak
+                } else {
+                    Token::Text(Cow::Borrowed(" "))
+                });
+                tokens.push(Token::Text(Cow::Owned(comment.src().to_owned())));
+
+                after_line_comment = comment.kind() == CommentKind::Line;
+            }
+
+            // Nothing else can follow a line comment on the same line.
+            if after_line_comment {
+                tokens.push(Token::BreakParent);
+            }
+        },
+    }
+}
+
+/// Pushes the tokens of a list, with the `inner` comments between its
+/// children.
+fn push_list(
+    se: &dyn SExpr,
+    inner: &[InnerComment],
+    tokens: &mut Vec<Token>,
+    options: &PrintOptions,
+) {
+    let car = se.car();
+    let exprs = se.cdr_with(options);
+
+    if exprs.is_empty() && inner.is_empty() {
+        let s = format!("({})", car);
+
+        return tokens.push(Token::Text(Cow::Owned(s)));
+    }
+
+    tokens.push(Token::Begin);
+    tokens.push(Token::Text(Cow::Owned(format!("({}", car))));
+
+    for (i, expr) in exprs.iter().enumerate() {
+        push_inner(inner.iter().filter(|c| c.index() == i), tokens);
+
+        // A list without a head starts with its first child.
+        if i > 0 || !car.is_empty() {
+            // The identifier of a module stays right below its keyword.
+            tokens.push(Token::Break {
+                flat:   " ",
+                nested: true,
+                fields: se.has_fields() && i > 0 && !exprs[i - 1].is_atom(),
+            });
+        }
+
+        push_tokens(expr, tokens, options);
+    }
+
+    push_inner(inner.iter().filter(|c| c.index() >= exprs.len()), tokens);
+
+    tokens.push(Token::Break {
+        flat:   "",
+        nested: false,
+        fields: false,
+    });
+    tokens.push(Token::Text(Cow::Borrowed(")")));
+    tokens.push(Token::End);
+}
+
+/// Pushes the tokens of inner comments, before the break that precedes the
+/// next child or the closing `)`.
+fn push_inner<'a>(
+    comments: impl Iterator<Item = &'a InnerComment>,
+    tokens: &mut Vec<Token>,
+) {
+    for comment in comments {
+        if comment.is_own_line() {
+            tokens.push(Token::BreakParent);
+            tokens.push(Token::Break {
+                flat:   " ",
+                nested: true,
+                fields: false,
+            });
+        } else {
+            tokens.push(Token::Text(Cow::Borrowed(" ")));
+        }
+
+        let src = comment.comment().src();
+
+        tokens.push(Token::Text(Cow::Owned(src.to_owned())));
+
+        if comment.comment().kind() == CommentKind::Line {
+            tokens.push(Token::BreakParent);
+        }
+    }
+}
+
+/// Returns, for each `Begin` token, the width of its group laid out on one
+/// line, or `None` if the group cannot be laid out on one line.
+fn measure(tokens: &[Token]) -> Vec<Option<usize>> {
+    let mut widths = vec![None; tokens.len()];
+
+    // The open groups, with the index of their `Begin` token, the position at
+    // which they start, and whether they must break.
+    let mut stack: Vec<(usize, usize, bool)> = Vec::new();
+    let mut pos = 0;
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            | Token::Text(s) => {
+                pos += s.chars().count();
+
+                if s.contains('\n') {
+                    if let Some(top) = stack.last_mut() {
+                        top.2 = true;
+                    }
+                }
+            },
+            | Token::Break { flat, .. } => pos += flat.len(),
+            | Token::HardBreak | Token::BreakParent => {
+                if let Some(top) = stack.last_mut() {
+                    top.2 = true;
+                }
+            },
+            | Token::Begin => stack.push((i, pos, false)),
+            | Token::End => {
+                if let Some((begin, start, hard)) = stack.pop() {
+                    if hard {
+                        if let Some(top) = stack.last_mut() {
+                            top.2 = true;
+                        }
+                    } else {
+                        widths[begin] = Some(pos - start);
+                    }
+                }
+            },
+        }
+    }
+
+    widths
+}
+
+/// An open group while laying out.
+struct Group {
+    level: usize,
+    flat:  bool,
+}
+
+fn layout<O: Output>(
+    tokens: &[Token],
+    widths: &[Option<usize>],
+    out: &mut O,
+    options: &PrintOptions,
+    level: usize,
+) -> Result<(), O::Error> {
+    let mut stack: Vec<Group> = Vec::new();
+    let mut column = options.indent_width * level;
+
+    // The level of the children of the innermost open group.
+    let child_level = |stack: &[Group]| {
+        stack.last().map_or(level, |group| group.level + 1)
+    };
+
+    for (token, width) in tokens.iter().zip(widths) {
+        match token {
+            | Token::Text(s) => {
+                out.write(s)?;
+
+                column = match s.rfind('\n') {
+                    | Some(i) => s[i + 1..].chars().count(),
+                    | None => column + s.chars().count(),
+                };
+            },
+            | Token::Break {
+                flat,
+                nested,
+                fields,
+            } => match stack.last() {
+                | Some(group) if !group.flat => {
+                    let level = group.level + *nested as usize;
+                    let blank_lines = if *fields {
+                        options.blank_lines
+                    } else {
+                        0
+                    };
+
+                    out.write(&"\n".repeat(blank_lines + 1))?;
+                    out.write(&options.indent(level))?;
+
+                    column = options.indent_width * level;
+                },
+                | _ => {
+                    out.write(flat)?;
+
+                    column += flat.len();
+                },
+            },
+            | Token::HardBreak => {
+                let level = child_level(&stack);
+
+                out.write("\n")?;
+                out.write(&options.indent(level))?;
+
+                column = options.indent_width * level;
+            },
+            | Token::BreakParent => {},
+            | Token::Begin => {
+                let flat = stack.last().is_some_and(|group| group.flat)
+                    || width.is_some_and(|width| {
+                        column + width <= options.line_width
+                    });
+
+                stack.push(Group {
+                    level: child_level(&stack),
+                    flat,
+                });
+            },
+            | Token::End => {
+                stack.pop();
+            },
+        }
+    }
+
+    Ok(())
+}
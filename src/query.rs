@@ -0,0 +1,348 @@
+use std::ops::Range;
+
+use wast::Span;
+
+use crate::{
+    recover,
+    span::offset_in,
+    Annotations, CustomSection, DataSectionEntry, Document, Expression, Float,
+    FunctionSectionEntry, GlobalSectionEntry, ImportDesc, ImportSectionEntry,
+    Index, Instruction, Integer, MemorySectionEntry, Module, ModuleKind,
+    Section, Spanned, StartSection, SyntaxElement, SyntaxKind, SyntaxNode,
+    SyntaxToken, SyntaxTree, TypeSectionEntry, TypeUse, ValueType,
+};
+
+/// A node found by a position query, see [`Document::path_at`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Node<'a> {
+    Module(&'a Module),
+    Type(&'a TypeSectionEntry),
+    Import(&'a ImportSectionEntry),
+    Function(&'a FunctionSectionEntry),
+    Memory(&'a MemorySectionEntry),
+    Global(&'a GlobalSectionEntry),
+    Start(&'a StartSection),
+    Data(&'a DataSectionEntry),
+    Custom(&'a CustomSection),
+    Instruction(&'a Instruction),
+    Index(&'a Index),
+}
+
+impl Spanned for Node<'_> {
+    fn span(&self) -> Option<Span> {
+        match self {
+            | Self::Module(_) => None,
+            | Self::Type(n) => n.span(),
+            | Self::Import(n) => n.span(),
+            | Self::Function(n) => n.span(),
+            | Self::Memory(n) => n.span(),
+            | Self::Global(n) => n.span(),
+            | Self::Start(n) => n.span(),
+            | Self::Data(n) => n.span(),
+            | Self::Custom(n) => n.span(),
+            | Self::Instruction(n) => n.span(),
+            | Self::Index(n) => n.span(),
+        }
+    }
+}
+
+/// Collects the indexes among the immediates of an instruction.
+pub(crate) trait Indexed {
+    fn indexes<'a>(&'a self, v: &mut Vec<&'a Index>);
+}
+
+impl<T: Indexed> Indexed for Option<T> {
+    fn indexes<'a>(&'a self, v: &mut Vec<&'a Index>) {
+        if let Some(t) = self {
+            t.indexes(v);
+        }
+    }
+}
+
+impl<T: Indexed> Indexed for Vec<T> {
+    fn indexes<'a>(&'a self, v: &mut Vec<&'a Index>) {
+        self.iter().for_each(|t| t.indexes(v));
+    }
+}
+
+/// Implements [`Indexed`] for immediates that hold no index.
+macro_rules! no_indexes {
+    ($($node:ty),* $(,)?) => {
+        $(
+            impl Indexed for $node {
+                fn indexes<'a>(&'a self, _: &mut Vec<&'a Index>) {}
+            }
+        )*
+    };
+}
+
+no_indexes!(Annotations, Float, Integer, ValueType);
+
+impl Indexed for TypeUse {
+    fn indexes<'a>(&'a self, v: &mut Vec<&'a Index>) {
+        if let Some(type_def) = self.type_def() {
+            v.push(&type_def.idx);
+        }
+    }
+}
+
+/// Returns the nodes of `doc` whose source covers `offset`, see
+/// [`Document::path_at`].
+pub(crate) fn path_at<'a>(
+    doc: &'a Document,
+    src: &str,
+    offset: usize,
+) -> Vec<(Node<'a>, Range<usize>)> {
+    // A document parsed with `parse_recovering` may come from unbalanced
+    // source, which is queried as balanced for the parse.
+    let tree = match src.parse::<SyntaxTree>() {
+        | Ok(tree) => tree,
+        | Err(_) => match recover::balance(src).0.parse::<SyntaxTree>() {
+            | Ok(tree) => tree,
+            | Err(_) => return Vec::new(),
+        },
+    };
+    let mut query = Query {
+        src,
+        root: tree.root(),
+        offset,
+        path: Vec::new(),
+    };
+
+    if doc.is_implicit() {
+        for module in doc.modules() {
+            query.module(module, Some(0..src.len()));
+        }
+    } else {
+        for (module, list) in doc.modules().iter().zip(tree.root().children())
+        {
+            query.module(module, Some(list.text_range()));
+        }
+    }
+
+    query.path
+}
+
+struct Query<'a, 's> {
+    src:    &'s str,
+    root:   SyntaxNode,
+    offset: usize,
+    path:   Vec<(Node<'a>, Range<usize>)>,
+}
+
+impl<'a> Query<'a, '_> {
+    /// Adds `node` to the path if `range` covers the offset.
+    fn push(&mut self, node: Node<'a>, range: Option<Range<usize>>) -> bool {
+        match range {
+            | Some(range) if range.contains(&self.offset) => {
+                // Leaving out the `)` added to balance the source.
+                let end = range.end.min(self.src.len());
+
+                self.path.push((node, range.start..end));
+                true
+            },
+            | _ => false,
+        }
+    }
+
+    fn module(&mut self, module: &'a Module, range: Option<Range<usize>>) {
+        if !self.push(Node::Module(module), range) {
+            return;
+        }
+
+        let sections = match module.kind() {
+            | ModuleKind::Text(sections) => sections,
+            | _ => return,
+        };
+
+        for section in sections {
+            match section {
+                | Section::Type(s) => {
+                    for entry in s.entries() {
+                        if self.entry(Node::Type(entry)) {
+                            self.index(entry.idx());
+                        }
+                    }
+                },
+                | Section::Import(s) => {
+                    for entry in s.entries() {
+                        if self.entry(Node::Import(entry)) {
+                            let ImportDesc::Func(desc) = entry.desc();
+
+                            self.index(desc.idx());
+                        }
+                    }
+                },
+                | Section::Function(s) => {
+                    for entry in s.entries() {
+                        if self.entry(Node::Function(entry)) {
+                            self.index(entry.idx());
+
+                            if let Some(ty) = entry.type_use().type_def() {
+                                self.index(Some(&ty.idx));
+                            }
+
+                            self.exprs(entry.exprs());
+                        }
+                    }
+                },
+                | Section::Memory(s) => {
+                    for entry in s.entries() {
+                        if self.entry(Node::Memory(entry)) {
+                            self.index(entry.idx());
+                        }
+                    }
+                },
+                | Section::Global(s) => {
+                    for entry in s.entries() {
+                        if self.entry(Node::Global(entry)) {
+                            self.index(entry.idx());
+                            self.exprs(entry.expr().map_or(&[], |e| {
+                                std::slice::from_ref(e)
+                            }));
+                        }
+                    }
+                },
+                | Section::Start(s) => {
+                    if self.entry(Node::Start(s)) {
+                        self.index(Some(s.idx()));
+                    }
+                },
+                | Section::Data(s) => {
+                    for entry in s.entries() {
+                        if self.entry(Node::Data(entry)) {
+                            self.index(entry.idx());
+                            self.exprs(std::slice::from_ref(
+                                entry.offset().expression(),
+                            ));
+                        }
+                    }
+                },
+                | Section::Custom(s) => {
+                    self.entry(Node::Custom(s));
+                },
+                | Section::Annotation(_)
+                | Section::Unknown(_)
+                | Section::Error(_) => {},
+            }
+        }
+    }
+
+    /// Adds a module field to the path if it covers the offset.
+    fn entry(&mut self, node: Node<'a>) -> bool {
+        let range = self.range(node.span());
+
+        self.push(node, range)
+    }
+
+    fn index(&mut self, idx: Option<&'a Index>) {
+        if let Some(idx) = idx {
+            let range = self.range(idx.span());
+
+            self.push(Node::Index(idx), range);
+        }
+    }
+
+    fn exprs(&mut self, exprs: &'a [Expression]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            let range = match expr {
+                | Expression::Folded(instr) => self.range(instr.span()),
+                | Expression::Unfolded(instr) => {
+                    // The immediates of a plain instruction run up to the
+                    // next instruction.
+                    let next = exprs
+                        .get(i + 1)
+                        .and_then(|e| e.span())
+                        .map_or(usize::MAX, |span| offset_in(span, self.src));
+
+                    self.unfolded_range(instr.span(), next)
+                },
+            };
+            let instr = match expr {
+                | Expression::Folded(instr) | Expression::Unfolded(instr) => {
+                    instr
+                },
+            };
+
+            if self.push(Node::Instruction(instr), range) {
+                instr.indexes().into_iter().for_each(|i| self.index(Some(i)));
+                self.exprs(instr.exprs());
+
+                return;
+            }
+        }
+    }
+
+    /// Returns the source range of the node whose span is `span`: the whole
+    /// list when the span points at the keyword of a list, and the token it
+    /// points at otherwise.
+    fn range(&self, span: Option<Span>) -> Option<Range<usize>> {
+        let token = self.token(span?)?;
+        let list = token.parent();
+
+        if list.keyword().map(|k| k.text_range()) == Some(token.text_range()) {
+            Some(list.text_range())
+        } else {
+            Some(token.text_range())
+        }
+    }
+
+    /// Returns the source range of a plain instruction, from its keyword to
+    /// its last immediate before `next`, lists such as `(type $t)` included.
+    fn unfolded_range(
+        &self,
+        span: Option<Span>,
+        next: usize,
+    ) -> Option<Range<usize>> {
+        let token = self.token(span?)?;
+        let start = token.text_range().start;
+        let end = token
+            .parent()
+            .children_with_tokens()
+            .into_iter()
+            .skip_while(|e| start_of(e) <= start)
+            .take_while(|e| match e {
+                | SyntaxElement::Token(t) => {
+                    t.kind() != SyntaxKind::RParen && start_of(e) < next
+                },
+                | SyntaxElement::Node(n) => n.text_range().end <= next,
+            })
+            .filter_map(|e| match e {
+                | SyntaxElement::Token(t) if t.kind().is_trivia() => None,
+                | _ => Some(range_of(&e).end),
+            })
+            .last()
+            .unwrap_or(token.text_range().end);
+
+        Some(start..end)
+    }
+
+    /// Returns the token that `span` points at.
+    fn token(&self, span: Span) -> Option<SyntaxToken> {
+        let offset = offset_in(span, self.src);
+        let mut node = self.root.clone();
+
+        loop {
+            let element = node
+                .children_with_tokens()
+                .into_iter()
+                .find(|e| range_of(e).contains(&offset))?;
+
+            match element {
+                | SyntaxElement::Node(n) => node = n,
+                | SyntaxElement::Token(t) => return Some(t),
+            }
+        }
+    }
+}
+
+fn range_of(element: &SyntaxElement) -> Range<usize> {
+    match element {
+        | SyntaxElement::Node(n) => n.text_range(),
+        | SyntaxElement::Token(t) => t.text_range(),
+    }
+}
+
+fn start_of(element: &SyntaxElement) -> usize {
+    range_of(element).start
+}
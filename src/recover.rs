@@ -0,0 +1,340 @@
+use wast::{
+    lexer::{Lexer, Token},
+    parser::{Cursor, ParseBuffer, Parser, Result},
+    Span,
+};
+
+use crate::{
+    span::{ignore_spans, offset_in},
+    state::{self, State},
+    Atom, Comments, Document, Error, ErrorKind, Expr, Expression,
+    ParseOptions, Spanned,
+};
+
+/// A placeholder for source that failed to parse, see
+/// [`Document::parse_recovering`]. It is printed as the text it stands for.
+#[derive(Debug, Clone)]
+pub struct Malformed {
+    text:                String,
+    pub(crate) comments: Comments,
+    pub(crate) span:     Option<Span>,
+
+    /// Always empty, so that a malformed instruction can stand in for any
+    /// other.
+    pub(crate) exprs: Vec<Expression>,
+}
+
+ignore_spans!(Malformed { text, comments, exprs; span });
+
+impl Malformed {
+    /// Returns the source text that failed to parse.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn comments(&self) -> &Comments {
+        &self.comments
+    }
+
+    pub(crate) fn expr(&self) -> Expr<'_> {
+        self.comments.wrap(Expr::Atom(Atom::new(self.text.clone())))
+    }
+}
+
+impl Spanned for Malformed {
+    fn span(&self) -> Option<Span> {
+        self.span
+    }
+}
+
+/// Parses `src`, see [`Document::parse_recovering`].
+pub(crate) fn parse(
+    src: &str,
+    options: &ParseOptions,
+) -> (Document, Vec<Error>) {
+    let (text, mut errors) = balance(src);
+    let document = ParseBuffer::new(&text)
+        .map_err(Error::from)
+        .and_then(|buf| {
+            let state = State::recovering(&text).with_options(options);
+            let (document, state) = state::parse::<Document>(&buf, state);
+            let document = document.map_err(|err| state.error(err));
+
+            errors.extend(state.into_errors());
+            document
+        });
+    let document = document.unwrap_or_else(|err| {
+        errors.push(err);
+        Document::with_modules(Vec::new())
+    });
+
+    for err in &mut errors {
+        // Show the source as written, unless the error points at a `)` that
+        // was added to it.
+        if offset_in(err.span(), &text) < src.len() {
+            err.set_text(src);
+        } else {
+            err.set_text(&text);
+        }
+    }
+
+    errors.sort_by_key(Error::span);
+
+    (document, errors)
+}
+
+/// Returns `src` with its parentheses balanced, by blanking out unmatched
+/// `)` and closing unclosed `(` at the end, with an error for each. Text that
+/// fails to lex is blanked out too, see [`blank_bad_token`]. Offsets in the
+/// result are the same as in `src`.
+pub(crate) fn balance(src: &str) -> (String, Vec<Error>) {
+    let mut text = src.to_owned();
+    let mut errors = Vec::new();
+    let mut open = Vec::new();
+    let mut start = 0;
+    let error = |offset, message: &str| {
+        Error::new(
+            ErrorKind::UnbalancedParens,
+            Span::from_offset(offset),
+            message.to_owned(),
+        )
+    };
+
+    'lex: while start < src.len() {
+        // Where the token after the last one lexed starts.
+        let mut next = start;
+
+        for token in Lexer::new(&src[start..]) {
+            let token = match token {
+                | Ok(token) => token,
+                | Err(err) => {
+                    let at = start + offset_in(err.span(), &src[start..]);
+                    let err = Error::from(err);
+
+                    errors.push(err.with_span(Span::from_offset(at)));
+                    start = blank_bad_token(src, next, &mut text);
+
+                    continue 'lex;
+                },
+            };
+            let offset = token.src().as_ptr() as usize - src.as_ptr() as usize;
+
+            match token {
+                | Token::LParen(_) => open.push(offset),
+                | Token::RParen(_) if open.pop().is_none() => {
+                    errors.push(error(offset, "unexpected `)`"));
+                    text.replace_range(offset..offset + 1, " ");
+                },
+                | _ => {},
+            }
+
+            next = offset + token.src().len();
+        }
+
+        break;
+    }
+
+    if !open.is_empty() {
+        // On a new line, in case the input ends in a line comment.
+        text.push('\n');
+    }
+
+    for offset in open.into_iter().rev() {
+        errors.push(error(offset, "unclosed `(`"));
+        text.push(')');
+    }
+
+    (text, errors)
+}
+
+/// Blanks out in `text` the token at `start` of `src` that failed to lex,
+/// and returns the offset to lex from next. A string is replaced by `""` up
+/// to its closing quote, or to the end of the line if it has none; anything
+/// else is skipped up to the end of the line.
+fn blank_bad_token(src: &str, start: usize, text: &mut String) -> usize {
+    let line_end = src[start..].find('\n').map_or(src.len(), |i| start + i);
+    let line = &src[start..line_end];
+
+    if !line.starts_with('"') {
+        text.replace_range(start..line_end, &" ".repeat(line.len()));
+
+        return line_end;
+    }
+
+    let mut escaped = false;
+    let close = line.char_indices().skip(1).find_map(|(i, c)| {
+        let closes = c == '"' && !escaped;
+
+        escaped = c == '\\' && !escaped;
+        closes.then_some(i + 1)
+    });
+    let len = close.unwrap_or(line.len());
+    let blank = match len {
+        | 1 => " ".to_owned(),
+        | _ => format!("\"\"{}", " ".repeat(len - 2)),
+    };
+
+    text.replace_range(start..start + len, &blank);
+    start + len
+}
+
+/// Parses a node with `f`. When recovering from an error, the parser is put
+/// back where it was and the input that failed to parse is skipped: a list,
+/// or a token with the immediates after it. The error is recorded and the
+/// skipped input returned as a placeholder.
+pub(crate) fn attempt<'a, T>(
+    parser: Parser<'a>,
+    state: &State,
+    f: impl FnOnce(Parser<'a>) -> Result<T>,
+) -> Result<std::result::Result<T, Malformed>> {
+    if !state.is_recovering() {
+        return f(parser).map(Ok);
+    }
+
+    let mark = Mark::new(parser, state)?;
+    let err = match f(parser) {
+        | Ok(t) => return Ok(Ok(t)),
+        | Err(err) => err,
+    };
+
+    mark.rewind(parser, state)?;
+
+    match skip(parser, state)? {
+        | Some(malformed) => {
+            state.recovered(state.error(err));
+
+            Ok(Err(malformed))
+        },
+        // Nothing to skip, so the error cannot be recovered from.
+        | None => Err(err),
+    }
+}
+
+/// Parses an entry of a section with `f`. When recovering from an error in
+/// an entry other than the `first`, the parser is put back where it was and
+/// `None` returned, ending the section: the entry is then parsed again as the
+/// start of the next one, and the error recovered from there.
+pub(crate) fn backtrack<'a, T>(
+    parser: Parser<'a>,
+    state: &State,
+    first: bool,
+    f: impl FnOnce(Parser<'a>) -> Result<T>,
+) -> Result<Option<T>> {
+    if first || !state.is_recovering() {
+        return f(parser).map(Some);
+    }
+
+    let mark = Mark::new(parser, state)?;
+
+    match f(parser) {
+        | Ok(t) => Ok(Some(t)),
+        | Err(_) => {
+            mark.rewind(parser, state)?;
+
+            Ok(None)
+        },
+    }
+}
+
+/// A position of the parser to go back to.
+struct Mark<'a> {
+    cursor:   Cursor<'a>,
+    comments: usize,
+}
+
+impl<'a> Mark<'a> {
+    fn new(parser: Parser<'a>, state: &State) -> Result<Self> {
+        Ok(Self {
+            cursor:   parser.step(|cursor| Ok((cursor, cursor)))?,
+            comments: state.comments.checkpoint(),
+        })
+    }
+
+    fn rewind(self, parser: Parser<'a>, state: &State) -> Result<()> {
+        state.comments.rewind(self.comments);
+        parser.step(|_| Ok(((), self.cursor)))
+    }
+}
+
+/// Skips a list, or a token with the immediates after it, returning `None`
+/// at a `)` or the end of the input.
+fn skip(parser: Parser<'_>, state: &State) -> Result<Option<Malformed>> {
+    let leading = state.comments.leading(parser);
+    let span = parser.cur_span();
+    let skipped = parser.step(|cursor| {
+        let rest = skip_tokens(cursor);
+
+        Ok((rest.cur_span() != cursor.cur_span(), rest))
+    })?;
+
+    if !skipped {
+        return Ok(None);
+    }
+
+    // The text keeps the comments inside it.
+    state.comments.inside(span, parser.prev_span());
+
+    let trailing = state.comments.trailing(parser);
+    let src = state.text();
+    let start = offset_in(span, src);
+    let last = offset_in(parser.prev_span(), src);
+    let len = Lexer::new(&src[last..])
+        .next()
+        .and_then(|token| token.ok())
+        .map_or(0, |token| token.src().len());
+    let text = src[start..last + len].to_owned();
+
+    Ok(Some(Malformed {
+        text,
+        comments: Comments::new(leading, trailing),
+        span: Some(span),
+        exprs: Vec::new(),
+    }))
+}
+
+/// Returns the cursor after a list, or after a token and the non-keyword
+/// tokens that follow it.
+fn skip_tokens(mut cursor: Cursor<'_>) -> Cursor<'_> {
+    let mut depth = 0;
+    let mut first = true;
+
+    loop {
+        let next = if let Some(next) = cursor.lparen() {
+            if depth == 0 && !first {
+                return cursor;
+            }
+
+            depth += 1;
+            next
+        } else if let Some(next) = cursor.rparen() {
+            match depth {
+                | 0 => return cursor,
+                | 1 => return next,
+                | _ => depth -= 1,
+            }
+
+            next
+        } else if depth == 0 && !first && cursor.keyword().is_some() {
+            return cursor;
+        } else {
+            match advance(cursor) {
+                | Some(next) => next,
+                | None => return cursor,
+            }
+        };
+
+        cursor = next;
+        first = false;
+    }
+}
+
+/// Returns the cursor after the next token other than a parenthesis.
+fn advance(cursor: Cursor<'_>) -> Option<Cursor<'_>> {
+    None.or_else(|| cursor.keyword().map(|(_, rest)| rest))
+        .or_else(|| cursor.id().map(|(_, rest)| rest))
+        .or_else(|| cursor.reserved().map(|(_, rest)| rest))
+        .or_else(|| cursor.integer().map(|(_, rest)| rest))
+        .or_else(|| cursor.float().map(|(_, rest)| rest))
+        .or_else(|| cursor.string().map(|(_, rest)| rest))
+        .or_else(|| cursor.annotation().map(|(_, rest)| rest))
+}
@@ -11,6 +11,10 @@ impl Result {
     pub fn with_value_types(value_types: Vec<ValueType>) -> Self {
         Self { value_types }
     }
+
+    pub fn value_types(&self) -> &[ValueType] {
+        &self.value_types
+    }
 }
 
 impl SExpr for Result {
@@ -18,7 +22,7 @@ impl SExpr for Result {
         "result".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         self.value_types
             .iter()
             .map(|v| Expr::Atom(Atom::new(v.to_string())))
@@ -0,0 +1,885 @@
+use std::ops::Range;
+
+use crate::{
+    print::write_nested, span::offset_in, Annotation, Comments,
+    CustomSection, DataSectionEntry, Document, Error, Expr,
+    FunctionSectionEntry, GlobalSectionEntry, ImportSectionEntry, Malformed,
+    MemorySectionEntry, Module, ModuleKind, Print, PrintOptions, Section,
+    Spanned, StartSection, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxTree,
+    TypeSectionEntry, Unknown,
+};
+
+/// A change to a source text: the bytes in `range` are replaced by `text`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TextEdit {
+    range: Range<usize>,
+    text:  String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, text: String) -> Self {
+        Self { range, text }
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Applies `edits`, which must not overlap, to `src`.
+    pub fn apply(src: &str, edits: &[TextEdit]) -> String {
+        let mut edits = edits.iter().collect::<Vec<_>>();
+        let mut out = String::with_capacity(src.len());
+        let mut pos = 0;
+
+        edits.sort_by_key(|e| (e.range.start, e.range.end));
+
+        for edit in edits {
+            out.push_str(&src[pos..edit.range.start]);
+            out.push_str(&edit.text);
+            pos = edit.range.end;
+        }
+
+        out.push_str(&src[pos..]);
+
+        out
+    }
+}
+
+/// Returns the edits that turn `src` into a source for `edited`, a changed
+/// version of the document parsed from `src`.
+///
+/// The document parsed from `src` is compared with `edited` field by field,
+/// and only the fields that differ are rewritten, at the source their spans
+/// point at. Within a changed field, the tokens that differ from its printed
+/// form are replaced. Whitespace and comments are kept unless they sit inside
+/// a replaced node. New nodes are printed with `options` and indented like
+/// their neighbors. A document whose modules were added or removed is
+/// printed again as a whole. The edits are sorted and do not overlap.
+pub fn text_edits(
+    src: &str,
+    edited: &Document,
+    options: &PrintOptions,
+) -> Result<Vec<TextEdit>, Error> {
+    let doc = src.parse::<Document>()?;
+    let tree = src.parse::<SyntaxTree>()?;
+    let mut rewrite = Rewrite {
+        src,
+        options,
+        edits: Vec::new(),
+    };
+
+    if doc.is_implicit() != edited.is_implicit()
+        || doc.modules().len() != edited.modules().len()
+        || doc.comments() != edited.comments()
+    {
+        rewrite.edit(0..src.len(), edited.print_with(options));
+    } else if doc.is_implicit() {
+        for (old, new) in doc.modules().iter().zip(edited.modules()) {
+            rewrite.fields(&tree.root(), old, new, 0);
+        }
+    } else {
+        let lists = tree.root().children();
+
+        for ((old, new), list) in
+            doc.modules().iter().zip(edited.modules()).zip(lists)
+        {
+            rewrite.module(&list, old, new);
+        }
+    }
+
+    rewrite.edits.sort_by_key(|e| (e.range.start, e.range.end));
+
+    Ok(rewrite.edits)
+}
+
+/// The size of the table used to match the children of two lists, beyond
+/// which the changed children are rewritten as a whole.
+const MAX_LCS_CELLS: usize = 1 << 20;
+
+/// A module field of a document, the unit in which documents are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field<'a> {
+    Type(&'a TypeSectionEntry),
+    Import(&'a ImportSectionEntry),
+    Function(&'a FunctionSectionEntry),
+    Memory(&'a MemorySectionEntry),
+    Global(&'a GlobalSectionEntry),
+    Start(&'a StartSection),
+    Data(&'a DataSectionEntry),
+    Custom(&'a CustomSection),
+    Annotation(&'a Annotation),
+    Unknown(&'a Unknown),
+    Error(&'a Malformed),
+}
+
+impl Field<'_> {
+    fn comments(&self) -> Option<&Comments> {
+        match self {
+            | Self::Type(f) => Some(f.comments()),
+            | Self::Import(f) => Some(f.comments()),
+            | Self::Function(f) => Some(f.comments()),
+            | Self::Memory(f) => Some(f.comments()),
+            | Self::Global(f) => Some(f.comments()),
+            | Self::Start(f) => Some(f.comments()),
+            | Self::Data(f) => Some(f.comments()),
+            | Self::Custom(f) => Some(f.comments()),
+            | Self::Annotation(_) => None,
+            | Self::Unknown(u) => Some(&u.comments),
+            | Self::Error(m) => Some(&m.comments),
+        }
+    }
+}
+
+impl Spanned for Field<'_> {
+    fn span(&self) -> Option<wast::Span> {
+        match self {
+            | Self::Type(f) => f.span(),
+            | Self::Import(f) => f.span(),
+            | Self::Function(f) => f.span(),
+            | Self::Memory(f) => f.span(),
+            | Self::Global(f) => f.span(),
+            | Self::Start(f) => f.span(),
+            | Self::Data(f) => f.span(),
+            | Self::Custom(f) => f.span(),
+            | Self::Annotation(_) => None,
+            | Self::Unknown(u) => u.span(),
+            | Self::Error(m) => m.span(),
+        }
+    }
+}
+
+/// Returns the fields of `module`, in the order they are printed.
+fn fields(module: &Module) -> Vec<Field<'_>> {
+    let sections = match module.kind() {
+        | ModuleKind::Text(sections) => sections,
+        | _ => return Vec::new(),
+    };
+    let mut fields = Vec::new();
+
+    for section in sections {
+        match section {
+            | Section::Type(s) => {
+                fields.extend(s.entries().iter().map(Field::Type))
+            },
+            | Section::Import(s) => {
+                fields.extend(s.entries().iter().map(Field::Import))
+            },
+            | Section::Function(s) => {
+                fields.extend(s.entries().iter().map(Field::Function))
+            },
+            | Section::Memory(s) => {
+                fields.extend(s.entries().iter().map(Field::Memory))
+            },
+            | Section::Global(s) => {
+                fields.extend(s.entries().iter().map(Field::Global))
+            },
+            | Section::Start(s) => fields.push(Field::Start(s)),
+            | Section::Data(s) => {
+                fields.extend(s.entries().iter().map(Field::Data))
+            },
+            | Section::Custom(s) => fields.push(Field::Custom(s)),
+            | Section::Annotation(a) => fields.push(Field::Annotation(a)),
+            | Section::Unknown(u) => fields.push(Field::Unknown(u)),
+            | Section::Error(m) => fields.push(Field::Error(m)),
+        }
+    }
+
+    fields
+}
+
+struct Rewrite<'a> {
+    src:     &'a str,
+    options: &'a PrintOptions,
+    edits:   Vec<TextEdit>,
+}
+
+impl Rewrite<'_> {
+    /// Rewrites the module at `list` into `new`, field by field if only its
+    /// fields changed.
+    fn module(&mut self, list: &SyntaxNode, old: &Module, new: &Module) {
+        if old == new {
+            return;
+        }
+
+        let both_text = matches!(
+            (old.kind(), new.kind()),
+            (ModuleKind::Text(_), ModuleKind::Text(_)),
+        );
+
+        if both_text
+            && old.idx() == new.idx()
+            && old.comments() == new.comments()
+        {
+            return self.fields(list, old, new, 1);
+        }
+
+        let root = list.parent().map(SyntaxNode::children_with_tokens);
+        let extent = commented(
+            root.as_deref().unwrap_or_default(),
+            list.text_range(),
+            old.comments(),
+        );
+
+        self.replace(extent, &new.expr(), 0);
+    }
+
+    /// Rewrites the fields of `old`, the children of `list`, into those of
+    /// `new`, printed at `level`.
+    fn fields(
+        &mut self,
+        list: &SyntaxNode,
+        old: &Module,
+        new: &Module,
+        level: usize,
+    ) {
+        let all = list.children_with_tokens();
+        let elements = significant(list);
+        let old_fields = fields(old);
+        let new_fields = fields(new);
+        let printed = new
+            .field_exprs(self.options)
+            .iter()
+            .map(|expr| self.print(expr, level))
+            .collect::<Vec<_>>();
+        let positions = match self.positions(&elements, &old_fields) {
+            | Some(positions) => positions,
+            | None => {
+                // The fields are not where their spans point, so the whole
+                // list is printed again.
+                return match level {
+                    | 0 => {
+                        let doc = Document::implicit(new.clone());
+                        let text = doc.print_with(self.options);
+
+                        self.edit(list.text_range(), text)
+                    },
+                    | _ => self.replace(list.text_range(), &new.expr(), 0),
+                };
+            },
+        };
+        let mut extents = elements.iter().map(range).collect::<Vec<_>>();
+
+        for (field, &p) in old_fields.iter().zip(&positions) {
+            if let Some(comments) = field.comments() {
+                extents[p] = commented(&all, extents[p].clone(), comments);
+            }
+        }
+
+        // Where fields are inserted after the last one.
+        let end = match list.kind() {
+            | SyntaxKind::List => elements.len() - 1,
+            | _ => elements.len(),
+        };
+        let at = |i: usize| {
+            positions.get(i).copied().unwrap_or_else(|| {
+                positions.last().map_or(end, |p| p + 1)
+            })
+        };
+        let indent = self.options.indent(level);
+        let gap = |this: &mut Self, olds: Range<usize>, news: Range<usize>| {
+            if olds.len() == news.len() {
+                for (o, n) in olds.zip(news) {
+                    this.field(
+                        &elements[positions[o]],
+                        extents[positions[o]].clone(),
+                        (old_fields[o], new_fields[n]),
+                        &printed[n],
+                        level,
+                    );
+                }
+            } else {
+                let olds = match olds.end.checked_sub(1) {
+                    | Some(last) if !olds.is_empty() => {
+                        positions[olds.start]..positions[last] + 1
+                    },
+                    | _ => at(olds.start)..at(olds.start),
+                };
+                let news = printed[news]
+                    .iter()
+                    .map(|text| (text.as_str(), indent.clone()))
+                    .collect::<Vec<_>>();
+
+                this.gap(&all, &extents, olds, &news);
+            }
+        };
+        let mut i = 0;
+        let mut j = 0;
+
+        for (oi, nj) in common(&old_fields, &new_fields) {
+            gap(self, i..oi, j..nj);
+            i = oi + 1;
+            j = nj + 1;
+        }
+
+        gap(self, i..old_fields.len(), j..new_fields.len());
+    }
+
+    /// Returns the indexes in `elements` of the lists of `fields`, found
+    /// from their spans.
+    fn positions(
+        &self,
+        elements: &[SyntaxElement],
+        fields: &[Field<'_>],
+    ) -> Option<Vec<usize>> {
+        let mut positions = Vec::<usize>::new();
+
+        for field in fields {
+            let after = positions.last().map_or(0, |p| p + 1);
+            let p = match field.span() {
+                | Some(span) => {
+                    let offset = offset_in(span, self.src);
+
+                    elements
+                        .iter()
+                        .position(|e| range(e).contains(&offset))?
+                },
+                // Fields without a span, such as annotations, are the next
+                // list.
+                | None => {
+                    after
+                        + elements[after..]
+                            .iter()
+                            .position(|e| kind(e) == SyntaxKind::List)?
+                },
+            };
+
+            if p < after || kind(&elements[p]) != SyntaxKind::List {
+                return None;
+            }
+
+            positions.push(p);
+        }
+
+        Some(positions)
+    }
+
+    /// Rewrites the field `old` at `element` into `new`, printed as
+    /// `printed` at `level`. Only the tokens that differ are replaced, unless
+    /// the comments of the field changed.
+    fn field(
+        &mut self,
+        element: &SyntaxElement,
+        extent: Range<usize>,
+        (old, new): (Field<'_>, Field<'_>),
+        printed: &str,
+        level: usize,
+    ) {
+        let indent = self.options.indent(level);
+
+        if old.comments() == new.comments() {
+            // Parse the field where it was printed, so that its lines keep
+            // their printed indentation.
+            let padded = format!("{}{}", indent, printed);
+            let new_list = padded
+                .parse::<SyntaxTree>()
+                .ok()
+                .and_then(|tree| tree.root().children().next());
+
+            if let (SyntaxElement::Node(old_list), Some(new_list)) =
+                (element, new_list)
+            {
+                return self.node(&padded, old_list, &new_list);
+            }
+        }
+
+        let to = indent_at(self.src, extent.start);
+        let text = reindent(printed, &indent, &to);
+
+        self.edit(extent, text);
+    }
+
+    /// Diffs the children of `old` and `new`, which are both lists or both
+    /// roots. `new` is part of the `printed` text.
+    fn node(&mut self, printed: &str, old: &SyntaxNode, new: &SyntaxNode) {
+        let all = old.children_with_tokens();
+        let old_elements = significant(old);
+        let new_elements = significant(new);
+        let old_keys = old_elements.iter().map(key).collect::<Vec<_>>();
+        let new_keys = new_elements.iter().map(key).collect::<Vec<_>>();
+        let extents = old_elements.iter().map(range).collect::<Vec<_>>();
+        let gap = |this: &mut Self, olds: Range<usize>, news: Range<usize>| {
+            if olds.len() != news.len() {
+                let news = new_elements[news]
+                    .iter()
+                    .map(|n| {
+                        (&printed[range(n)], indent_at(printed, start(n)))
+                    })
+                    .collect::<Vec<_>>();
+
+                return this.gap(&all, &extents, olds, &news);
+            }
+
+            // Children changed one for one are rewritten recursively.
+            for (o, n) in olds.zip(news) {
+                match (&old_elements[o], &new_elements[n]) {
+                    | (SyntaxElement::Node(o), SyntaxElement::Node(n)) => {
+                        this.node(printed, o, n)
+                    },
+                    | (o, n) => {
+                        let text = reindent(
+                            &printed[range(n)],
+                            &indent_at(printed, start(n)),
+                            &indent_at(this.src, start(o)),
+                        );
+
+                        this.edit(range(o), text);
+                    },
+                }
+            }
+        };
+        let mut i = 0;
+        let mut j = 0;
+
+        for (oi, nj) in common(&old_keys, &new_keys) {
+            gap(self, i..oi, j..nj);
+            i = oi + 1;
+            j = nj + 1;
+        }
+
+        gap(self, i..old_elements.len(), j..new_elements.len());
+    }
+
+    /// Replaces the children `olds` of a list, whose full children are `all`
+    /// and whose significant children span `old`, with `news`. Each new
+    /// child comes with the indentation it was printed at.
+    fn gap(
+        &mut self,
+        all: &[SyntaxElement],
+        old: &[Range<usize>],
+        olds: Range<usize>,
+        news: &[(&str, String)],
+    ) {
+        if olds.is_empty() && news.is_empty() {
+            return;
+        }
+
+        let is_list = all.first().map(kind) == Some(SyntaxKind::LParen);
+        let sep = self.separator(old, olds.start, is_list);
+        let indent = match sep.rfind('\n') {
+            | Some(i) => sep[i + 1..].to_owned(),
+            | None => {
+                indent_at(self.src, old.get(olds.start).map_or(0, |r| r.start))
+            },
+        };
+        let texts = news
+            .iter()
+            .map(|(text, printed)| reindent(text, printed, &indent))
+            .collect::<Vec<_>>();
+
+        if olds.is_empty() {
+            match olds.start.checked_sub(1) {
+                | Some(prev) => {
+                    // Insert after the previous child and the comments that
+                    // follow it on its line.
+                    let at = trailing_end(all, old[prev].end);
+                    let sep = match kind_ending_at(all, at) {
+                        | Some(SyntaxKind::LineComment)
+                            if !sep.contains('\n') =>
+                        {
+                            format!("\n{}", indent)
+                        },
+                        | _ => sep,
+                    };
+                    let text = texts.iter().map(|t| format!("{}{}", sep, t));
+
+                    self.edit(at..at, text.collect());
+                },
+                | None => {
+                    // Insert before the first child.
+                    let at = old.first().map_or(self.src.len(), |r| r.start);
+                    let text = texts.iter().map(|t| format!("{}{}", t, sep));
+
+                    self.edit(at..at, text.collect());
+                },
+            }
+        } else if news.is_empty() {
+            let range = old[olds.start].start..old[olds.end - 1].end;
+
+            self.edit(self.removal(all, range), String::new());
+        } else {
+            let from = old[olds.start].start;
+            let to = old[olds.end - 1].end;
+
+            self.edit(from..to, texts.join(&sep));
+        }
+    }
+
+    /// Returns the whitespace that separates the children of a list, taken
+    /// from before `old[i]`, or before `old[i - 1]` when `old[i]` is the
+    /// closing `)`.
+    fn separator(
+        &self,
+        old: &[Range<usize>],
+        i: usize,
+        is_list: bool,
+    ) -> String {
+        let before = |i: usize| {
+            let prev = old.get(i.checked_sub(1)?)?.end;
+            let between = &self.src[prev..old.get(i)?.start];
+            let ws = &between[between.trim_end().len()..];
+
+            Some(ws).filter(|ws| !ws.is_empty())
+        };
+
+        old.get(i)
+            .filter(|r| &self.src[(*r).clone()] != ")")
+            .and_then(|_| before(i))
+            .or_else(|| before(i.checked_sub(1)?))
+            .unwrap_or(if is_list { " " } else { "\n" })
+            .to_owned()
+    }
+
+    /// Returns the source to remove along with the children at `range`: the
+    /// whitespace before them, or after them if nothing comes before.
+    fn removal(
+        &self,
+        all: &[SyntaxElement],
+        range: Range<usize>,
+    ) -> Range<usize> {
+        let from = self.src[..range.start].trim_end().len();
+        let rest = &self.src[range.end..];
+
+        if from == 0 {
+            let after = rest.len() - rest.trim_start().len();
+
+            return range.start..range.end + after;
+        }
+
+        let rest_of_line = rest.split('\n').next().unwrap_or("");
+        let line_goes_on = !rest_of_line.trim().is_empty();
+
+        match kind_ending_at(all, from) {
+            // The line break before the children ends a line comment, so
+            // if their line goes on, the spaces after them go instead.
+            | Some(SyntaxKind::LineComment) if line_goes_on => {
+                let spaces = rest.trim_start_matches([' ', '\t']);
+
+                range.start..range.end + rest.len() - spaces.len()
+            },
+            | _ => from..range.end,
+        }
+    }
+
+    /// Replaces `extent` with `expr` printed at `level`.
+    fn replace(&mut self, extent: Range<usize>, expr: &Expr<'_>, level: usize) {
+        let text = reindent(
+            &self.print(expr, level),
+            &self.options.indent(level),
+            &indent_at(self.src, extent.start),
+        );
+
+        self.edit(extent, text);
+    }
+
+    /// Returns `expr` printed at `level`, without the indentation of its
+    /// first line.
+    fn print(&self, expr: &Expr<'_>, level: usize) -> String {
+        let mut out = Vec::new();
+
+        // Writing to a `Vec` cannot fail, and the printer writes UTF-8.
+        write_nested(expr, &mut out, self.options, level).unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    fn edit(&mut self, range: Range<usize>, text: String) {
+        if self.src[range.clone()] != text {
+            self.edits.push(TextEdit::new(range, text));
+        }
+    }
+}
+
+/// Returns `node`, the source of a node among `all`, extended over the
+/// leading and trailing `comments` of the node.
+fn commented(
+    all: &[SyntaxElement],
+    node: Range<usize>,
+    comments: &Comments,
+) -> Range<usize> {
+    let is_comment = |e: &&SyntaxElement| {
+        matches!(kind(e), SyntaxKind::LineComment | SyntaxKind::BlockComment)
+    };
+    let is_whitespace = |e: &&SyntaxElement| kind(e) == SyntaxKind::Whitespace;
+    let before = all
+        .iter()
+        .rev()
+        .skip_while(|e| start(e) >= node.start)
+        .filter(|e| !is_whitespace(e))
+        .take_while(is_comment)
+        .take(comments.leading().len())
+        .last()
+        .map_or(node.start, start);
+    let after = all
+        .iter()
+        .skip_while(|e| start(e) < node.end)
+        .filter(|e| !is_whitespace(e))
+        .take_while(is_comment)
+        .take(comments.trailing().len())
+        .last()
+        .map_or(node.end, |e| range(e).end);
+
+    before..after
+}
+
+/// Returns the end of the comments that follow `at` on the same line.
+fn trailing_end(all: &[SyntaxElement], at: usize) -> usize {
+    let mut end = at;
+
+    for element in all.iter().skip_while(|e| start(e) < at) {
+        match element {
+            | SyntaxElement::Token(t) if t.kind() == SyntaxKind::Whitespace => {
+                if t.text().contains('\n') {
+                    break;
+                }
+            },
+            | SyntaxElement::Token(t) if t.kind().is_trivia() => {
+                end = t.text_range().end;
+            },
+            | _ => break,
+        }
+    }
+
+    end
+}
+
+/// Returns the kind of the child among `all` that ends at `offset`.
+fn kind_ending_at(all: &[SyntaxElement], offset: usize) -> Option<SyntaxKind> {
+    all.iter().find(|e| range(e).end == offset).map(kind)
+}
+
+/// Returns `text` with its lines after the first indented by `to` instead of
+/// `from`.
+fn reindent(text: &str, from: &str, to: &str) -> String {
+    text.split('\n')
+        .enumerate()
+        .map(|(i, line)| match line.strip_prefix(from) {
+            | Some(line) if i > 0 && !line.is_empty() => {
+                format!("{}{}", to, line)
+            },
+            | _ => line.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the children of `node` other than whitespace and comments.
+fn significant(node: &SyntaxNode) -> Vec<SyntaxElement> {
+    node.children_with_tokens()
+        .into_iter()
+        .filter(|e| !kind(e).is_trivia())
+        .collect()
+}
+
+/// Returns the text of `element` without whitespace and comments, which is
+/// equal for elements that only differ in layout.
+fn key(element: &SyntaxElement) -> String {
+    match element {
+        | SyntaxElement::Token(t) => t.text().to_owned(),
+        | SyntaxElement::Node(n) => {
+            significant(n).iter().map(key).collect::<Vec<_>>().join(" ")
+        },
+    }
+}
+
+/// Returns the pairs of indexes of a longest common subsequence of `old` and
+/// `new`. Common prefixes and suffixes are matched first.
+fn common<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+    let prefix = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let o = &old[prefix..old.len() - suffix];
+    let n = &new[prefix..new.len() - suffix];
+    let mut pairs = (0..prefix).map(|i| (i, i)).collect::<Vec<_>>();
+
+    if o.len().saturating_mul(n.len()) <= MAX_LCS_CELLS {
+        // lengths[i][j] is the length of the LCS of o[i..] and n[j..].
+        let mut lengths = vec![vec![0; n.len() + 1]; o.len() + 1];
+
+        for i in (0..o.len()).rev() {
+            for j in (0..n.len()).rev() {
+                lengths[i][j] = if o[i] == n[j] {
+                    lengths[i + 1][j + 1] + 1
+                } else {
+                    lengths[i + 1][j].max(lengths[i][j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+
+        while i < o.len() && j < n.len() {
+            if o[i] == n[j] {
+                pairs.push((prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+
+    pairs.extend(
+        (0..suffix).map(|k| (old.len() - suffix + k, new.len() - suffix + k)),
+    );
+
+    pairs
+}
+
+/// Returns the indentation of the line containing `offset`.
+fn indent_at(src: &str, offset: usize) -> String {
+    let line = &src[src[..offset].rfind('\n').map_or(0, |i| i + 1)..];
+
+    line[..line.len() - line.trim_start().len()].to_owned()
+}
+
+fn kind(element: &SyntaxElement) -> SyntaxKind {
+    match element {
+        | SyntaxElement::Node(n) => n.kind(),
+        | SyntaxElement::Token(t) => t.kind(),
+    }
+}
+
+fn range(element: &SyntaxElement) -> Range<usize> {
+    match element {
+        | SyntaxElement::Node(n) => n.text_range(),
+        | SyntaxElement::Token(t) => t.text_range(),
+    }
+}
+
+fn start(element: &SyntaxElement) -> usize {
+    range(element).start
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    const SRC: &str = concat!(
+        "(module $m\n",
+        "  ;; Entry.\n",
+        "  (func $f (export \"f\")   (result i32)\n",
+        "    i32.const 1)   ;; one\n",
+        "\n",
+        "  (func $g (param i32))\n",
+        ")\n",
+    );
+
+    fn edit(f: impl FnOnce(&mut Module)) -> String {
+        let mut doc = SRC.parse::<Document>().unwrap();
+
+        f(&mut doc.modules_mut()[0]);
+
+        let edits = text_edits(SRC, &doc, &PrintOptions::default()).unwrap();
+
+        TextEdit::apply(SRC, &edits)
+    }
+
+    /// Edits the functions of the first field of `module`.
+    fn edit_functions(
+        module: &mut Module,
+        f: impl FnOnce(&mut FunctionSection),
+    ) {
+        let mut section = module.sections().unwrap()[0].clone();
+
+        match section {
+            | Section::Function(ref mut functions) => f(functions),
+            | _ => unreachable!(),
+        }
+
+        module.replace_section(0, section).unwrap();
+    }
+
+    #[test]
+    fn rename_export() {
+        let edited = edit(|module| {
+            edit_functions(module, |functions| {
+                let f = &mut functions.entries_mut()[0];
+
+                *f = f.clone().with_inline_export(InlineExport::new(
+                    "main".to_owned(),
+                ));
+            })
+        });
+
+        assert_eq!(edited, SRC.replace(r#""f""#, r#""main""#));
+    }
+
+    #[test]
+    fn add_and_remove_fields() {
+        let import = ImportSectionEntry::new(
+            "env".to_owned(),
+            "log".to_owned(),
+            ImportDesc::Func(ImportDescFunc::new(
+                Some(symbolic("log")),
+                TypeUse::new(None, vec![], vec![]),
+            )),
+        );
+        let edited = edit(|module| {
+            let import = ImportSection::with_entries(vec![import]);
+
+            module.insert_section(0, Section::Import(import)).unwrap();
+        });
+
+        assert_eq!(
+            edited,
+            SRC.replace(
+                "$m\n",
+                "$m\n  (import \"env\" \"log\" (func $log))\n",
+            ),
+        );
+
+        let edited = edit(|module| {
+            edit_functions(module, |functions| {
+                *functions = FunctionSection::with_entries(
+                    functions.entries()[..1].to_vec(),
+                );
+            })
+        });
+
+        assert_eq!(edited, SRC.replace("\n\n  (func $g (param i32))", ""));
+    }
+
+    #[test]
+    fn keep_comments() {
+        let src = concat!(
+            ";; Header.\n",
+            "(module\n",
+            "  (func $f ;; after name\n",
+            "    (param i32))\n",
+            "  (func $g (result i32) (i32.const (; x ;) 1))\n",
+            "  (@producers (; c ;) (language \"wat\"))\n",
+            "  ;; Own line.\n",
+            ")\n",
+            ";; End.\n",
+        );
+        let mut doc = src.parse::<Document>().unwrap();
+        let options = PrintOptions::default();
+
+        assert_eq!(text_edits(src, &doc, &options).unwrap(), vec![]);
+
+        edit_functions(&mut doc.modules_mut()[0], |functions| {
+            let g = &mut functions.entries_mut()[1];
+
+            *g = g.clone().with_inline_export(InlineExport::new(
+                "g".to_owned(),
+            ));
+        });
+
+        let edits = text_edits(src, &doc, &options).unwrap();
+
+        assert_eq!(
+            TextEdit::apply(src, &edits),
+            src.replace("$g (result", "$g (export \"g\") (result"),
+        );
+    }
+}
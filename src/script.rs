@@ -0,0 +1,725 @@
+use std::{fmt, str::FromStr};
+
+use wast::parser::{Parse, ParseBuffer, Parser, Result};
+
+use crate::{
+    annotation, expression,
+    print::Printable,
+    state::{self, parse_without_state, ParseIn, State},
+    Atom, Comments, Error, Expr, Expression, ExpressionParser, InnerComment,
+    Module, ParseOptions, Print, PrintOptions, SExpr, SymbolicIndex,
+};
+
+mod kw {
+    wast::custom_keyword!(f32_const = "f32.const");
+    wast::custom_keyword!(f64_const = "f64.const");
+}
+
+/// https://github.com/WebAssembly/spec/tree/master/interpreter#scripts
+///
+/// A WebAssembly script (`.wast`), as used by the spec test suite.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    commands: Vec<Command>,
+
+    /// The comments around each command, in the order of `commands`.
+    command_comments: Vec<Comments>,
+
+    /// The comments between commands that no command claimed, such as those
+    /// at the end of the file.
+    comments: Vec<InnerComment>,
+}
+
+impl Script {
+    pub fn with_commands(commands: Vec<Command>) -> Self {
+        Self {
+            command_comments: vec![Comments::default(); commands.len()],
+            commands,
+            comments: Vec::new(),
+        }
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Adds comments on their own lines between the commands, each before
+    /// the command at its [`index`](InnerComment::index).
+    pub fn with_comments(mut self, comments: Vec<InnerComment>) -> Self {
+        self.comments = comments;
+        self
+    }
+
+    pub fn comments(&self) -> &[InnerComment] {
+        &self.comments
+    }
+
+    /// Parses `src` like [`FromStr`], with `options`.
+    pub fn parse_with(
+        src: &str,
+        options: &ParseOptions,
+    ) -> std::result::Result<Self, Error> {
+        let buf =
+            ParseBuffer::new(src).map_err(|err| Error::parse(err, src))?;
+        let state = State::new(src).with_options(options);
+        let (script, state) = state::parse::<Script>(&buf, state);
+
+        script.map_err(|err| {
+            let mut err = state.error(err);
+
+            err.set_text(src);
+            err
+        })
+    }
+}
+
+impl Printable for Script {
+    fn print_exprs(&self) -> Vec<Expr<'_>> {
+        let mut exprs: Vec<_> = self
+            .commands
+            .iter()
+            .zip(&self.command_comments)
+            .map(|(command, comments)| {
+                comments.wrap(Expr::SExpr(Box::new(command)))
+            })
+            .collect();
+
+        // From the last, so that the indexes of the others stay the same.
+        for comment in self.comments.iter().rev() {
+            let at = comment.index().min(exprs.len());
+            let src = comment.comment().src().to_owned();
+
+            exprs.insert(at, Expr::Atom(Atom::new(src)));
+        }
+
+        exprs
+    }
+
+    fn is_file(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_to(f, &PrintOptions::default())
+    }
+}
+
+/// Parses a script, keeping every annotation and comment in the input. See
+/// [`Document`](crate::Document)'s `FromStr` implementation.
+impl FromStr for Script {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Error> {
+        Self::parse_with(s, &ParseOptions::default())
+    }
+}
+
+impl ParseIn for Script {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let mut commands = Vec::new();
+        let mut command_comments = Vec::new();
+
+        while !parser.is_empty() {
+            let (command, comments) =
+                state.comments.parse_with_comments(parser, |p| {
+                    p.parens(|p| Command::parse_in(p, state))
+                })?;
+
+            commands.push(command);
+            command_comments.push(comments);
+        }
+
+        Ok(Self {
+            commands,
+            command_comments,
+            comments: state.comments.top_level(),
+        })
+    }
+}
+
+parse_without_state!(Script);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Module(Module),
+    Register(Register),
+    Action(Action),
+    AssertReturn(AssertReturn),
+    AssertTrap(AssertTrap),
+    AssertExhaustion(AssertExhaustion),
+    AssertInvalid(AssertModule),
+    AssertMalformed(AssertModule),
+    AssertUnlinkable(AssertModule),
+}
+
+impl SExpr for Command {
+    fn car(&self) -> String {
+        match self {
+            | Self::Module(m) => m.car(),
+            | Self::Register(r) => r.car(),
+            | Self::Action(a) => a.car(),
+            | Self::AssertReturn(_) => "assert_return".to_owned(),
+            | Self::AssertTrap(_) => "assert_trap".to_owned(),
+            | Self::AssertExhaustion(_) => "assert_exhaustion".to_owned(),
+            | Self::AssertInvalid(_) => "assert_invalid".to_owned(),
+            | Self::AssertMalformed(_) => "assert_malformed".to_owned(),
+            | Self::AssertUnlinkable(_) => "assert_unlinkable".to_owned(),
+        }
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        match self {
+            | Self::Module(m) => m.cdr(),
+            | Self::Register(r) => r.cdr(),
+            | Self::Action(a) => a.cdr(),
+            | Self::AssertReturn(a) => a.exprs(),
+            | Self::AssertTrap(a) => a.exprs(),
+            | Self::AssertExhaustion(a) => a.exprs(),
+            | Self::AssertInvalid(a)
+            | Self::AssertMalformed(a)
+            | Self::AssertUnlinkable(a) => a.exprs(),
+        }
+    }
+}
+
+impl ParseIn for Command {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let mut l = parser.lookahead1();
+
+        if l.peek::<wast::kw::module>() {
+            Ok(Self::Module(Module::parse_in(parser, state)?))
+        } else if l.peek::<wast::kw::register>() {
+            Ok(Self::Register(parser.parse()?))
+        } else if l.peek::<wast::kw::invoke>() || l.peek::<wast::kw::get>() {
+            Ok(Self::Action(Action::parse_in(parser, state)?))
+        } else if l.peek::<wast::kw::assert_return>() {
+            parser.parse::<wast::kw::assert_return>()?;
+            Ok(Self::AssertReturn(AssertReturn::parse_in(parser, state)?))
+        } else if l.peek::<wast::kw::assert_trap>() {
+            parser.parse::<wast::kw::assert_trap>()?;
+            Ok(Self::AssertTrap(AssertTrap::parse_in(parser, state)?))
+        } else if l.peek::<wast::kw::assert_exhaustion>() {
+            parser.parse::<wast::kw::assert_exhaustion>()?;
+            Ok(Self::AssertExhaustion(AssertExhaustion::parse_in(
+                parser, state,
+            )?))
+        } else if l.peek::<wast::kw::assert_invalid>() {
+            parser.parse::<wast::kw::assert_invalid>()?;
+            Ok(Self::AssertInvalid(AssertModule::parse_in(parser, state)?))
+        } else if l.peek::<wast::kw::assert_malformed>() {
+            parser.parse::<wast::kw::assert_malformed>()?;
+            Ok(Self::AssertMalformed(AssertModule::parse_in(parser, state)?))
+        } else if l.peek::<wast::kw::assert_unlinkable>() {
+            parser.parse::<wast::kw::assert_unlinkable>()?;
+            Ok(Self::AssertUnlinkable(AssertModule::parse_in(
+                parser, state,
+            )?))
+        } else {
+            Err(l.error())
+        }
+    }
+}
+
+/// `(register "name" $module?)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Register {
+    name:   String,
+    module: Option<SymbolicIndex>,
+}
+
+impl Register {
+    pub fn new(name: String, module: Option<SymbolicIndex>) -> Self {
+        Self { name, module }
+    }
+}
+
+impl SExpr for Register {
+    fn car(&self) -> String {
+        "register".to_owned()
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        let mut v = vec![string_expr(&self.name)];
+
+        if let Some(ref module) = self.module {
+            v.push(Expr::Atom(Atom::new(module.to_string())));
+        }
+
+        v
+    }
+}
+
+impl Parse<'_> for Register {
+    fn parse(parser: Parser<'_>) -> Result<Self> {
+        parser.parse::<wast::kw::register>()?;
+
+        let name = parser.parse::<String>()?;
+        let module = parser.parse::<Option<SymbolicIndex>>()?;
+
+        Ok(Self { name, module })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// `(invoke $module? "name" const*)`
+    Invoke(Invoke),
+
+    /// `(get $module? "name")`
+    Get(Get),
+}
+
+impl SExpr for Action {
+    fn car(&self) -> String {
+        match self {
+            | Self::Invoke(i) => i.car(),
+            | Self::Get(g) => g.car(),
+        }
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        match self {
+            | Self::Invoke(i) => i.cdr(),
+            | Self::Get(g) => g.cdr(),
+        }
+    }
+}
+
+impl ParseIn for Action {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let mut l = parser.lookahead1();
+
+        if l.peek::<wast::kw::invoke>() {
+            Ok(Self::Invoke(Invoke::parse_in(parser, state)?))
+        } else if l.peek::<wast::kw::get>() {
+            Ok(Self::Get(parser.parse()?))
+        } else {
+            Err(l.error())
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invoke {
+    module: Option<SymbolicIndex>,
+    name:   String,
+    args:   Vec<Expression>,
+}
+
+impl Invoke {
+    pub fn new(
+        module: Option<SymbolicIndex>,
+        name: String,
+        args: Vec<Expression>,
+    ) -> Self {
+        Self { module, name, args }
+    }
+}
+
+impl SExpr for Invoke {
+    fn car(&self) -> String {
+        "invoke".to_owned()
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        let mut v = Vec::new();
+
+        if let Some(ref module) = self.module {
+            v.push(Expr::Atom(Atom::new(module.to_string())));
+        }
+
+        v.push(string_expr(&self.name));
+        v.append(&mut self.args.iter().map(|e| e.expr()).collect());
+
+        v
+    }
+}
+
+impl ParseIn for Invoke {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        parser.parse::<wast::kw::invoke>()?;
+
+        let module = parser.parse::<Option<SymbolicIndex>>()?;
+        let name = parser.parse::<String>()?;
+        let args = ExpressionParser::default().parse(parser, state)?;
+
+        Ok(Self { module, name, args })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Get {
+    module: Option<SymbolicIndex>,
+    name:   String,
+}
+
+impl Get {
+    pub fn new(module: Option<SymbolicIndex>, name: String) -> Self {
+        Self { module, name }
+    }
+}
+
+impl SExpr for Get {
+    fn car(&self) -> String {
+        "get".to_owned()
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        let mut v = Vec::new();
+
+        if let Some(ref module) = self.module {
+            v.push(Expr::Atom(Atom::new(module.to_string())));
+        }
+
+        v.push(string_expr(&self.name));
+
+        v
+    }
+}
+
+impl Parse<'_> for Get {
+    fn parse(parser: Parser<'_>) -> Result<Self> {
+        parser.parse::<wast::kw::get>()?;
+
+        let module = parser.parse::<Option<SymbolicIndex>>()?;
+        let name = parser.parse::<String>()?;
+
+        Ok(Self { module, name })
+    }
+}
+
+/// `(assert_return action result*)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssertReturn {
+    action:  Action,
+    results: Vec<ResultPattern>,
+}
+
+impl AssertReturn {
+    pub fn new(action: Action, results: Vec<ResultPattern>) -> Self {
+        Self { action, results }
+    }
+
+    fn exprs(&self) -> Vec<Expr<'_>> {
+        let mut v = vec![Expr::SExpr(Box::new(&self.action))];
+
+        v.append(&mut self.results.iter().map(ResultPattern::expr).collect());
+
+        v
+    }
+}
+
+impl ParseIn for AssertReturn {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let action = parser.parens(|p| Action::parse_in(p, state))?;
+        let mut results = Vec::new();
+
+        while !parser.is_empty() {
+            results.push(ResultPattern::parse_in(parser, state)?);
+        }
+
+        Ok(Self { action, results })
+    }
+}
+
+/// An expected result in `assert_return`: either an exact constant such as
+/// `(i32.const 1)`, or a NaN pattern such as `(f32.const nan:canonical)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResultPattern {
+    Const(Box<Expression>),
+    F32Nan(NanPattern),
+    F64Nan(NanPattern),
+}
+
+impl ResultPattern {
+    fn expr(&self) -> Expr<'_> {
+        match self {
+            | Self::Const(e) => e.expr(),
+            | Self::F32Nan(n) => Expr::SExpr(Box::new(NanResult("f32", *n))),
+            | Self::F64Nan(n) => Expr::SExpr(Box::new(NanResult("f64", *n))),
+        }
+    }
+}
+
+impl ParseIn for ResultPattern {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        parser.parens(|p| {
+            if p.peek::<kw::f32_const>() && p.peek2::<NanPattern>() {
+                p.parse::<kw::f32_const>()?;
+
+                return Ok(Self::F32Nan(p.parse()?));
+            }
+
+            if p.peek::<kw::f64_const>() && p.peek2::<NanPattern>() {
+                p.parse::<kw::f64_const>()?;
+
+                return Ok(Self::F64Nan(p.parse()?));
+            }
+
+            Ok(Self::Const(Box::new(Expression::Folded(
+                expression::parse_folded(p, state)?,
+            ))))
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NanPattern {
+    /// `nan:canonical`
+    Canonical,
+
+    /// `nan:arithmetic`
+    Arithmetic,
+}
+
+impl fmt::Display for NanPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            | Self::Canonical => write!(f, "nan:canonical"),
+            | Self::Arithmetic => write!(f, "nan:arithmetic"),
+        }
+    }
+}
+
+impl Parse<'_> for NanPattern {
+    fn parse(parser: Parser<'_>) -> Result<Self> {
+        let mut l = parser.lookahead1();
+
+        if l.peek::<wast::kw::nan_canonical>() {
+            parser.parse::<wast::kw::nan_canonical>()?;
+            Ok(Self::Canonical)
+        } else if l.peek::<wast::kw::nan_arithmetic>() {
+            parser.parse::<wast::kw::nan_arithmetic>()?;
+            Ok(Self::Arithmetic)
+        } else {
+            Err(l.error())
+        }
+    }
+}
+
+impl wast::parser::Peek for NanPattern {
+    fn peek(cursor: wast::parser::Cursor<'_>) -> bool {
+        wast::kw::nan_canonical::peek(cursor)
+            || wast::kw::nan_arithmetic::peek(cursor)
+    }
+
+    fn display() -> &'static str {
+        "a NaN pattern"
+    }
+}
+
+/// `(f32.const nan:canonical)` and friends.
+struct NanResult(&'static str, NanPattern);
+
+impl SExpr for NanResult {
+    fn car(&self) -> String {
+        format!("{}.const", self.0)
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        vec![Expr::Atom(Atom::new(self.1.to_string()))]
+    }
+}
+
+/// The subject of `assert_trap`, which is either an action or a module whose
+/// instantiation traps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrapSubject {
+    Action(Action),
+    Module(Module),
+}
+
+/// `(assert_trap action "message")` or `(assert_trap module "message")`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssertTrap {
+    subject: TrapSubject,
+    message: String,
+}
+
+impl AssertTrap {
+    pub fn new(subject: TrapSubject, message: String) -> Self {
+        Self { subject, message }
+    }
+
+    fn exprs(&self) -> Vec<Expr<'_>> {
+        let subject = match self.subject {
+            | TrapSubject::Action(ref a) => Expr::SExpr(Box::new(a)),
+            | TrapSubject::Module(ref m) => Expr::SExpr(Box::new(m)),
+        };
+
+        vec![subject, string_expr(&self.message)]
+    }
+}
+
+impl ParseIn for AssertTrap {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let subject = if parser.peek2::<wast::kw::module>() {
+            TrapSubject::Module(parser.parens(|p| Module::parse_in(p, state))?)
+        } else {
+            TrapSubject::Action(parser.parens(|p| Action::parse_in(p, state))?)
+        };
+        let message = parser.parse::<String>()?;
+
+        Ok(Self { subject, message })
+    }
+}
+
+/// `(assert_exhaustion action "message")`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssertExhaustion {
+    action:  Action,
+    message: String,
+}
+
+impl AssertExhaustion {
+    pub fn new(action: Action, message: String) -> Self {
+        Self { action, message }
+    }
+
+    fn exprs(&self) -> Vec<Expr<'_>> {
+        vec![
+            Expr::SExpr(Box::new(&self.action)),
+            string_expr(&self.message),
+        ]
+    }
+}
+
+impl ParseIn for AssertExhaustion {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let action = parser.parens(|p| Action::parse_in(p, state))?;
+        let message = parser.parse::<String>()?;
+
+        Ok(Self { action, message })
+    }
+}
+
+/// The body of `assert_invalid`, `assert_malformed` and `assert_unlinkable`:
+/// `module "message"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssertModule {
+    module:  Module,
+    message: String,
+}
+
+impl AssertModule {
+    pub fn new(module: Module, message: String) -> Self {
+        Self { module, message }
+    }
+
+    fn exprs(&self) -> Vec<Expr<'_>> {
+        vec![
+            Expr::SExpr(Box::new(&self.module)),
+            string_expr(&self.message),
+        ]
+    }
+}
+
+impl ParseIn for AssertModule {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let module = parser.parens(|p| Module::parse_in(p, state))?;
+        let message = parser.parse::<String>()?;
+
+        Ok(Self { module, message })
+    }
+}
+
+parse_without_state!(
+    Command,
+    Action,
+    Invoke,
+    AssertReturn,
+    ResultPattern,
+    AssertTrap,
+    AssertExhaustion,
+    AssertModule,
+);
+
+fn string_expr(s: &str) -> Expr<'_> {
+    Expr::Atom(Atom::new(format!(
+        r#""{}""#,
+        annotation::escape(s.as_bytes())
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn round_trip() {
+        let src = concat!(
+            "(module $m (func $f))\n",
+            "(register \"m\" $m)\n",
+            "(invoke $m \"f\" (i32.const 1) (f64.const -0x1p-1))\n",
+            "(assert_return (invoke \"f\") (i32.const 1) (f32.const nan:canonical))\n",
+            "(assert_return (get \"g\") (f64.const nan:arithmetic))\n",
+            "(assert_trap (invoke \"f\") \"unreachable\")\n",
+            "(assert_exhaustion (invoke \"f\") \"call stack exhausted\")\n",
+            "(assert_invalid (module (func)) \"type mismatch\")\n",
+            "(assert_malformed (module quote \"(func\") \"unexpected end\")\n",
+            "(assert_unlinkable (module binary \"\\00asm\") \"unknown import\")",
+        );
+
+        assert_eq!(src.parse::<Script>().unwrap().to_string(), src);
+    }
+
+    #[test]
+    fn comments() {
+        let src = ";; header\n(module (func $f)) ;; trailing";
+
+        assert_eq!(src.parse::<Script>().unwrap().to_string(), src);
+
+        let src = concat!(
+            "(module\n",
+            "  ;; Adds one.\n",
+            "  (func $f)\n",
+            ")\n",
+            "(assert_return (invoke \"f\" (i32.const 1) (; one ;))",
+            " (i32.const 2)) ;; two\n",
+            ";; Footer.",
+        );
+
+        assert_eq!(src.parse::<Script>().unwrap().to_string(), src);
+    }
+
+    #[test]
+    fn custom_instructions() {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Zero;
+
+        impl CustomInstruction for Zero {
+            fn immediates(&self) -> Vec<Atom> {
+                Vec::new()
+            }
+        }
+
+        impl wast::parser::Parse<'_> for Zero {
+            fn parse(
+                _: wast::parser::Parser<'_>,
+            ) -> wast::parser::Result<Self> {
+                Ok(Self)
+            }
+        }
+
+        let options = ParseOptions::default()
+            .with_instruction::<Zero>("ext.zero")
+            .unwrap();
+        let src = "(module (func ext.zero))\n(invoke \"f\" (ext.zero))";
+        let script = Script::parse_with(src, &options).unwrap();
+        let body = match script.commands()[0] {
+            | Command::Module(ref m) => match m.sections() {
+                | Some([Section::Function(f)]) => f.entries()[0].exprs(),
+                | _ => panic!("not a function: {:?}", m),
+            },
+            | ref c => panic!("not a module: {:?}", c),
+        };
+
+        assert!(matches!(
+            body,
+            [Expression::Unfolded(Instruction::Custom(_))],
+        ));
+        assert_eq!(script.to_string(), src);
+    }
+}
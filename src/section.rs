@@ -1,8 +1,21 @@
+use std::rc::Rc;
+
 use wast::parser::{Parse, Parser, Result};
 
 use crate::{
-    AsAtoms, Atom, Expr, Expression, ExpressionParser, FuncType, GlobalType,
-    ImportDesc, Index, InlineExport, MemType, SExpr, TypeUse,
+    annotation::{self, escape},
+    canonical::Canonicalize,
+    comment::Comments,
+    error::ErrorKind,
+    fold::{self, Signatures},
+    print::Printable,
+    recover::{self, Malformed},
+    span::ignore_spans,
+    state::{parse_without_state, ParseIn, State},
+    token_tree,
+    Annotation, Annotations, AsAtoms, Atom, Expr, Expression, ExpressionParser,
+    FuncType, GlobalType, ImportDesc, Index, InlineExport, Integer, Limits,
+    MemType, PrintOptions, SExpr, Spanned, TypeUse, Unknown,
 };
 
 /// https://webassembly.github.io/spec/core/text/modules.html#text-module
@@ -13,43 +26,256 @@ pub enum Section {
     Function(FunctionSection),
     Memory(MemorySection),
     Global(GlobalSection),
+    Start(StartSection),
     Data(DataSection),
+    Custom(CustomSection),
+
+    /// An annotation other than `@custom` at module field position, e.g.
+    /// `(@producers ...)`.
+    Annotation(Annotation),
+
+    /// A module field that this crate does not model, see [`Unknown`].
+    Unknown(Unknown),
+
+    /// A module field that failed to parse, see
+    /// [`Document::parse_recovering`].
+    ///
+    /// [`Document::parse_recovering`]: crate::Document::parse_recovering
+    Error(Malformed),
 }
 
 impl Section {
-    pub(crate) fn exprs(&self) -> Vec<Expr> {
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
         match self {
             Self::Type(s) => s.exprs(),
             Self::Import(s) => s.exprs(),
             Self::Function(s) => s.exprs(),
             Self::Memory(s) => s.exprs(),
             Self::Global(s) => s.exprs(),
+            Self::Start(s) => s.exprs(),
             Self::Data(s) => s.exprs(),
+            Self::Custom(s) => s.exprs(),
+            Self::Annotation(a) => vec![a.expr()],
+            Self::Unknown(u) => vec![u.expr()],
+            Self::Error(m) => vec![m.expr()],
+        }
+    }
+
+    /// Like [`exprs`](Self::exprs), for a field of a module whose functions
+    /// have the given `signatures`.
+    pub(crate) fn exprs_in(
+        &self,
+        signatures: &Rc<Signatures>,
+    ) -> Vec<Expr<'_>> {
+        match self {
+            | Self::Function(s) => s.exprs_in(signatures),
+            | _ => self.exprs(),
         }
     }
 }
 
-impl Parse<'_> for Section {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
-        while !parser.is_empty() {
-            if parser.peek2::<wast::kw::r#type>() {
-                return Ok(Self::Type(parser.parse()?));
-            } else if parser.peek2::<wast::kw::import>() {
-                return Ok(Self::Import(parser.parse()?));
-            } else if parser.peek2::<wast::kw::func>() {
-                return Ok(Self::Function(parser.parse()?));
-            } else if parser.peek2::<wast::kw::memory>() {
-                return Ok(Self::Memory(parser.parse()?));
-            } else if parser.peek2::<wast::kw::global>() {
-                return Ok(Self::Global(parser.parse()?));
-            } else if parser.peek2::<wast::kw::data>() {
-                return Ok(Self::Data(parser.parse()?));
-            } else {
-                return Err(parser.error("unexpected section"));
+impl Printable for Section {
+    fn print_exprs(&self) -> Vec<Expr<'_>> {
+        self.exprs()
+    }
+}
+
+/// Implements [`Printable`] for module fields that carry their own comments.
+macro_rules! print_fields {
+    ($($field:ident),* $(,)?) => {
+        $(
+            impl Printable for $field {
+                fn print_exprs(&self) -> Vec<Expr<'_>> {
+                    vec![self.comments.wrap(Expr::SExpr(Box::new(self)))]
+                }
+            }
+        )*
+    };
+}
+
+print_fields!(
+    TypeSectionEntry,
+    ImportSectionEntry,
+    FunctionSectionEntry,
+    MemorySectionEntry,
+    GlobalSectionEntry,
+    StartSection,
+    DataSectionEntry,
+    CustomSection,
+);
+
+/// Implements [`Spanned`] for module fields, whose span points at their
+/// keyword.
+macro_rules! spanned_fields {
+    ($($field:ident),* $(,)?) => {
+        $(
+            impl Spanned for $field {
+                fn span(&self) -> Option<wast::Span> {
+                    self.span
+                }
             }
+        )*
+    };
+}
+
+spanned_fields!(
+    TypeSectionEntry,
+    ImportSectionEntry,
+    FunctionSectionEntry,
+    MemorySectionEntry,
+    GlobalSectionEntry,
+    StartSection,
+    DataSectionEntry,
+    CustomSection,
+);
+
+/// Implements the accessors of the comments attached to module fields.
+macro_rules! commented_fields {
+    ($($field:ident),* $(,)?) => {
+        $(
+            impl $field {
+                /// Attaches comments, printed around the entry.
+                pub fn with_comments(mut self, comments: Comments) -> Self {
+                    self.comments = comments;
+                    self
+                }
+
+                pub fn comments(&self) -> &Comments {
+                    &self.comments
+                }
+            }
+        )*
+    };
+}
+
+commented_fields!(
+    TypeSectionEntry,
+    ImportSectionEntry,
+    FunctionSectionEntry,
+    MemorySectionEntry,
+    GlobalSectionEntry,
+    StartSection,
+    DataSectionEntry,
+    CustomSection,
+);
+
+parse_without_state!(
+    Section,
+    TypeSection,
+    TypeSectionEntry,
+    ImportSection,
+    ImportSectionEntry,
+    FunctionSection,
+    FunctionSectionEntry,
+    MemorySection,
+    MemorySectionEntry,
+    MemoryKind,
+    GlobalSection,
+    GlobalSectionEntry,
+    DataSection,
+    DataSectionEntry,
+    Offset,
+    CustomSection,
+);
+
+impl Spanned for Section {
+    /// Returns the span of the first entry.
+    fn span(&self) -> Option<wast::Span> {
+        match self {
+            | Self::Type(s) => s.entries.first()?.span(),
+            | Self::Import(s) => s.entries.first()?.span(),
+            | Self::Function(s) => s.entries.first()?.span(),
+            | Self::Memory(s) => s.entries.first()?.span(),
+            | Self::Global(s) => s.entries.first()?.span(),
+            | Self::Start(s) => s.span(),
+            | Self::Data(s) => s.entries.first()?.span(),
+            | Self::Custom(s) => s.span(),
+            | Self::Annotation(_) => None,
+            | Self::Unknown(u) => u.span(),
+            | Self::Error(m) => m.span(),
         }
+    }
+}
 
-        Err(parser.error("empty section"))
+impl Canonicalize for Section {
+    fn canonicalize(&mut self) {
+        match self {
+            | Self::Type(s) => s.entries.canonicalize(),
+            | Self::Function(s) => s.entries.canonicalize(),
+            | Self::Memory(s) => s.entries.canonicalize(),
+            | Self::Global(s) => s.entries.canonicalize(),
+            | Self::Start(s) => s.idx.canonicalize(),
+            | Self::Data(s) => s.entries.canonicalize(),
+            | Self::Import(_)
+            | Self::Custom(_)
+            | Self::Annotation(_)
+            | Self::Unknown(_)
+            | Self::Error(_) => {},
+        }
+    }
+}
+
+impl ParseIn for Section {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        if parser.is_empty() {
+            return Err(state.raise(
+                ErrorKind::EmptySection,
+                parser.cur_span(),
+                &[],
+            ));
+        }
+
+        if parser.peek2::<wast::kw::r#type>() {
+            Ok(Self::Type(TypeSection::parse_in(parser, state)?))
+        } else if parser.peek2::<wast::kw::import>() {
+            Ok(Self::Import(ImportSection::parse_in(parser, state)?))
+        } else if parser.peek2::<wast::kw::func>() {
+            Ok(Self::Function(FunctionSection::parse_in(parser, state)?))
+        } else if parser.peek2::<wast::kw::memory>() {
+            Ok(Self::Memory(MemorySection::parse_in(parser, state)?))
+        } else if parser.peek2::<wast::kw::global>() {
+            Ok(Self::Global(GlobalSection::parse_in(parser, state)?))
+        } else if parser.peek2::<wast::kw::start>() {
+            let (mut start, comments) =
+                state.comments.parse_with_comments(parser, |p| {
+                    p.parens(StartSection::parse)
+                })?;
+
+            start.comments = comments;
+
+            Ok(Self::Start(start))
+        } else if parser.peek2::<wast::kw::data>() {
+            Ok(Self::Data(DataSection::parse_in(parser, state)?))
+        } else if parser.peek2::<annotation::kw::custom>() {
+            let (mut custom, comments) =
+                state.comments.parse_with_comments(parser, |p| {
+                    p.parens(|p| CustomSection::parse_in(p, state))
+                })?;
+
+            custom.comments = comments;
+
+            Ok(Self::Custom(custom))
+        } else if parser.peek::<Annotation>() {
+            Ok(Self::Annotation(Annotation::parse_in(parser, state)?))
+        } else if parser.peek2::<Unknown>() {
+            let (mut unknown, comments) =
+                state.comments.parse_with_comments(parser, |p| {
+                    p.parens(|p| Unknown::parse_list(p, state))
+                })?;
+
+            unknown.comments = comments;
+
+            Ok(Self::Unknown(unknown))
+        } else {
+            Err(state.raise(
+                ErrorKind::UnknownSection,
+                parser.cur_span(),
+                &[
+                    "type", "import", "func", "memory", "global", "start",
+                    "data", "@custom",
+                ],
+            ))
+        }
     }
 }
 
@@ -63,20 +289,36 @@ impl TypeSection {
         Self { entries }
     }
 
-    pub(crate) fn exprs(&self) -> Vec<Expr> {
+    pub fn entries(&self) -> &[TypeSectionEntry] {
+        &self.entries
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
         self.entries
             .iter()
-            .map(|e| Expr::SExpr(Box::new(e.clone())))
+            .map(|e| e.comments.wrap(Expr::SExpr(Box::new(e))))
             .collect()
     }
 }
 
-impl Parse<'_> for TypeSection {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl ParseIn for TypeSection {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
         let mut entries = Vec::new();
 
         while !parser.is_empty() {
-            entries.push(parser.parens(|p| p.parse::<TypeSectionEntry>())?);
+            let first = entries.is_empty();
+            let parsed = recover::backtrack(parser, state, first, |parser| {
+                state.comments.parse_with_comments(parser, |p| {
+                    p.parens(|p| TypeSectionEntry::parse_in(p, state))
+                })
+            })?;
+            let (mut entry, comments) = match parsed {
+                | Some(parsed) => parsed,
+                | None => break,
+            };
+
+            entry.comments = comments;
+            entries.push(entry);
 
             if !parser.peek2::<wast::kw::r#type>() {
                 break;
@@ -87,15 +329,32 @@ impl Parse<'_> for TypeSection {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct TypeSectionEntry {
     idx:       Option<Index>,
     func_type: FuncType,
+    comments:  Comments,
+    span:      Option<wast::Span>,
 }
 
+ignore_spans!(TypeSectionEntry { idx, func_type, comments; span });
+
 impl TypeSectionEntry {
     pub fn new(idx: Option<Index>, func_type: FuncType) -> Self {
-        Self { idx, func_type }
+        Self {
+            idx,
+            func_type,
+            comments: Comments::default(),
+            span: None,
+        }
+    }
+
+    pub fn idx(&self) -> Option<&Index> {
+        self.idx.as_ref()
+    }
+
+    pub fn func_type(&self) -> &FuncType {
+        &self.func_type
     }
 }
 
@@ -104,27 +363,40 @@ impl SExpr for TypeSectionEntry {
         "type".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         let mut v = Vec::new();
 
         if let Some(ref idx) = self.idx {
             v.push(Expr::Atom(Atom::new(idx.to_string())));
         }
 
-        v.push(Expr::SExpr(Box::new(self.func_type.clone())));
+        v.push(Expr::SExpr(Box::new(&self.func_type)));
 
         v
     }
 }
 
-impl Parse<'_> for TypeSectionEntry {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl Canonicalize for TypeSectionEntry {
+    fn canonicalize(&mut self) {
+        self.idx.canonicalize();
+    }
+}
+
+impl ParseIn for TypeSectionEntry {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let span = Some(parser.cur_span());
+
         parser.parse::<wast::kw::r#type>()?;
 
         let idx = parser.parse::<Option<Index>>()?;
-        let func_type = parser.parens(|p| p.parse::<FuncType>())?;
+        let func_type = parser.parens(|p| FuncType::parse_in(p, state))?;
 
-        Ok(Self { idx, func_type })
+        Ok(Self {
+            idx,
+            func_type,
+            comments: Comments::default(),
+            span,
+        })
     }
 }
 
@@ -138,20 +410,36 @@ impl ImportSection {
         Self { entries }
     }
 
-    pub(crate) fn exprs(&self) -> Vec<Expr> {
+    pub fn entries(&self) -> &[ImportSectionEntry] {
+        &self.entries
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
         self.entries
             .iter()
-            .map(|e| Expr::SExpr(Box::new(e.clone())))
+            .map(|e| e.comments.wrap(Expr::SExpr(Box::new(e))))
             .collect()
     }
 }
 
-impl Parse<'_> for ImportSection {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl ParseIn for ImportSection {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
         let mut entries = Vec::new();
 
         while !parser.is_empty() {
-            entries.push(parser.parens(ImportSectionEntry::parse)?);
+            let first = entries.is_empty();
+            let parsed = recover::backtrack(parser, state, first, |parser| {
+                state.comments.parse_with_comments(parser, |p| {
+                    p.parens(|p| ImportSectionEntry::parse_in(p, state))
+                })
+            })?;
+            let (mut entry, comments) = match parsed {
+                | Some(parsed) => parsed,
+                | None => break,
+            };
+
+            entry.comments = comments;
+            entries.push(entry);
 
             if !parser.peek2::<wast::kw::import>() {
                 break;
@@ -162,16 +450,57 @@ impl Parse<'_> for ImportSection {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct ImportSectionEntry {
-    module: String,
-    name:   String,
-    desc:   ImportDesc,
+    module:      String,
+    name:        String,
+    desc:        ImportDesc,
+    comments:    Comments,
+    span:        Option<wast::Span>,
+    module_span: Option<wast::Span>,
+    name_span:   Option<wast::Span>,
 }
 
+ignore_spans!(
+    ImportSectionEntry {
+        module, name, desc, comments;
+        span, module_span, name_span
+    },
+);
+
 impl ImportSectionEntry {
     pub fn new(module: String, name: String, desc: ImportDesc) -> Self {
-        Self { module, name, desc }
+        Self {
+            module,
+            name,
+            desc,
+            comments: Comments::default(),
+            span: None,
+            module_span: None,
+            name_span: None,
+        }
+    }
+
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    /// Returns the span of the module name string.
+    pub fn module_span(&self) -> Option<wast::Span> {
+        self.module_span
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the span of the import name string.
+    pub fn name_span(&self) -> Option<wast::Span> {
+        self.name_span
+    }
+
+    pub fn desc(&self) -> &ImportDesc {
+        &self.desc
     }
 }
 
@@ -180,7 +509,7 @@ impl SExpr for ImportSectionEntry {
         "import".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         let mut v = Vec::with_capacity(3);
 
         v.append(
@@ -199,21 +528,33 @@ impl SExpr for ImportSectionEntry {
                 .map(|a| Expr::Atom(a.clone()))
                 .collect(),
         );
-        v.push(Expr::SExpr(Box::new(self.desc.clone())));
+        v.push(Expr::SExpr(Box::new(&self.desc)));
 
         v
     }
 }
 
-impl Parse<'_> for ImportSectionEntry {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl ParseIn for ImportSectionEntry {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let span = Some(parser.cur_span());
+
         parser.parse::<wast::kw::import>()?;
 
+        let module_span = Some(parser.cur_span());
         let module = parser.parse::<String>()?;
+        let name_span = Some(parser.cur_span());
         let name = parser.parse::<String>()?;
-        let desc = parser.parens(ImportDesc::parse)?;
+        let desc = parser.parens(|p| ImportDesc::parse_in(p, state))?;
 
-        Ok(Self { module, name, desc })
+        Ok(Self {
+            module,
+            name,
+            desc,
+            comments: Comments::default(),
+            span,
+            module_span,
+            name_span,
+        })
     }
 }
 
@@ -227,20 +568,68 @@ impl FunctionSection {
         Self { entries }
     }
 
-    pub(crate) fn exprs(&self) -> Vec<Expr> {
+    pub fn entries(&self) -> &[FunctionSectionEntry] {
+        &self.entries
+    }
+
+    pub fn entries_mut(&mut self) -> &mut [FunctionSectionEntry] {
+        &mut self.entries
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
+        self.entries
+            .iter()
+            .map(|e| e.comments.wrap(Expr::SExpr(Box::new(e))))
+            .collect()
+    }
+
+    fn exprs_in(&self, signatures: &Rc<Signatures>) -> Vec<Expr<'_>> {
         self.entries
             .iter()
-            .map(|e| Expr::SExpr(Box::new(e.clone())))
+            .map(|entry| entry.expr_in(signatures))
             .collect()
     }
 }
 
-impl Parse<'_> for FunctionSection {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+/// A function printed in its module, whose calls are given operands
+/// according to the `signatures` of the module when its body is laid out.
+struct InModule<'a> {
+    entry:      &'a FunctionSectionEntry,
+    signatures: Rc<Signatures>,
+}
+
+impl SExpr for InModule<'_> {
+    fn car(&self) -> String {
+        self.entry.car()
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        self.entry.cdr()
+    }
+
+    fn cdr_with(&self, options: &PrintOptions) -> Vec<Expr<'_>> {
+        self.entry.cdr_in(options, &self.signatures)
+    }
+}
+
+impl ParseIn for FunctionSection {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
         let mut entries = Vec::new();
 
         while !parser.is_empty() {
-            entries.push(parser.parens(|p| p.parse::<FunctionSectionEntry>())?);
+            let first = entries.is_empty();
+            let parsed = recover::backtrack(parser, state, first, |parser| {
+                state.comments.parse_with_comments(parser, |p| {
+                    p.parens(|p| FunctionSectionEntry::parse_in(p, state))
+                })
+            })?;
+            let (mut entry, comments) = match parsed {
+                | Some(parsed) => parsed,
+                | None => break,
+            };
+
+            entry.comments = comments;
+            entries.push(entry);
 
             if !parser.peek2::<wast::kw::func>() {
                 break;
@@ -251,14 +640,24 @@ impl Parse<'_> for FunctionSection {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct FunctionSectionEntry {
     idx:           Option<Index>,
+    annotations:   Annotations,
     inline_export: Option<InlineExport>,
     type_use:      TypeUse,
     exprs:         Vec<Expression>,
+    comments:      Comments,
+    span:          Option<wast::Span>,
 }
 
+ignore_spans!(
+    FunctionSectionEntry {
+        idx, annotations, inline_export, type_use, exprs, comments;
+        span
+    },
+);
+
 impl FunctionSectionEntry {
     pub fn new(
         idx: Option<Index>,
@@ -268,11 +667,51 @@ impl FunctionSectionEntry {
     ) -> Self {
         Self {
             idx,
+            annotations: Annotations::default(),
             inline_export,
             type_use,
             exprs,
+            comments: Comments::default(),
+            span: None,
         }
     }
+
+    pub fn idx(&self) -> Option<&Index> {
+        self.idx.as_ref()
+    }
+
+    pub fn type_use(&self) -> &TypeUse {
+        &self.type_use
+    }
+
+    pub fn with_inline_export(mut self, inline_export: InlineExport) -> Self {
+        self.inline_export = Some(inline_export);
+        self
+    }
+
+    pub fn inline_export(&self) -> Option<&InlineExport> {
+        self.inline_export.as_ref()
+    }
+
+    /// Returns the function body.
+    pub fn exprs(&self) -> &[Expression] {
+        &self.exprs
+    }
+
+    pub(crate) fn exprs_mut(&mut self) -> &mut Vec<Expression> {
+        &mut self.exprs
+    }
+
+    /// Attaches annotations such as `(@name "foo")`, printed right after the
+    /// function index.
+    pub fn with_annotations(mut self, annotations: Annotations) -> Self {
+        self.annotations = annotations;
+        self
+    }
+
+    pub fn annotations(&self) -> &Annotations {
+        &self.annotations
+    }
 }
 
 impl SExpr for FunctionSectionEntry {
@@ -280,44 +719,104 @@ impl SExpr for FunctionSectionEntry {
         "func".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        let mut v = self.header_exprs();
+
+        v.append(&mut self.exprs.iter().map(|e| e.expr()).collect());
+
+        v
+    }
+
+    fn cdr_with(&self, options: &PrintOptions) -> Vec<Expr<'_>> {
+        self.cdr_in(options, &Signatures::default())
+    }
+}
+
+impl FunctionSectionEntry {
+    /// Returns the function with its comments, as printed in a module whose
+    /// functions have the given `signatures`.
+    pub(crate) fn expr_in(&self, signatures: &Rc<Signatures>) -> Expr<'_> {
+        let in_module = InModule {
+            entry:      self,
+            signatures: Rc::clone(signatures),
+        };
+
+        self.comments.wrap(Expr::SExpr(Box::new(in_module)))
+    }
+
+    /// Returns the children with the body laid out with `options`, giving
+    /// calls operands according to `signatures`.
+    fn cdr_in(
+        &self,
+        options: &PrintOptions,
+        signatures: &Signatures,
+    ) -> Vec<Expr<'_>> {
+        let policy = match options.folding() {
+            | Some(policy) => policy,
+            | None => return self.cdr(),
+        };
+        let mut v = self.header_exprs();
+
+        for node in fold::relayout(&self.exprs, policy, signatures) {
+            v.push(node.into_expr());
+        }
+
+        v
+    }
+
+    /// Returns everything but the body.
+    fn header_exprs(&self) -> Vec<Expr<'_>> {
         let mut v = Vec::new();
 
         if let Some(ref idx) = self.idx {
             v.push(Expr::Atom(Atom::new(idx.to_string())));
         }
 
+        v.append(&mut self.annotations.exprs());
+
         if let Some(ref inline_export) = self.inline_export {
-            v.push(Expr::SExpr(Box::new(inline_export.clone())));
+            v.push(Expr::SExpr(Box::new(inline_export)));
         }
 
         v.append(&mut self.type_use.exprs());
-        v.append(&mut self.exprs.iter().map(|e| e.expr()).collect());
 
         v
     }
 }
 
-impl Parse<'_> for FunctionSectionEntry {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl Canonicalize for FunctionSectionEntry {
+    fn canonicalize(&mut self) {
+        self.idx.canonicalize();
+        self.type_use.canonicalize();
+        self.exprs.canonicalize();
+    }
+}
+
+impl ParseIn for FunctionSectionEntry {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let span = Some(parser.cur_span());
+
         parser.parse::<wast::kw::func>()?;
 
         let idx = parser.parse::<Option<Index>>()?;
+        let annotations = Annotations::parse_in(parser, state)?;
         let mut inline_export = None;
 
         if parser.peek2::<wast::kw::export>() {
             inline_export = Some(parser.parens(InlineExport::parse)?);
         }
 
-        let type_use = parser.parse::<TypeUse>()?;
-        let expressions_parser = ExpressionParser::default();
-        let exprs = expressions_parser.parse(parser)?;
+        let type_use = TypeUse::parse_in(parser, state)?;
+        let exprs = ExpressionParser::default().parse(parser, state)?;
 
         Ok(Self {
             idx,
+            annotations,
             inline_export,
             type_use,
             exprs,
+            comments: Comments::default(),
+            span,
         })
     }
 }
@@ -332,20 +831,36 @@ impl MemorySection {
         Self { entries }
     }
 
-    pub(crate) fn exprs(&self) -> Vec<Expr> {
+    pub fn entries(&self) -> &[MemorySectionEntry] {
+        &self.entries
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
         self.entries
             .iter()
-            .map(|e| Expr::SExpr(Box::new(e.clone())))
+            .map(|e| e.comments.wrap(Expr::SExpr(Box::new(e))))
             .collect()
     }
 }
 
-impl Parse<'_> for MemorySection {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl ParseIn for MemorySection {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
         let mut entries = Vec::new();
 
         while !parser.is_empty() {
-            entries.push(parser.parens(|p| p.parse::<MemorySectionEntry>())?);
+            let first = entries.is_empty();
+            let parsed = recover::backtrack(parser, state, first, |parser| {
+                state.comments.parse_with_comments(parser, |p| {
+                    p.parens(|p| MemorySectionEntry::parse_in(p, state))
+                })
+            })?;
+            let (mut entry, comments) = match parsed {
+                | Some(parsed) => parsed,
+                | None => break,
+            };
+
+            entry.comments = comments;
+            entries.push(entry);
 
             if !parser.peek2::<wast::kw::memory>() {
                 break;
@@ -356,13 +871,17 @@ impl Parse<'_> for MemorySection {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct MemorySectionEntry {
     idx:           Option<Index>,
     inline_export: Option<InlineExport>,
-    mem_type:      MemType,
+    kind:          MemoryKind,
+    comments:      Comments,
+    span:          Option<wast::Span>,
 }
 
+ignore_spans!(MemorySectionEntry { idx, inline_export, kind, comments; span });
+
 impl MemorySectionEntry {
     pub fn new(
         idx: Option<Index>,
@@ -372,17 +891,110 @@ impl MemorySectionEntry {
         Self {
             idx,
             inline_export,
-            mem_type,
+            kind: MemoryKind::Normal(mem_type),
+            comments: Comments::default(),
+            span: None,
+        }
+    }
+
+    /// Creates a memory using the inline data abbreviation, e.g.
+    /// `(memory (data "abc"))`.
+    pub fn with_inline_data(
+        idx: Option<Index>,
+        inline_export: Option<InlineExport>,
+        data_string: DataString,
+    ) -> Self {
+        Self {
+            idx,
+            inline_export,
+            kind: MemoryKind::Inline(data_string),
+            comments: Comments::default(),
+            span: None,
+        }
+    }
+
+    pub fn idx(&self) -> Option<&Index> {
+        self.idx.as_ref()
+    }
+
+    pub fn kind(&self) -> &MemoryKind {
+        &self.kind
+    }
+
+    /// Returns the memory type, deriving the limits from the data length when
+    /// the inline data abbreviation was used.
+    pub fn mem_type(&self) -> MemType {
+        match self.kind {
+            | MemoryKind::Normal(ref mem_type) => mem_type.clone(),
+            | MemoryKind::Inline(ref data_string) => {
+                let pages = data_string.len().div_ceil(PAGE_SIZE);
+                let pages = Integer::new(pages.to_string());
+
+                MemType::new(Limits::new(pages.clone(), Some(pages)))
+            },
+        }
+    }
+}
+
+/// The size in bytes of a WebAssembly memory page.
+const PAGE_SIZE: usize = 65536;
+
+/// Records which spelling a memory definition used so that it is printed back
+/// the same way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryKind {
+    /// `(memory 1 2)`
+    Normal(MemType),
+
+    /// `(memory (data "abc" "def"))`
+    Inline(DataString),
+}
+
+impl MemoryKind {
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
+        match self {
+            | Self::Normal(mem_type) => mem_type.exprs(),
+            | Self::Inline(data_string) => {
+                vec![Expr::SExpr(Box::new(InlineData(data_string)))]
+            },
         }
     }
 }
 
+impl ParseIn for MemoryKind {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        if parser.peek2::<wast::kw::data>() {
+            let data_string = parser.parens(|p| {
+                p.parse::<wast::kw::data>()?;
+                p.parse::<DataString>()
+            })?;
+
+            Ok(Self::Inline(data_string))
+        } else {
+            Ok(Self::Normal(MemType::parse_in(parser, state)?))
+        }
+    }
+}
+
+/// The `(data ...)` form nested in an abbreviated memory definition.
+struct InlineData<'a>(&'a DataString);
+
+impl SExpr for InlineData<'_> {
+    fn car(&self) -> String {
+        "data".to_owned()
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        self.0.exprs()
+    }
+}
+
 impl SExpr for MemorySectionEntry {
     fn car(&self) -> String {
         "memory".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         let mut v = Vec::new();
 
         if let Some(ref idx) = self.idx {
@@ -390,17 +1002,29 @@ impl SExpr for MemorySectionEntry {
         }
 
         if let Some(ref inline_export) = self.inline_export {
-            v.push(Expr::SExpr(Box::new(inline_export.clone())));
+            v.push(Expr::SExpr(Box::new(inline_export)));
         }
 
-        v.append(&mut self.mem_type.exprs());
+        v.append(&mut self.kind.exprs());
 
         v
     }
 }
 
-impl Parse<'_> for MemorySectionEntry {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl Canonicalize for MemorySectionEntry {
+    fn canonicalize(&mut self) {
+        self.idx.canonicalize();
+
+        if let MemoryKind::Normal(ref mut mem_type) = self.kind {
+            mem_type.canonicalize();
+        }
+    }
+}
+
+impl ParseIn for MemorySectionEntry {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let span = Some(parser.cur_span());
+
         parser.parse::<wast::kw::memory>()?;
 
         let idx = parser.parse::<Option<Index>>()?;
@@ -410,12 +1034,14 @@ impl Parse<'_> for MemorySectionEntry {
             inline_export = Some(parser.parens(InlineExport::parse)?);
         }
 
-        let mem_type = parser.parse::<MemType>()?;
+        let kind = MemoryKind::parse_in(parser, state)?;
 
         Ok(Self {
             idx,
             inline_export,
-            mem_type,
+            kind,
+            comments: Comments::default(),
+            span,
         })
     }
 }
@@ -430,20 +1056,36 @@ impl GlobalSection {
         Self { entries }
     }
 
-    pub(crate) fn exprs(&self) -> Vec<Expr> {
+    pub fn entries(&self) -> &[GlobalSectionEntry] {
+        &self.entries
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
         self.entries
             .iter()
-            .map(|e| Expr::SExpr(Box::new(e.clone())))
+            .map(|e| e.comments.wrap(Expr::SExpr(Box::new(e))))
             .collect()
     }
 }
 
-impl Parse<'_> for GlobalSection {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl ParseIn for GlobalSection {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
         let mut entries = Vec::new();
 
         while !parser.is_empty() {
-            entries.push(parser.parens(|p| p.parse::<GlobalSectionEntry>())?);
+            let first = entries.is_empty();
+            let parsed = recover::backtrack(parser, state, first, |parser| {
+                state.comments.parse_with_comments(parser, |p| {
+                    p.parens(|p| GlobalSectionEntry::parse_in(p, state))
+                })
+            })?;
+            let (mut entry, comments) = match parsed {
+                | Some(parsed) => parsed,
+                | None => break,
+            };
+
+            entry.comments = comments;
+            entries.push(entry);
 
             if !parser.peek2::<wast::kw::global>() {
                 break;
@@ -454,7 +1096,7 @@ impl Parse<'_> for GlobalSection {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct GlobalSectionEntry {
     idx:           Option<Index>,
     inline_export: Option<InlineExport>,
@@ -462,8 +1104,18 @@ pub struct GlobalSectionEntry {
 
     /// An imported global does not have expr.
     expr: Option<Expression>,
+
+    comments: Comments,
+    span:     Option<wast::Span>,
 }
 
+ignore_spans!(
+    GlobalSectionEntry {
+        idx, inline_export, global_type, expr, comments;
+        span
+    },
+);
+
 impl GlobalSectionEntry {
     pub fn new(
         idx: Option<Index>,
@@ -476,8 +1128,19 @@ impl GlobalSectionEntry {
             inline_export,
             global_type,
             expr,
+            comments: Comments::default(),
+            span: None,
         }
     }
+
+    pub fn idx(&self) -> Option<&Index> {
+        self.idx.as_ref()
+    }
+
+    /// Returns the initializer, or `None` for an imported global.
+    pub fn expr(&self) -> Option<&Expression> {
+        self.expr.as_ref()
+    }
 }
 
 impl SExpr for GlobalSectionEntry {
@@ -485,7 +1148,7 @@ impl SExpr for GlobalSectionEntry {
         "global".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         let mut v = Vec::new();
 
         if let Some(ref idx) = self.idx {
@@ -493,7 +1156,7 @@ impl SExpr for GlobalSectionEntry {
         }
 
         if let Some(ref inline_export) = self.inline_export {
-            v.push(Expr::SExpr(Box::new(inline_export.clone())));
+            v.push(Expr::SExpr(Box::new(inline_export)));
         }
 
         v.push(self.global_type.expr());
@@ -506,8 +1169,17 @@ impl SExpr for GlobalSectionEntry {
     }
 }
 
-impl Parse<'_> for GlobalSectionEntry {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl Canonicalize for GlobalSectionEntry {
+    fn canonicalize(&mut self) {
+        self.idx.canonicalize();
+        self.expr.canonicalize();
+    }
+}
+
+impl ParseIn for GlobalSectionEntry {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let span = Some(parser.cur_span());
+
         parser.parse::<wast::kw::global>()?;
 
         let idx = parser.parse::<Option<Index>>()?;
@@ -518,24 +1190,85 @@ impl Parse<'_> for GlobalSectionEntry {
         }
 
         let global_type = parser.parse::<GlobalType>()?;
-        let mut exprs = ExpressionParser::default().parse(parser)?;
-        let expr;
-
-        if exprs.len() == 0 {
-            expr = None;
+        let mut exprs = ExpressionParser::default().parse(parser, state)?;
+        let expr = if exprs.is_empty() {
+            None
         } else {
             if exprs.len() != 1 {
-                return Err(parser.error("only one expr is expected"));
+                return Err(state.raise(
+                    ErrorKind::TooManyInitExprs,
+                    parser.cur_span(),
+                    &[],
+                ));
             }
 
-            expr = Some(exprs.pop().unwrap());
-        }
+            Some(exprs.pop().unwrap())
+        };
 
         Ok(Self {
             idx,
             inline_export,
             global_type,
             expr,
+            comments: Comments::default(),
+            span,
+        })
+    }
+}
+
+/// https://webassembly.github.io/spec/core/text/modules.html#start-function
+///
+/// A module has at most one start function, so unlike the other sections this
+/// one holds a single entry.
+#[derive(Debug, Clone)]
+pub struct StartSection {
+    idx:      Index,
+    comments: Comments,
+    span:     Option<wast::Span>,
+}
+
+ignore_spans!(StartSection { idx, comments; span });
+
+impl StartSection {
+    pub fn new(idx: Index) -> Self {
+        Self {
+            idx,
+            comments: Comments::default(),
+            span: None,
+        }
+    }
+
+    pub fn idx(&self) -> &Index {
+        &self.idx
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
+        vec![self.comments.wrap(Expr::SExpr(Box::new(self)))]
+    }
+}
+
+impl SExpr for StartSection {
+    fn car(&self) -> String {
+        "start".to_owned()
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        vec![Expr::Atom(Atom::new(self.idx.to_string()))]
+    }
+}
+
+impl Parse<'_> for StartSection {
+    fn parse(parser: Parser<'_>) -> Result<Self> {
+        let span = Some(parser.cur_span());
+
+        parser.parse::<wast::kw::start>()?;
+
+        let idx = parser.parse::<Index>()?;
+
+        Ok(Self {
+            idx,
+            comments: Comments::default(),
+            span,
         })
     }
 }
@@ -550,20 +1283,36 @@ impl DataSection {
         Self { entries }
     }
 
-    pub(crate) fn exprs(&self) -> Vec<Expr> {
+    pub fn entries(&self) -> &[DataSectionEntry] {
+        &self.entries
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
         self.entries
             .iter()
-            .map(|e| Expr::SExpr(Box::new(e.clone())))
+            .map(|e| e.comments.wrap(Expr::SExpr(Box::new(e))))
             .collect()
     }
 }
 
-impl Parse<'_> for DataSection {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl ParseIn for DataSection {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
         let mut entries = Vec::new();
 
         while !parser.is_empty() {
-            entries.push(parser.parens(DataSectionEntry::parse)?);
+            let first = entries.is_empty();
+            let parsed = recover::backtrack(parser, state, first, |parser| {
+                state.comments.parse_with_comments(parser, |p| {
+                    p.parens(|p| DataSectionEntry::parse_in(p, state))
+                })
+            })?;
+            let (mut entry, comments) = match parsed {
+                | Some(parsed) => parsed,
+                | None => break,
+            };
+
+            entry.comments = comments;
+            entries.push(entry);
 
             if !parser.peek2::<wast::kw::data>() {
                 break;
@@ -582,65 +1331,120 @@ impl Offset {
         Self(expression)
     }
 
-    pub(crate) fn expr(&self) -> Expr {
+    pub fn expression(&self) -> &Expression {
+        &self.0
+    }
+
+    pub(crate) fn expr(&self) -> Expr<'_> {
         self.0.expr()
     }
 }
 
-impl Parse<'_> for Offset {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
-        let mut exprs = ExpressionParser::default().parse(parser)?;
+impl ParseIn for Offset {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let mut exprs = ExpressionParser::default().parse(parser, state)?;
 
-        if exprs.len() == 0 {
-            return Err(parser.error("init_expr is empty"));
+        if exprs.is_empty() {
+            return Err(state.raise(
+                ErrorKind::EmptyInitExpr,
+                parser.cur_span(),
+                &[],
+            ));
         }
 
         if exprs.len() > 1 {
-            return Err(parser.error("only one init_expr operator is expected"));
+            return Err(state.raise(
+                ErrorKind::TooManyInitExprs,
+                parser.cur_span(),
+                &[],
+            ));
         }
 
         Ok(Self(exprs.pop().unwrap()))
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// The string literals holding the data of a data segment or of an inline
+/// `(data ...)` memory, decoded into bytes.
+#[derive(Debug, Clone)]
 pub struct DataString {
-    strings: Vec<String>,
+    strings: Vec<Vec<u8>>,
+    span:    Option<wast::Span>,
 }
 
+ignore_spans!(DataString { strings; span });
+
 impl DataString {
+    /// Creates data from UTF-8 strings. They are escaped when printed.
     pub fn with_strings(strings: Vec<String>) -> Self {
-        Self { strings }
+        Self::with_bytes(strings.into_iter().map(String::into_bytes).collect())
+    }
+
+    /// Creates data from arbitrary bytes. They are escaped when printed.
+    pub fn with_bytes(strings: Vec<Vec<u8>>) -> Self {
+        Self {
+            strings,
+            span: None,
+        }
     }
 
-    pub(crate) fn exprs(&self) -> Vec<Expr> {
+    /// Returns the bytes of each string literal.
+    pub fn strings(&self) -> &[Vec<u8>] {
+        &self.strings
+    }
+
+    /// Returns the total length in bytes of the data.
+    pub fn len(&self) -> usize {
+        self.strings.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.iter().all(Vec::is_empty)
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
         self.strings
             .iter()
-            .map(|s| Expr::Atom(Atom::new(format!(r#""{}""#, s))))
+            .map(|s| Expr::Atom(Atom::new(format!(r#""{}""#, escape(s)))))
             .collect()
     }
 }
 
+/// Points at the first string.
+impl Spanned for DataString {
+    fn span(&self) -> Option<wast::Span> {
+        self.span
+    }
+}
+
 impl Parse<'_> for DataString {
     fn parse(parser: Parser<'_>) -> Result<Self> {
+        let span = Some(parser.cur_span());
         let mut strings = Vec::new();
 
         while !parser.is_empty() {
-            strings.push(parser.parse::<String>()?);
+            strings.push(parser.parse::<&[u8]>()?.to_vec());
         }
 
-        Ok(Self { strings })
+        // Data without strings has no token to point at.
+        let span = span.filter(|_| !strings.is_empty());
+
+        Ok(Self { strings, span })
     }
 }
 
 /// https://webassembly.github.io/spec/core/text/modules.html#data-segments
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct DataSectionEntry {
     idx:         Option<Index>,
     offset:      Offset,
     data_string: DataString,
+    comments:    Comments,
+    span:        Option<wast::Span>,
 }
 
+ignore_spans!(DataSectionEntry { idx, offset, data_string, comments; span });
+
 impl DataSectionEntry {
     pub fn new(
         idx: Option<Index>,
@@ -651,8 +1455,18 @@ impl DataSectionEntry {
             idx,
             offset,
             data_string,
+            comments: Comments::default(),
+            span: None,
         }
     }
+
+    pub fn idx(&self) -> Option<&Index> {
+        self.idx.as_ref()
+    }
+
+    pub fn offset(&self) -> &Offset {
+        &self.offset
+    }
 }
 
 impl SExpr for DataSectionEntry {
@@ -660,7 +1474,7 @@ impl SExpr for DataSectionEntry {
         "data".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         let mut v = Vec::new();
 
         if let Some(idx) = self.idx.clone() {
@@ -674,18 +1488,300 @@ impl SExpr for DataSectionEntry {
     }
 }
 
-impl Parse<'_> for DataSectionEntry {
-    fn parse(parser: Parser<'_>) -> Result<Self> {
+impl Canonicalize for DataSectionEntry {
+    fn canonicalize(&mut self) {
+        self.idx.canonicalize();
+        self.offset.0.canonicalize();
+    }
+}
+
+impl ParseIn for DataSectionEntry {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let span = Some(parser.cur_span());
+
         parser.parse::<wast::kw::data>()?;
 
         let idx = parser.parse::<Option<Index>>()?;
-        let offset = parser.parse::<Offset>()?;
+        let offset = Offset::parse_in(parser, state)?;
         let data_string = parser.parse::<DataString>()?;
 
         Ok(Self {
             idx,
             offset,
             data_string,
+            comments: Comments::default(),
+            span,
         })
     }
 }
+
+/// https://github.com/WebAssembly/annotations/blob/master/proposals/annotations/Overview.md#custom-sections
+#[derive(Debug, Clone)]
+pub struct CustomSection {
+    name:      String,
+    place:     Option<CustomPlace>,
+    data:      Vec<Vec<u8>>,
+    comments:  Comments,
+    span:      Option<wast::Span>,
+    name_span: Option<wast::Span>,
+
+    /// The name and the data strings as written, empty if the section was
+    /// not parsed from a known source.
+    spelling: Vec<String>,
+}
+
+ignore_spans!(
+    CustomSection {
+        name, place, data, comments;
+        span, name_span, spelling
+    },
+);
+
+impl CustomSection {
+    pub fn new(
+        name: String,
+        place: Option<CustomPlace>,
+        data: Vec<Vec<u8>>,
+    ) -> Self {
+        Self {
+            name,
+            place,
+            data,
+            comments: Comments::default(),
+            span: None,
+            name_span: None,
+            spelling: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the span of the section name string.
+    pub fn name_span(&self) -> Option<wast::Span> {
+        self.name_span
+    }
+
+    /// Returns where the section is placed. `None` means the placement was
+    /// omitted, which is equivalent to `(after last)`.
+    pub fn place(&self) -> Option<CustomPlace> {
+        self.place
+    }
+
+    pub fn data(&self) -> &[Vec<u8>] {
+        &self.data
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
+        vec![self.comments.wrap(Expr::SExpr(Box::new(self)))]
+    }
+}
+
+impl SExpr for CustomSection {
+    fn car(&self) -> String {
+        "@custom".to_owned()
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        let string = |i: usize, bytes: &[u8]| {
+            Expr::Atom(Atom::new(match self.spelling.get(i) {
+                | Some(s) => s.clone(),
+                | None => format!(r#""{}""#, escape(bytes)),
+            }))
+        };
+        let mut v = vec![string(0, self.name.as_bytes())];
+
+        if let Some(place) = self.place {
+            v.push(Expr::SExpr(Box::new(place)));
+        }
+
+        v.extend(self.data.iter().enumerate().map(|(i, d)| string(i + 1, d)));
+
+        v
+    }
+}
+
+impl ParseIn for CustomSection {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let span = Some(parser.cur_span());
+
+        parser.parse::<annotation::kw::custom>()?;
+
+        let mut spelling = Vec::new();
+        let mut spell = |parser: Parser<'_>| {
+            let at = parser.cur_span();
+
+            if let Some(s) = token_tree::string_src(at, state.text()) {
+                spelling.push(s.to_owned());
+            }
+        };
+
+        spell(parser);
+
+        let name_span = Some(parser.cur_span());
+        let name = parser.parse::<String>()?;
+        let mut place = None;
+
+        if parser.peek::<wast::LParen>() {
+            place = Some(parser.parens(CustomPlace::parse)?);
+        }
+
+        let mut data = Vec::new();
+
+        while !parser.is_empty() {
+            spell(parser);
+            data.push(parser.parse::<&[u8]>()?.to_vec());
+        }
+
+        // Strings are spelled as written only if all of them are known.
+        if spelling.len() != data.len() + 1 {
+            spelling.clear();
+        }
+
+        Ok(Self {
+            name,
+            place,
+            data,
+            comments: Comments::default(),
+            span,
+            name_span,
+            spelling,
+        })
+    }
+}
+
+/// Where a custom section is placed relative to the known sections.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CustomPlace {
+    BeforeFirst,
+    Before(CustomPlaceAnchor),
+    After(CustomPlaceAnchor),
+    AfterLast,
+}
+
+impl SExpr for CustomPlace {
+    fn car(&self) -> String {
+        match self {
+            | Self::BeforeFirst | Self::Before(_) => "before".to_owned(),
+            | Self::After(_) | Self::AfterLast => "after".to_owned(),
+        }
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        let anchor = match self {
+            | Self::BeforeFirst => "first".to_owned(),
+            | Self::AfterLast => "last".to_owned(),
+            | Self::Before(a) | Self::After(a) => a.to_string(),
+        };
+
+        vec![Expr::Atom(Atom::new(anchor))]
+    }
+}
+
+impl Parse<'_> for CustomPlace {
+    fn parse(parser: Parser<'_>) -> Result<Self> {
+        let mut l = parser.lookahead1();
+
+        if l.peek::<wast::kw::before>() {
+            parser.parse::<wast::kw::before>()?;
+
+            if parser.peek::<wast::kw::first>() {
+                parser.parse::<wast::kw::first>()?;
+
+                return Ok(Self::BeforeFirst);
+            }
+
+            Ok(Self::Before(parser.parse()?))
+        } else if l.peek::<wast::kw::after>() {
+            parser.parse::<wast::kw::after>()?;
+
+            if parser.peek::<wast::kw::last>() {
+                parser.parse::<wast::kw::last>()?;
+
+                return Ok(Self::AfterLast);
+            }
+
+            Ok(Self::After(parser.parse()?))
+        } else {
+            Err(l.error())
+        }
+    }
+}
+
+/// Known sections that custom sections can be placed relative to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CustomPlaceAnchor {
+    Type,
+    Import,
+    Func,
+    Table,
+    Memory,
+    Global,
+    Export,
+    Start,
+    Elem,
+    Code,
+    Data,
+}
+
+impl std::fmt::Display for CustomPlaceAnchor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            | Self::Type => write!(f, "type"),
+            | Self::Import => write!(f, "import"),
+            | Self::Func => write!(f, "func"),
+            | Self::Table => write!(f, "table"),
+            | Self::Memory => write!(f, "memory"),
+            | Self::Global => write!(f, "global"),
+            | Self::Export => write!(f, "export"),
+            | Self::Start => write!(f, "start"),
+            | Self::Elem => write!(f, "elem"),
+            | Self::Code => write!(f, "code"),
+            | Self::Data => write!(f, "data"),
+        }
+    }
+}
+
+impl Parse<'_> for CustomPlaceAnchor {
+    fn parse(parser: Parser<'_>) -> Result<Self> {
+        let mut l = parser.lookahead1();
+
+        if l.peek::<wast::kw::r#type>() {
+            parser.parse::<wast::kw::r#type>()?;
+            Ok(Self::Type)
+        } else if l.peek::<wast::kw::import>() {
+            parser.parse::<wast::kw::import>()?;
+            Ok(Self::Import)
+        } else if l.peek::<wast::kw::func>() {
+            parser.parse::<wast::kw::func>()?;
+            Ok(Self::Func)
+        } else if l.peek::<wast::kw::table>() {
+            parser.parse::<wast::kw::table>()?;
+            Ok(Self::Table)
+        } else if l.peek::<wast::kw::memory>() {
+            parser.parse::<wast::kw::memory>()?;
+            Ok(Self::Memory)
+        } else if l.peek::<wast::kw::global>() {
+            parser.parse::<wast::kw::global>()?;
+            Ok(Self::Global)
+        } else if l.peek::<wast::kw::export>() {
+            parser.parse::<wast::kw::export>()?;
+            Ok(Self::Export)
+        } else if l.peek::<wast::kw::start>() {
+            parser.parse::<wast::kw::start>()?;
+            Ok(Self::Start)
+        } else if l.peek::<wast::kw::elem>() {
+            parser.parse::<wast::kw::elem>()?;
+            Ok(Self::Elem)
+        } else if l.peek::<wast::kw::code>() {
+            parser.parse::<wast::kw::code>()?;
+            Ok(Self::Code)
+        } else if l.peek::<wast::kw::data>() {
+            parser.parse::<wast::kw::data>()?;
+            Ok(Self::Data)
+        } else {
+            Err(l.error())
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::{borrow::Cow, fmt, str::FromStr};
+
+use wast::{
+    lexer::{Lexer, Token},
+    parser::{ParseBuffer, Parser, Result},
+};
+
+use crate::{
+    state::{self, ParseIn, State},
+    Atom, Comments, Error, Expr, Print, PrintOptions, SExpr, TokenTree,
+};
+
+impl<'a> Expr<'a> {
+    /// Creates a single token, such as `i32` or `"name"`, printed as is, or
+    /// returns `None` if `s` is not exactly one such token.
+    pub fn atom<S: AsRef<str>>(s: S) -> Option<Self> {
+        let s = s.as_ref();
+        let mut tokens = Lexer::new(s);
+
+        match (tokens.next(), tokens.next()) {
+            | (
+                Some(Ok(
+                    Token::String(_)
+                    | Token::Id(_)
+                    | Token::Keyword(_)
+                    | Token::Reserved(_)
+                    | Token::Integer(_)
+                    | Token::Float(_),
+                )),
+                None,
+            ) => Some(Self::Atom(Atom::new(s.to_owned()))),
+            | _ => None,
+        }
+    }
+
+    /// Creates a list, such as `(memory 1)`. The list is printed with `car`
+    /// in head position; an empty `car` leaves the head out, as in `(1 2)`.
+    pub fn list<S: AsRef<str>>(car: S, cdr: Vec<Expr<'a>>) -> Self {
+        Self::SExpr(Box::new(List {
+            car: car.as_ref().to_owned(),
+            cdr,
+        }))
+    }
+
+    /// Attaches comments, printed around the expression.
+    pub fn with_comments(self, comments: Comments) -> Self {
+        Self::Commented(Cow::Owned(comments), Box::new(self))
+    }
+
+    /// Parses a sequence of expressions, such as the contents of a `.wat` or
+    /// `.wast` file, keeping their comments. Any balanced text made of
+    /// WebAssembly tokens is accepted.
+    pub fn parse_all(src: &str) -> std::result::Result<Vec<Self>, Error> {
+        let buf =
+            ParseBuffer::new(src).map_err(|err| Error::parse(err, src))?;
+
+        let (exprs, _) = state::parse::<Exprs>(&buf, State::new(src));
+
+        exprs.map(|e| e.0).map_err(|err| Error::parse(err, src))
+    }
+
+    /// Returns the expression borrowing from `self`.
+    fn borrowed(&self) -> Expr<'_> {
+        match self {
+            | Self::Atom(a) => Expr::Atom(a.clone()),
+            | Self::SExpr(se) => Expr::SExpr(Box::new(&**se)),
+            | Self::Commented(comments, expr) => Expr::Commented(
+                Cow::Borrowed(&**comments),
+                Box::new(expr.borrowed()),
+            ),
+        }
+    }
+}
+
+/// Parses a single expression.
+impl FromStr for Expr<'_> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Error> {
+        let mut exprs = Self::parse_all(s)?;
+
+        match exprs.len() {
+            | 1 => Ok(exprs.remove(0)),
+            | n => {
+                let message = format!("expected one expression, found {}", n);
+                let err = wast::Error::new(wast::Span::from_offset(0), message);
+
+                Err(Error::parse(err, s))
+            },
+        }
+    }
+}
+
+impl fmt::Display for Expr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_to(f, &PrintOptions::default())
+    }
+}
+
+impl fmt::Debug for Expr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expr({})", self)
+    }
+}
+
+/// A list built by [`Expr::list`].
+struct List<'a> {
+    car: String,
+    cdr: Vec<Expr<'a>>,
+}
+
+impl SExpr for List<'_> {
+    fn car(&self) -> String {
+        self.car.clone()
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        self.cdr.iter().map(Expr::borrowed).collect()
+    }
+}
+
+/// The expressions of a whole input.
+struct Exprs(Vec<Expr<'static>>);
+
+impl ParseIn for Exprs {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let mut exprs = Vec::new();
+
+        while !parser.is_empty() {
+            exprs.push(parse_expr(parser, state)?);
+        }
+
+        Ok(Self(exprs))
+    }
+}
+
+fn parse_expr(parser: Parser<'_>, state: &State) -> Result<Expr<'static>> {
+    let (expr, comments) = state.comments.parse_with_comments(parser, |p| {
+        if !p.peek::<wast::LParen>() {
+            return p.step(|cursor| match TokenTree::atom(cursor, state.text()) {
+                | Some((TokenTree::Atom(atom), rest)) => {
+                    Ok((Expr::Atom(atom), rest))
+                },
+                | _ => Err(cursor.error("unexpected token")),
+            });
+        }
+
+        p.parens(|p| {
+            let car = p.step(|cursor| {
+                match TokenTree::atom(cursor, state.text()) {
+                    | Some((TokenTree::Atom(atom), rest)) => {
+                        Ok((atom.to_string(), rest))
+                    },
+                    | _ => Ok((String::new(), cursor)),
+                }
+            })?;
+            let mut cdr = Vec::new();
+
+            while !p.is_empty() {
+                cdr.push(parse_expr(p, state)?);
+            }
+
+            Ok(Expr::list(car, cdr))
+        })
+    })?;
+
+    if comments.is_empty() {
+        Ok(expr)
+    } else {
+        Ok(expr.with_comments(comments))
+    }
+}
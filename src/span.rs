@@ -0,0 +1,67 @@
+use wast::Span;
+
+/// Nodes that record where they were parsed from.
+///
+/// The span of a node points at its first token, or at its keyword for nodes
+/// in parentheses. Nodes that were built rather than parsed have no span.
+pub trait Spanned {
+    fn span(&self) -> Option<Span>;
+}
+
+/// Returns the byte offset of `span` in `text`. `wast` does not expose the
+/// offset of a span, but orders spans by it.
+pub(crate) fn offset_in(span: Span, text: &str) -> usize {
+    let (mut low, mut high) = (0, text.len());
+
+    while low < high {
+        let mid = low + (high - low) / 2;
+
+        if Span::from_offset(mid) < span {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+
+    low
+}
+
+/// Returns the byte offset of the zero-based `line` and byte column `col` in
+/// `text`.
+pub(crate) fn offset_at(text: &str, line: usize, col: usize) -> usize {
+    text.split_terminator('\n')
+        .take(line)
+        .map(|l| l.len() + 1)
+        .sum::<usize>()
+        + col
+}
+
+/// Implements `PartialEq` and `Eq`, and `Hash` if asked for, comparing the
+/// fields listed before `;` and ignoring the spans listed after it. As for
+/// [`SymbolicIndex`](crate::SymbolicIndex), the same node parsed from
+/// differently laid out sources compares equal.
+macro_rules! ignore_spans {
+    ($node:ident { $($field:ident),* ; $($span:ident),* } $(,)?) => {
+        impl PartialEq for $node {
+            fn eq(&self, other: &Self) -> bool {
+                // Fails to compile if a field is added but not listed.
+                let Self { $($field: _,)* $($span: _,)* } = self;
+
+                $(self.$field == other.$field &&)* true
+            }
+        }
+
+        impl Eq for $node {}
+    };
+    ($node:ident { $($field:ident),* ; $($span:ident),* }, Hash $(,)?) => {
+        ignore_spans!($node { $($field),* ; $($span),* });
+
+        impl std::hash::Hash for $node {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                $(self.$field.hash(state);)*
+            }
+        }
+    };
+}
+
+pub(crate) use ignore_spans;
@@ -0,0 +1,226 @@
+use std::cell::RefCell;
+
+use wast::{
+    parser::{Parse, ParseBuffer, Parser, Result},
+    Span,
+};
+
+use crate::{
+    annotation, comment, custom,
+    error::{self, ErrorKind},
+    CustomInstruction, Error, KeywordError,
+};
+
+/// Options controlling how a [`Document`](crate::Document) is parsed, see
+/// [`Document::parse_with`](crate::Document::parse_with).
+///
+/// The defaults parse the instructions this crate models, and any other
+/// instruction as [`Unknown`](crate::Unknown).
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    instructions: custom::Instructions,
+}
+
+impl ParseOptions {
+    /// Parses `T` as the instruction spelled `keyword`, see
+    /// [`CustomInstruction`]. `T` parses the immediates after the keyword.
+    ///
+    /// Fails if `keyword` is that of an instruction this crate models, or
+    /// of one added before.
+    pub fn with_instruction<T>(
+        mut self,
+        keyword: &str,
+    ) -> std::result::Result<Self, KeywordError>
+    where
+        T: CustomInstruction + Clone + Eq + for<'a> Parse<'a>,
+    {
+        self.instructions.add::<T>(keyword)?;
+
+        Ok(self)
+    }
+}
+
+/// What a parse knows beyond the tokens: the source text, the comments and
+/// annotations in it, and the errors recovered from so far. `wast` passes
+/// nothing but the parser to [`Parse`] impls, so the nodes that need the
+/// state are parsed with [`ParseIn`] and hand it down to their children.
+///
+/// The type is public only to appear in [`View`](crate::View), whose method
+/// is hidden; it cannot be named outside the crate.
+#[derive(Debug, Default)]
+pub struct State {
+    /// The source, empty when it is not known.
+    text: String,
+
+    /// The names of the annotations in the source. `wast` silently skips
+    /// annotations it has not been told about, so these are registered
+    /// alongside the well-known ones to keep unknown annotations.
+    annotations: Vec<String>,
+
+    pub(crate) comments: comment::Table,
+
+    /// The instructions added by the caller.
+    instructions: custom::Instructions,
+
+    /// The errors recovered from, when parsing without stopping at the first
+    /// one.
+    errors: Option<RefCell<Vec<Error>>>,
+
+    /// The errors raised with [`State::raise`], by their span and message,
+    /// with their kind. A `wast::Error` cannot carry a kind, so it is looked
+    /// up here when the error is converted.
+    raised: RefCell<Vec<(Span, String, ErrorKind)>>,
+}
+
+impl State {
+    /// Creates the state of a parse of `text`.
+    pub(crate) fn new(text: &str) -> Self {
+        Self {
+            text:         text.to_owned(),
+            annotations:  annotation::discover(text),
+            comments:     comment::Table::new(text),
+            instructions: custom::Instructions::default(),
+            errors:       None,
+            raised:       RefCell::default(),
+        }
+    }
+
+    /// Parses with `options`.
+    pub(crate) fn with_options(mut self, options: &ParseOptions) -> Self {
+        self.instructions = options.instructions.clone();
+        self
+    }
+
+    /// Like [`State::new`], for a parse that records errors instead of
+    /// stopping at the first one, see [`Document::parse_recovering`].
+    ///
+    /// [`Document::parse_recovering`]: crate::Document::parse_recovering
+    pub(crate) fn recovering(text: &str) -> Self {
+        Self {
+            errors: Some(RefCell::new(Vec::new())),
+            ..Self::new(text)
+        }
+    }
+
+    pub(crate) fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub(crate) fn instructions(&self) -> &custom::Instructions {
+        &self.instructions
+    }
+
+    pub(crate) fn is_recovering(&self) -> bool {
+        self.errors.is_some()
+    }
+
+    /// Records an error that was recovered from.
+    pub(crate) fn recovered(&self, err: Error) {
+        if let Some(ref errors) = self.errors {
+            errors.borrow_mut().push(err);
+        }
+    }
+
+    /// Returns the errors recorded with [`State::recovered`].
+    pub(crate) fn into_errors(self) -> Vec<Error> {
+        self.errors.map(RefCell::into_inner).unwrap_or_default()
+    }
+
+    /// Returns an error of `kind` at `span`, listing the `expected` tokens,
+    /// and records its kind for [`State::error`].
+    pub(crate) fn raise(
+        &self,
+        kind: ErrorKind,
+        span: Span,
+        expected: &[&str],
+    ) -> wast::Error {
+        let message = error::message(kind, expected);
+
+        self.raised.borrow_mut().push((span, message.clone(), kind));
+        wast::Error::new(span, message)
+    }
+
+    /// Converts an error from a parse in this state, of the kind it was
+    /// raised with if it comes from [`State::raise`].
+    pub(crate) fn error(&self, err: wast::Error) -> Error {
+        let message = err.message();
+        let kind = self
+            .raised
+            .borrow()
+            .iter()
+            .rev()
+            .find(|(span, m, _)| *span == err.span() && *m == message)
+            .map(|&(.., kind)| kind);
+
+        match kind {
+            | Some(kind) => Error::raised(err, kind),
+            | None => Error::from(err),
+        }
+    }
+
+    /// Parses a `T` with the annotations of the source registered.
+    pub(crate) fn parse<T: ParseIn>(&self, parser: Parser<'_>) -> Result<T> {
+        let _registered = annotation::register(parser, &self.annotations);
+
+        T::parse_in(parser, self)
+    }
+}
+
+/// Nodes parsed with the [`State`] of the parse.
+pub(crate) trait ParseIn: Sized {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self>;
+}
+
+thread_local! {
+    /// The state of the parse started by [`parse`], for its root node to
+    /// take. `wast` only hands out a parser to the `Parse` impl of the root,
+    /// so there is no other way to pass the state to it.
+    static ROOT: RefCell<Option<State>> = const { RefCell::new(None) };
+}
+
+/// Parses `buf` as a `T` in `state`, and returns the state with the result.
+pub(crate) fn parse<T: ParseIn>(
+    buf: &ParseBuffer<'_>,
+    state: State,
+) -> (Result<T>, State) {
+    ROOT.with(|r| *r.borrow_mut() = Some(state));
+
+    let result = wast::parser::parse::<Root<T>>(buf).map(|root| root.0);
+    let state = ROOT.with(|r| r.borrow_mut().take()).unwrap_or_default();
+
+    (result, state)
+}
+
+/// The root node of a parse started by [`parse`].
+struct Root<T>(T);
+
+impl<T: ParseIn> Parse<'_> for Root<T> {
+    fn parse(parser: Parser<'_>) -> Result<Self> {
+        let state = ROOT.with(|r| r.borrow_mut().take()).unwrap_or_default();
+        let result = state.parse(parser);
+
+        ROOT.with(|r| *r.borrow_mut() = Some(state));
+
+        result.map(Self)
+    }
+}
+
+/// Implements [`Parse`] for nodes parsed with [`ParseIn`], in a state that
+/// does not know the source: comments are dropped, and annotations other
+/// than `@custom`, `@name` and `@producers` are only kept if the caller
+/// registered them with the parser.
+macro_rules! parse_without_state {
+    ($($node:ty),* $(,)?) => {
+        $(
+            impl wast::parser::Parse<'_> for $node {
+                fn parse(
+                    parser: wast::parser::Parser<'_>,
+                ) -> wast::parser::Result<Self> {
+                    crate::state::State::default().parse(parser)
+                }
+            }
+        )*
+    };
+}
+
+pub(crate) use parse_without_state;
@@ -0,0 +1,189 @@
+use wast::{
+    lexer::{Lexer, Token},
+    parser::{Cursor, Parse, Parser, Result},
+    Span,
+};
+
+use crate::{
+    annotation::escape, span::offset_in, Atom, Comment, Comments, Expr,
+    InnerComment, SExpr,
+};
+
+/// A single token, or a parenthesized group of tokens, kept as written. Used
+/// for the payload of an [`Annotation`] and for the constructs this crate
+/// does not model, see [`Unknown`].
+///
+/// [`Annotation`]: crate::Annotation
+/// [`Unknown`]: crate::Unknown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenTree {
+    Atom(Atom),
+    List(Vec<TokenTree>),
+
+    /// A comment between the tokens, printed where it was written.
+    Comment(Comment),
+}
+
+impl TokenTree {
+    pub fn string(bytes: &[u8]) -> Self {
+        Self::Atom(Atom::new(format!(r#""{}""#, escape(bytes))))
+    }
+
+    pub(crate) fn expr(&self) -> Expr<'_> {
+        match self {
+            | Self::Atom(a) => Expr::Atom(a.clone()),
+            | Self::List(items) => {
+                let list = Expr::SExpr(Box::new(TokenList(items)));
+
+                inner_comments(items, 1).wrap_owned(list)
+            },
+            | Self::Comment(c) => Expr::Atom(Atom::new(c.src().to_owned())),
+        }
+    }
+
+    /// Parses a token tree, keeping the comments in lists. Strings are kept
+    /// as written in `src`, the source being parsed, or escaped again if it
+    /// is not known.
+    pub(crate) fn parse_in(parser: Parser<'_>, src: &str) -> Result<Self> {
+        if parser.peek::<wast::LParen>() {
+            return parser
+                .parens(|p| Ok(Self::List(Self::parse_items(p, src)?)));
+        }
+
+        parser.step(|cursor| match Self::atom(cursor, src) {
+            | Some(parsed) => Ok(parsed),
+            | None => Err(cursor.error("unexpected token")),
+        })
+    }
+
+    /// Parses the rest of a list, keeping its comments, see
+    /// [`TokenTree::parse_in`].
+    pub(crate) fn parse_items(
+        parser: Parser<'_>,
+        src: &str,
+    ) -> Result<Vec<Self>> {
+        let mut items = Vec::new();
+
+        loop {
+            let comment = parser.step(|cursor| match cursor.comment() {
+                | Some((src, rest)) => Ok((Some(Comment::from_src(src)), rest)),
+                | None => Ok((None, cursor)),
+            })?;
+
+            match comment {
+                | Some(comment) => items.push(Self::Comment(comment)),
+                | None if parser.is_empty() => return Ok(items),
+                | None => items.push(Self::parse_in(parser, src)?),
+            }
+        }
+    }
+
+    /// Parses the token at `cursor` if it is not a parenthesis, see
+    /// [`TokenTree::parse_in`].
+    pub(crate) fn atom<'a>(
+        cursor: Cursor<'a>,
+        src: &str,
+    ) -> Option<(Self, Cursor<'a>)> {
+        let atom = |s: String| Self::Atom(Atom::new(s));
+
+        if let Some((s, rest)) = cursor.keyword() {
+            return Some((atom(s.to_owned()), rest));
+        }
+
+        if let Some((s, rest)) = cursor.reserved() {
+            return Some((atom(s.to_owned()), rest));
+        }
+
+        if let Some((s, rest)) = cursor.id() {
+            return Some((atom(format!("${}", s)), rest));
+        }
+
+        if let Some((i, rest)) = cursor.integer() {
+            return Some((atom(i.src().to_owned()), rest));
+        }
+
+        if let Some((f, rest)) = cursor.float() {
+            return Some((atom(f.src().to_owned()), rest));
+        }
+
+        if let Some((s, rest)) = cursor.string() {
+            return match string_src(cursor.cur_span(), src) {
+                | Some(spelled) => Some((atom(spelled.to_owned()), rest)),
+                | None => Some((Self::string(s), rest)),
+            };
+        }
+
+        if let Some((s, rest)) = cursor.annotation() {
+            return Some((atom(format!("@{}", s)), rest));
+        }
+
+        None
+    }
+}
+
+impl Parse<'_> for TokenTree {
+    fn parse(parser: Parser<'_>) -> Result<Self> {
+        Self::parse_in(parser, "")
+    }
+}
+
+/// Returns the string literal at `span` as written in `src`, the source being
+/// parsed, quotes included. `None` if the source is not known.
+pub(crate) fn string_src(span: Span, src: &str) -> Option<&str> {
+    let at = offset_in(span, src);
+
+    match Lexer::new(src.get(at..)?).next() {
+        | Some(Ok(token @ Token::String(_))) => Some(token.src()),
+        | _ => None,
+    }
+}
+
+/// Returns the comments among `items` as the inner comments of the list
+/// holding them, whose first `head` items are not among its children.
+pub(crate) fn inner_comments(items: &[TokenTree], head: usize) -> Comments {
+    let mut comments = Comments::default();
+    let mut tokens: usize = 0;
+
+    for item in items {
+        match item {
+            | TokenTree::Comment(c) => {
+                let index = tokens.saturating_sub(head);
+                let inner = InnerComment::new(index, c.clone(), false);
+
+                comments = comments.with_inner(inner);
+            },
+            | _ => tokens += 1,
+        }
+    }
+
+    comments
+}
+
+/// A parenthesized group of tokens. The first item is printed in head
+/// position if it is an atom; comments are printed by the caller, see
+/// [`inner_comments`].
+struct TokenList<'a>(&'a [TokenTree]);
+
+impl TokenList<'_> {
+    fn tokens(&self) -> impl Iterator<Item = &TokenTree> {
+        self.0.iter().filter(|t| !matches!(t, TokenTree::Comment(_)))
+    }
+}
+
+impl SExpr for TokenList<'_> {
+    fn car(&self) -> String {
+        match self.tokens().next() {
+            | Some(TokenTree::Atom(a)) => a.to_string(),
+            | _ => String::new(),
+        }
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        let skip = match self.tokens().next() {
+            | Some(TokenTree::Atom(_)) => 1,
+            | _ => 0,
+        };
+
+        self.tokens().skip(skip).map(TokenTree::expr).collect()
+    }
+}
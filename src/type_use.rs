@@ -1,15 +1,23 @@
 use wast::parser::{self, Parse, Parser};
 
-use crate::{Atom, Expr, Index, Param, Result, SExpr};
+use crate::{
+    canonical::Canonicalize,
+    span::ignore_spans,
+    state::{parse_without_state, ParseIn, State},
+    Atom, Expr, Index, Param, Result, SExpr, Spanned,
+};
 
 /// https://webassembly.github.io/spec/core/text/modules.html#text-typeuse
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct TypeUse {
     type_def: Option<Type>,
     params:   Vec<Param>,
     results:  Vec<Result>,
+    span:     Option<wast::Span>,
 }
 
+ignore_spans!(TypeUse { type_def, params, results; span });
+
 impl TypeUse {
     pub fn new(
         type_def: Option<Type>,
@@ -20,28 +28,41 @@ impl TypeUse {
             type_def,
             params,
             results,
+            span: None,
         }
     }
 
-    pub(crate) fn exprs(&self) -> Vec<Expr> {
+    pub fn type_def(&self) -> Option<&Type> {
+        self.type_def.as_ref()
+    }
+
+    pub fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    pub fn results(&self) -> &[Result] {
+        &self.results
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
         let mut v = Vec::new();
 
         if let Some(ref type_def) = self.type_def {
-            v.push(Expr::SExpr(Box::new(type_def.clone())));
+            v.push(Expr::SExpr(Box::new(type_def)));
         }
 
         v.append(
             &mut self
                 .params
                 .iter()
-                .map(|p| Expr::SExpr(Box::new(p.clone())))
+                .map(|p| Expr::SExpr(Box::new(p)))
                 .collect(),
         );
         v.append(
             &mut self
                 .results
                 .iter()
-                .map(|r| Expr::SExpr(Box::new(r.clone())))
+                .map(|r| Expr::SExpr(Box::new(r)))
                 .collect(),
         );
 
@@ -49,8 +70,23 @@ impl TypeUse {
     }
 }
 
-impl Parse<'_> for TypeUse {
-    fn parse(parser: Parser<'_>) -> parser::Result<Self> {
+impl Spanned for TypeUse {
+    fn span(&self) -> Option<wast::Span> {
+        self.span
+    }
+}
+
+impl Canonicalize for TypeUse {
+    fn canonicalize(&mut self) {
+        if let Some(ref mut type_def) = self.type_def {
+            type_def.idx.canonicalize();
+        }
+    }
+}
+
+impl ParseIn for TypeUse {
+    fn parse_in(parser: Parser<'_>, state: &State) -> parser::Result<Self> {
+        let span = parser.cur_span();
         let mut type_def = None;
 
         if parser.peek2::<wast::kw::r#type>() {
@@ -62,7 +98,7 @@ impl Parse<'_> for TypeUse {
 
         while !parser.is_empty() {
             if parser.peek2::<wast::kw::param>() {
-                params.push(parser.parens(Param::parse)?)
+                params.push(parser.parens(|p| Param::parse_list(p, state))?)
             } else {
                 break;
             }
@@ -76,14 +112,22 @@ impl Parse<'_> for TypeUse {
             }
         }
 
+        // An empty type use has no tokens to point at.
+        let is_empty =
+            type_def.is_none() && params.is_empty() && results.is_empty();
+        let span = if is_empty { None } else { Some(span) };
+
         Ok(Self {
             type_def,
             params,
             results,
+            span,
         })
     }
 }
 
+parse_without_state!(TypeUse);
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Type {
     pub idx: Index,
@@ -94,7 +138,7 @@ impl SExpr for Type {
         "type".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         vec![Expr::Atom(Atom::new(self.idx.to_string()))]
     }
 }
@@ -2,7 +2,11 @@ use std::fmt;
 
 use wast::parser::{self, Cursor, Parse, Parser, Peek};
 
-use crate::{AsAtoms, Atom, Expr, Integer, Param, Result, SExpr};
+use crate::{
+    canonical::Canonicalize,
+    state::{parse_without_state, ParseIn, State},
+    AsAtoms, Atom, Expr, Integer, Param, Result, SExpr,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ValueType {
@@ -13,7 +17,7 @@ pub enum ValueType {
 }
 
 impl ValueType {
-    pub fn as_expr(&self) -> Expr {
+    pub fn as_expr(&self) -> Expr<'_> {
         Expr::Atom(Atom::new(self.to_string()))
     }
 }
@@ -81,6 +85,14 @@ impl FuncType {
     pub fn new(params: Vec<Param>, results: Vec<Result>) -> Self {
         Self { params, results }
     }
+
+    pub fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    pub fn results(&self) -> &[Result] {
+        &self.results
+    }
 }
 
 impl SExpr for FuncType {
@@ -88,16 +100,16 @@ impl SExpr for FuncType {
         "func".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         let mut v: Vec<_> = self
             .params
             .iter()
-            .map(|p| Expr::SExpr(Box::new(p.clone())))
+            .map(|p| Expr::SExpr(Box::new(p)))
             .collect();
         let mut results: Vec<_> = self
             .results
             .iter()
-            .map(|r| Expr::SExpr(Box::new(r.clone())))
+            .map(|r| Expr::SExpr(Box::new(r)))
             .collect();
 
         v.append(&mut results);
@@ -106,8 +118,8 @@ impl SExpr for FuncType {
     }
 }
 
-impl Parse<'_> for FuncType {
-    fn parse(parser: Parser<'_>) -> parser::Result<Self> {
+impl ParseIn for FuncType {
+    fn parse_in(parser: Parser<'_>, state: &State) -> parser::Result<Self> {
         parser.parse::<wast::kw::func>()?;
 
         let mut params = Vec::new();
@@ -115,7 +127,7 @@ impl Parse<'_> for FuncType {
 
         while !parser.is_empty() {
             if parser.peek2::<wast::kw::param>() {
-                params.push(parser.parens(Param::parse)?)
+                params.push(parser.parens(|p| Param::parse_list(p, state))?)
             } else {
                 break;
             }
@@ -133,6 +145,8 @@ impl Parse<'_> for FuncType {
     }
 }
 
+parse_without_state!(FuncType);
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Limits {
     min: Integer,
@@ -144,7 +158,7 @@ impl Limits {
         Self { min, max }
     }
 
-    pub(crate) fn exprs(&self) -> Vec<Expr> {
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
         let mut v = vec![Expr::Atom(Atom::new(self.min.to_string()))];
 
         if let Some(ref max) = self.max {
@@ -155,15 +169,28 @@ impl Limits {
     }
 }
 
-impl Parse<'_> for Limits {
-    fn parse(parser: Parser<'_>) -> parser::Result<Self> {
-        let min = parser.parse::<Integer>()?;
-        let max = parser.parse::<Option<Integer>>()?;
+impl Canonicalize for Limits {
+    fn canonicalize(&mut self) {
+        self.min.canonicalize();
+        self.max.canonicalize();
+    }
+}
+
+impl ParseIn for Limits {
+    fn parse_in(parser: Parser<'_>, state: &State) -> parser::Result<Self> {
+        let min = Integer::parse_in(parser, state)?;
+        let max = if parser.peek::<Integer>() {
+            Some(Integer::parse_in(parser, state)?)
+        } else {
+            None
+        };
 
         Ok(Self { min, max })
     }
 }
 
+parse_without_state!(Limits);
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MemType {
     lim: Limits,
@@ -174,19 +201,27 @@ impl MemType {
         Self { lim }
     }
 
-    pub(crate) fn exprs(&self) -> Vec<Expr> {
+    pub(crate) fn exprs(&self) -> Vec<Expr<'_>> {
         self.lim.exprs()
     }
 }
 
-impl Parse<'_> for MemType {
-    fn parse(parser: Parser<'_>) -> parser::Result<Self> {
-        let lim = parser.parse::<Limits>()?;
+impl Canonicalize for MemType {
+    fn canonicalize(&mut self) {
+        self.lim.canonicalize();
+    }
+}
+
+impl ParseIn for MemType {
+    fn parse_in(parser: Parser<'_>, state: &State) -> parser::Result<Self> {
+        let lim = Limits::parse_in(parser, state)?;
 
         Ok(Self { lim })
     }
 }
 
+parse_without_state!(MemType);
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum GlobalType {
     Mut(GlobalTypeMut),
@@ -194,9 +229,9 @@ pub enum GlobalType {
 }
 
 impl GlobalType {
-    pub(crate) fn expr(&self) -> Expr {
+    pub(crate) fn expr(&self) -> Expr<'_> {
         match self {
-            Self::Mut(m) => Expr::SExpr(Box::new(m.clone())),
+            Self::Mut(m) => Expr::SExpr(Box::new(m)),
             Self::NonMut(v) => Expr::Atom(Atom::new(v.to_string())),
         }
     }
@@ -240,7 +275,7 @@ impl SExpr for GlobalTypeMut {
         "mut".to_owned()
     }
 
-    fn cdr(&self) -> Vec<Expr> {
+    fn cdr(&self) -> Vec<Expr<'_>> {
         vec![Expr::Atom(Atom::new(self.val_type.to_string()))]
     }
 }
@@ -0,0 +1,221 @@
+use wast::{
+    parser::{Cursor, Parser, Peek, Result},
+    Span,
+};
+
+use crate::{
+    span::ignore_spans,
+    state::{parse_without_state, ParseIn, State},
+    token_tree, Atom, Comments, Expr, Expression, Print, PrintOptions, SExpr,
+    Spanned, TokenTree,
+};
+
+/// An instruction or a module field that this crate does not model, such as
+/// `i32.popcnt` or `(table 1 funcref)`. It is kept as the tokens it was
+/// written with and printed back as is.
+///
+/// A plain instruction takes the tokens up to the next one that can start an
+/// instruction, i.e. the next list or keyword other than immediates: lists
+/// such as `(type $t)` or `(result i32)`, `name=value` pairs such as
+/// `offset=4`, and keywords such as the `i32x4` of `v128.const i32x4 1 2 3
+/// 4`. A folded instruction or a field takes everything up to its closing
+/// parenthesis.
+#[derive(Debug, Clone)]
+pub struct Unknown {
+    keyword:             String,
+    items:               Vec<TokenTree>,
+    pub(crate) comments: Comments,
+    pub(crate) span:     Option<Span>,
+
+    /// Always empty: folded operands are kept among the items.
+    pub(crate) exprs: Vec<Expression>,
+}
+
+ignore_spans!(Unknown { keyword, items, comments, exprs; span });
+
+impl Unknown {
+    pub fn new(keyword: String, items: Vec<TokenTree>) -> Self {
+        Self {
+            keyword,
+            items,
+            comments: Comments::default(),
+            span: None,
+            exprs: Vec::new(),
+        }
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    /// Returns the tokens after the keyword.
+    pub fn items(&self) -> &[TokenTree] {
+        &self.items
+    }
+
+    pub fn with_comments(mut self, comments: Comments) -> Self {
+        self.comments = comments;
+        self
+    }
+
+    pub fn comments(&self) -> &Comments {
+        &self.comments
+    }
+
+    /// Returns the instruction as written without parentheses. Comments
+    /// among the tokens are left out, a line comment would end the line.
+    pub(crate) fn as_atom(&self) -> Atom {
+        let mut s = self.keyword.clone();
+
+        for item in self.tokens() {
+            s.push(' ');
+            s.push_str(&item.expr().print_with(&PrintOptions::default()));
+        }
+
+        Atom::new(s)
+    }
+
+    /// Returns the node in parentheses with its comments.
+    pub(crate) fn expr(&self) -> Expr<'_> {
+        self.list_comments().wrap_owned(Expr::SExpr(Box::new(self)))
+    }
+
+    /// Returns the comments of the node in parentheses, with those among its
+    /// tokens as inner comments.
+    pub(crate) fn list_comments(&self) -> Comments {
+        token_tree::inner_comments(&self.items, 0)
+            .inner()
+            .iter()
+            .cloned()
+            .fold(self.comments.clone(), Comments::with_inner)
+    }
+
+    /// Parses the node from the keyword to the end of the enclosing list, as
+    /// for a folded instruction or a module field.
+    pub(crate) fn parse_list(
+        parser: Parser<'_>,
+        state: &State,
+    ) -> Result<Self> {
+        let span = parser.cur_span();
+        let keyword = parse_keyword(parser)?;
+        let items = TokenTree::parse_items(parser, state.text())?;
+
+        // The comments were kept among the items.
+        state.comments.inside(span, parser.cur_span());
+
+        Ok(Self {
+            keyword,
+            items,
+            comments: Comments::default(),
+            span: Some(span),
+            exprs: Vec::new(),
+        })
+    }
+
+    fn tokens(&self) -> impl Iterator<Item = &TokenTree> {
+        self.items
+            .iter()
+            .filter(|item| !matches!(item, TokenTree::Comment(_)))
+    }
+}
+
+impl Spanned for Unknown {
+    fn span(&self) -> Option<Span> {
+        self.span
+    }
+}
+
+impl SExpr for Unknown {
+    fn car(&self) -> String {
+        self.keyword.clone()
+    }
+
+    fn cdr(&self) -> Vec<Expr<'_>> {
+        self.tokens().map(TokenTree::expr).collect()
+    }
+}
+
+/// Parses a plain instruction, up to the next token that can start one.
+impl ParseIn for Unknown {
+    fn parse_in(parser: Parser<'_>, state: &State) -> Result<Self> {
+        let span = Some(parser.cur_span());
+        let keyword = parse_keyword(parser)?;
+        let mut items = Vec::new();
+
+        while !parser.step(|cursor| Ok((starts_instruction(cursor), cursor)))? {
+            if parser.peek::<wast::LParen>() {
+                let start = parser.cur_span();
+
+                items.push(TokenTree::parse_in(parser, state.text())?);
+
+                // The comments were kept among the items.
+                state.comments.inside(start, parser.cur_span());
+                continue;
+            }
+
+            let item = parser.step(|cursor| {
+                Ok(match TokenTree::atom(cursor, state.text()) {
+                    | Some((item, rest)) => (Some(item), rest),
+                    | None => (None, cursor),
+                })
+            })?;
+
+            match item {
+                | Some(item) => items.push(item),
+                | None => break,
+            }
+        }
+
+        Ok(Self {
+            keyword,
+            items,
+            comments: Comments::default(),
+            span,
+            exprs: Vec::new(),
+        })
+    }
+}
+
+impl Peek for Unknown {
+    fn peek(cursor: Cursor<'_>) -> bool {
+        cursor.keyword().is_some()
+    }
+
+    fn display() -> &'static str {
+        "a keyword"
+    }
+}
+
+parse_without_state!(Unknown);
+
+fn parse_keyword(parser: Parser<'_>) -> Result<String> {
+    parser.step(|cursor| match cursor.keyword() {
+        | Some((keyword, rest)) => Ok((keyword.to_owned(), rest)),
+        | None => Err(cursor.error("expected a keyword")),
+    })
+}
+
+/// The keywords of the lists that are immediates of an instruction, such as
+/// the `(type $t)` of `call_indirect`.
+const IMMEDIATE_LISTS: &[&str] = &["type", "param", "result", "ref"];
+
+/// The keywords that are immediates of an instruction: the shapes of vector
+/// instructions and the heap types of reference instructions.
+const IMMEDIATE_KEYWORDS: &[&str] = &[
+    "i8x16", "i16x8", "i32x4", "i64x2", "f32x4", "f64x2", "func", "extern",
+    "any", "eq", "i31", "struct", "array", "none", "nofunc", "noextern",
+];
+
+/// Returns `true` if the token at `cursor` can start an instruction, see
+/// [`Unknown`].
+fn starts_instruction(cursor: Cursor<'_>) -> bool {
+    if let Some(rest) = cursor.lparen() {
+        return !rest
+            .keyword()
+            .is_some_and(|(k, _)| IMMEDIATE_LISTS.contains(&k));
+    }
+
+    cursor.keyword().is_some_and(|(k, _)| {
+        !k.contains('=') && !IMMEDIATE_KEYWORDS.contains(&k)
+    })
+}